@@ -0,0 +1,65 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Trailer carrying a commit's provenance HMAC.
+const TRAILER: &str = "Claude-Attestation";
+
+/// HMAC-SHA256 under `key` of a commit message (without its attestation
+/// trailer) and the transcript tail UUID the commit records.
+fn mac(body: &str, tail: &str, key: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body.as_bytes());
+    mac.update(b"\n");
+    mac.update(tail.as_bytes());
+    mac
+}
+
+/// Whether the last paragraph of `body` is a trailer block
+/// (`Token: value` lines), so a new trailer can join it.
+fn ends_with_trailers(body: &str) -> bool {
+    let Some((_, last)) = body.rsplit_once("\n\n") else {
+        return false;
+    };
+    last.lines().all(|line| {
+        line.split_once(": ")
+            .is_some_and(|(token, _)| !token.is_empty() && !token.contains(char::is_whitespace))
+    })
+}
+
+/// `message` with a `Claude-Attestation: <hex HMAC>` trailer appended,
+/// joining an existing trailer block if there is one.
+pub fn sign(message: &str, tail: &str, key: &str) -> String {
+    let body = message.trim_end();
+    let hex: String = mac(body, tail, key)
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    let separator = if ends_with_trailers(body) { "\n" } else { "\n\n" };
+    format!("{body}{separator}{TRAILER}: {hex}\n")
+}
+
+/// Check the attestation trailer of `message` against the rest of the
+/// message and `tail`.  `None` when the message has no trailer.
+pub fn verify(message: &str, tail: &str, key: &str) -> Option<bool> {
+    let prefix = format!("{TRAILER}: ");
+    let mut claimed = None;
+    let rest: Vec<&str> = message
+        .lines()
+        .filter(|line| match line.strip_prefix(&prefix) {
+            Some(hex) => {
+                claimed = Some(hex.trim());
+                false
+            }
+            None => true,
+        })
+        .collect();
+    let claimed = claimed?;
+    let bytes: Option<Vec<u8>> = (0..claimed.len())
+        .step_by(2)
+        .map(|i| claimed.get(i..i + 2).and_then(|h| u8::from_str_radix(h, 16).ok()))
+        .collect();
+    let body = rest.join("\n");
+    Some(bytes.is_some_and(|bytes| mac(body.trim_end(), tail, key).verify_slice(&bytes).is_ok()))
+}
@@ -1,7 +1,14 @@
 use crate::metadata::{ContinuationBreadcrumb, PlanContext, PromptMetadata};
-use crate::transcript::{Transcript, Verbosity};
+use crate::types::PermissionMode;
+use crate::transcript::{
+    ContentBlock, MessageContent, QaPair, SummaryOptions, TailStrategy, ToolUseResult, Transcript,
+    TranscriptEntry,
+};
 use minijinja::{context, Environment};
+use std::collections::HashSet;
 use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
 
 // ===================================================================
 // Input: all I/O-derived state, gathered by Session before calling decide_stop()
@@ -18,15 +25,115 @@ pub struct StopContext<'a> {
     /// Stop hook never fired (e.g. ExitPlanMode approval).  Read from the
     /// project-wide `plan-entries.json`.  Prepended to the transcript note so
     /// the planning conversation is visible in the commit.
-    pub plan_entries: Vec<serde_json::Value>,
+    pub plan_entries: Vec<Arc<serde_json::Value>>,
     pub session_id: &'a str,
     pub breadcrumb: Option<ContinuationBreadcrumb>,
+    /// Whether `detect_reset` looks for resets at all.
+    pub detect_resets: bool,
     /// The value of refs/notes/tail on HEAD (if any).
     pub committed_tail: Option<String>,
     pub has_uncommitted_changes: bool,
     /// Pre-resolved commit message template string.
     pub commit_template: &'a str,
-    pub verbosity: Verbosity,
+    pub summary: SummaryOptions,
+    /// How to pick the tail when the transcript has several leaves.
+    pub tail_strategy: TailStrategy,
+    /// Whether the full prompt is quoted in the commit body.
+    pub prompt_body: PromptBody,
+    /// Summarize only entries from the tail's session, leaving out those of
+    /// an earlier session resumed into the same transcript.
+    pub summary_current_session_only: bool,
+    pub subject: SubjectOptions,
+    /// Commit body sections after the subject, in order.
+    pub body_sections: Vec<BodySection>,
+    pub section_heading_style: HeadingStyle,
+    /// Whether subagent sidechain entries (`isSidechain: true`) are kept in
+    /// the transcript note.
+    pub include_sidechains_in_note: bool,
+    /// The session's permission mode from the Stop hook input, if known.
+    pub permission_mode: Option<PermissionMode>,
+    /// Leave a turn uncommitted when Claude ran `git add` / `git commit`
+    /// itself, rather than committing on top of its work.
+    pub defer_to_manual_git: bool,
+    /// Keep at most this many (most recent) transcript note entries; 0
+    /// means no limit.
+    pub max_transcript_note_entries: usize,
+    /// Resolve a slash-command prompt to the latest uncommitted non-command
+    /// prompt for the commit subject.
+    pub ignore_slash_command_prompts: bool,
+    /// Whether a `Claude-Session: <id>` trailer ends the commit message.
+    pub include_session_trailer: bool,
+    /// Whether a `Generated-by: clautribution <version>` trailer ends the
+    /// commit message.
+    pub version_trailer: bool,
+    /// Pending changes split into new and modified files, when the
+    /// `Added:` / `Modified:` footers are enabled.
+    pub file_changes: Option<FileChanges>,
+    /// When a nonproductive stop carries a hint message.
+    pub nonproductive_hint: NonproductiveHint,
+}
+
+/// Paths of pending changes by git status: untracked (new) files and
+/// modified tracked files.
+#[derive(Debug, Clone, Default)]
+pub struct FileChanges {
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+/// A section of the commit body that `build_productive` may emit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodySection {
+    Qa,
+    Plan,
+    Summary,
+}
+
+/// How `build_productive` writes body section headings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeadingStyle {
+    /// `## Q&A`
+    Markdown,
+    /// `Q&A:`
+    Plain,
+}
+
+impl HeadingStyle {
+    fn heading(self, title: &str) -> String {
+        match self {
+            HeadingStyle::Markdown => format!("## {title}"),
+            HeadingStyle::Plain => format!("{}:", title.to_uppercase()),
+        }
+    }
+}
+
+/// Fixed text around the rendered subject line, and its length limit.
+#[derive(Debug, Clone, Default)]
+pub struct SubjectOptions {
+    pub prefix: String,
+    pub suffix: String,
+    /// Maximum subject length in chars, prefix and suffix included.  The
+    /// rendered part is truncated to fit; 0 means no limit.
+    pub max_len: usize,
+}
+
+/// Whether `build_productive` quotes the full prompt after the subject.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptBody {
+    Omit,
+    /// Quote the prompt unless it was moved to `refs/notes/prompt-full`.
+    Include,
+    /// Quote the prompt even when it was moved to a note.
+    Force,
+}
+
+/// When `build_nonproductive` emits its hint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NonproductiveHint {
+    Always,
+    /// Only when another hint (reset, plan snapshot) is present.
+    OnChange,
+    Never,
 }
 
 // ===================================================================
@@ -36,11 +143,16 @@ pub struct StopContext<'a> {
 pub enum StopDecision {
     /// No prompt metadata could be resolved from any source.
     NoMetadata,
+    /// The last user prompt in the transcript is at or before the committed
+    /// tail — it has already been committed (or dropped), so there is
+    /// nothing new since the last commit.
+    AlreadyCommitted,
     /// Transcript has no tail entry.
     NoTail,
-    /// Nonproductive stop: no uncommitted changes.
+    /// Nonproductive stop: no uncommitted changes.  `hint_message` is
+    /// `None` when `nonproductive_hint` suppresses it.
     Nonproductive {
-        hint_message: String,
+        hint_message: Option<String>,
         breadcrumb: ContinuationBreadcrumb,
         plan_snapshot: Option<(String, String)>,
         pending_plan: Option<String>,
@@ -52,7 +164,7 @@ pub enum StopDecision {
     Productive {
         hint_message: String,
         commit_message: String,
-        transcript_note_entries: Vec<serde_json::Value>,
+        transcript_note_entries: Vec<Arc<serde_json::Value>>,
         /// (ref_name, content) pairs for prompt/session/tail notes.
         simple_notes: Vec<(String, String)>,
         consumed_pending_plan: bool,
@@ -60,6 +172,64 @@ pub enum StopDecision {
     },
 }
 
+impl StopDecision {
+    /// The decision as JSON, tagged by `"type"` (the variant name), for
+    /// the `decide` subcommand and external tooling.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut value = match self {
+            StopDecision::NoMetadata | StopDecision::AlreadyCommitted | StopDecision::NoTail => {
+                serde_json::json!({})
+            }
+            StopDecision::Nonproductive {
+                hint_message,
+                breadcrumb,
+                plan_snapshot,
+                pending_plan,
+                plan_context,
+            } => serde_json::json!({
+                "hint_message": hint_message,
+                "breadcrumb": breadcrumb,
+                "plan_snapshot": plan_snapshot
+                    .as_ref()
+                    .map(|(prompt, plan)| serde_json::json!({ "prompt": prompt, "plan": plan })),
+                "pending_plan": pending_plan,
+                "plan_context": plan_context,
+            }),
+            StopDecision::Productive {
+                hint_message,
+                commit_message,
+                transcript_note_entries,
+                simple_notes,
+                consumed_pending_plan,
+                consumed_plan_context,
+            } => serde_json::json!({
+                "hint_message": hint_message,
+                "commit_message": commit_message,
+                "transcript_note_entries": transcript_note_entries,
+                "notes": simple_notes
+                    .iter()
+                    .map(|(r, c)| (r.clone(), serde_json::Value::from(c.as_str())))
+                    .collect::<serde_json::Map<_, _>>(),
+                "consumed_pending_plan": consumed_pending_plan,
+                "consumed_plan_context": consumed_plan_context,
+            }),
+        };
+        value["type"] = self.variant_name().into();
+        value
+    }
+
+    /// The variant's name, for diagnostics.
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            StopDecision::NoMetadata => "NoMetadata",
+            StopDecision::AlreadyCommitted => "AlreadyCommitted",
+            StopDecision::NoTail => "NoTail",
+            StopDecision::Nonproductive { .. } => "Nonproductive",
+            StopDecision::Productive { .. } => "Productive",
+        }
+    }
+}
+
 // ===================================================================
 // Error: only template rendering can fail in pure code
 // ===================================================================
@@ -90,6 +260,14 @@ struct ResolvedMetadata {
     pending_plan_from_fallback: Option<String>,
 }
 
+/// Why `resolve_metadata` produced no metadata.
+enum Unresolved {
+    /// No source had a prompt.
+    Missing,
+    /// The transcript's last prompt is at or before the committed tail.
+    AlreadyCommitted,
+}
+
 // ===================================================================
 // Pure entry point
 // ===================================================================
@@ -97,14 +275,31 @@ struct ResolvedMetadata {
 pub fn decide_stop(ctx: &StopContext) -> Result<StopDecision, DecisionError> {
     // 1. Resolve prompt metadata via 3-source fallback.
     let resolved = match resolve_metadata(ctx) {
-        Some(r) => r,
-        None => return Ok(StopDecision::NoMetadata),
+        Ok(r) => r,
+        Err(Unresolved::Missing) => return Ok(StopDecision::NoMetadata),
+        Err(Unresolved::AlreadyCommitted) => return Ok(StopDecision::AlreadyCommitted),
     };
 
-    let prompt = resolved.prompt;
+    let mut prompt = resolved.prompt;
     let session_id = resolved.session_id;
     let mut uuid = resolved.uuid;
 
+    // A slash command ("/test") makes a poor subject; prefer the most
+    // recent real prompt that hasn't been committed yet.
+    if ctx.ignore_slash_command_prompts
+        && prompt.trim_start().starts_with('/')
+        && let Some((found, text, _)) = ctx.transcript.last_non_command_user_text()
+    {
+        let committed = ctx
+            .committed_tail
+            .as_deref()
+            .is_some_and(|ct| found == ct || ctx.transcript.is_ancestor(ct, found));
+        if !committed {
+            prompt = text.to_string();
+            uuid = Some(found.to_string());
+        }
+    }
+
     // Always re-resolve the UUID from the transcript — at prompt-submit
     // time the new entry may not have been written yet, and if the same
     // prompt text was submitted again we need the *latest* UUID.
@@ -115,7 +310,7 @@ pub fn decide_stop(ctx: &StopContext) -> Result<StopDecision, DecisionError> {
     }
 
     // 2. Get the transcript tail.
-    let tail_uuid = match ctx.transcript.tail() {
+    let tail_uuid = match ctx.transcript.select_tail(ctx.tail_strategy) {
         Some(t) => t,
         None => return Ok(StopDecision::NoTail),
     };
@@ -123,13 +318,32 @@ pub fn decide_stop(ctx: &StopContext) -> Result<StopDecision, DecisionError> {
     // For storage (breadcrumbs, git notes) use the last conversation
     // entry's UUID rather than the raw tail. Progress and system entries
     // sit on side branches of the DAG.
-    let conv_tail = ctx.transcript.conversation_tail().unwrap_or(tail_uuid);
+    let conv_tail = match ctx.tail_strategy {
+        TailStrategy::Last => ctx.transcript.conversation_tail(),
+        // The last conversation entry in the file may sit on another
+        // branch; take the nearest one on the chosen chain instead.
+        TailStrategy::Deepest => ctx.transcript.ancestors(tail_uuid).find_map(|e| match e {
+            TranscriptEntry::User(c) | TranscriptEntry::Assistant(c) => Some(c.uuid.as_str()),
+            _ => None,
+        }),
+    }
+    .unwrap_or(tail_uuid);
 
     // 3. Reset detection.
     let mut hints = detect_reset(ctx, tail_uuid);
 
-    // 4. Branch: nonproductive vs productive.
-    if !ctx.has_uncommitted_changes {
+    // 4. Branch: nonproductive vs productive.  A turn in which Claude
+    // staged or committed by hand is left to it when `defer_to_manual_git`.
+    let manual_git = if ctx.defer_to_manual_git && ctx.has_uncommitted_changes {
+        Transcript::git_commits_run(&ctx.transcript.turn(tail_uuid, uuid.as_deref()))
+    } else {
+        Vec::new()
+    };
+    if !manual_git.is_empty() {
+        let commands: Vec<String> = manual_git.iter().map(|sub| format!("git {sub}")).collect();
+        hints.push(format!("Claude ran {} itself, not committing", commands.join(", ")));
+    }
+    if !ctx.has_uncommitted_changes || !manual_git.is_empty() {
         return Ok(build_nonproductive(
             ctx,
             tail_uuid,
@@ -198,10 +412,10 @@ fn split_long_prompt(prompt: &str) -> (String, Option<String>) {
     (summary, Some(prompt.to_string()))
 }
 
-fn resolve_metadata(ctx: &StopContext) -> Option<ResolvedMetadata> {
+fn resolve_metadata(ctx: &StopContext) -> Result<ResolvedMetadata, Unresolved> {
     // Source 1: prompt metadata file (written by UserPromptSubmit).
     if let Some(m) = &ctx.file_metadata {
-        return Some(ResolvedMetadata {
+        return Ok(ResolvedMetadata {
             prompt: m.prompt.clone(),
             session_id: m.session_id.clone(),
             uuid: m.uuid.clone(),
@@ -211,7 +425,7 @@ fn resolve_metadata(ctx: &StopContext) -> Option<ResolvedMetadata> {
 
     // Source 2: pending plan file (written by a preceding plan-mode nonproductive stop).
     if let Some(plan) = &ctx.pending_plan {
-        return Some(ResolvedMetadata {
+        return Ok(ResolvedMetadata {
             prompt: plan_prompt(plan),
             session_id: ctx.session_id.to_string(),
             uuid: None,
@@ -222,10 +436,10 @@ fn resolve_metadata(ctx: &StopContext) -> Option<ResolvedMetadata> {
     // Source 3: last user text in the transcript.
     if let Some((uuid, text, plan_content)) = ctx.transcript.last_user_text() {
         // If this entry is at or before the committed tail, it's already
-        // been committed (or dropped) — treat as no metadata.
+        // been committed (or dropped).
         if let Some(ct) = ctx.committed_tail.as_deref() {
             if uuid == ct || ctx.transcript.is_ancestor(ct, uuid) {
-                return None;
+                return Err(Unresolved::AlreadyCommitted);
             }
         }
         // When planContent is present the user text is Claude Code's
@@ -235,7 +449,7 @@ fn resolve_metadata(ctx: &StopContext) -> Option<ResolvedMetadata> {
             Some(plan) => plan_prompt(plan),
             None => text.to_string(),
         };
-        return Some(ResolvedMetadata {
+        return Ok(ResolvedMetadata {
             prompt,
             session_id: ctx.session_id.to_string(),
             uuid: Some(uuid.to_string()),
@@ -243,7 +457,7 @@ fn resolve_metadata(ctx: &StopContext) -> Option<ResolvedMetadata> {
         });
     }
 
-    None
+    Err(Unresolved::Missing)
 }
 
 // ===================================================================
@@ -254,6 +468,9 @@ fn resolve_metadata(ctx: &StopContext) -> Option<ResolvedMetadata> {
 /// from an earlier point). Returns a vec of hint strings (empty = no reset).
 pub fn detect_reset(ctx: &StopContext, tail_uuid: &str) -> Vec<String> {
     let mut hints = Vec::new();
+    if !ctx.detect_resets {
+        return hints;
+    }
 
     // Prefer the breadcrumb tail (covers nonproductive gaps); fall back to
     // refs/notes/tail on HEAD (covers the case where no breadcrumb exists yet).
@@ -286,14 +503,22 @@ fn build_nonproductive(
     hints: &mut Vec<String>,
     pending_plan_from_fallback: Option<String>,
 ) -> StopDecision {
-    // Check for ExitPlanMode plan snapshot.
-    let plan_snapshot =
-        ctx.transcript
-            .find_exit_plan_mode_plan(tail_uuid, prompt_uuid)
-            .map(|plan| {
-                hints.push("plan snapshot saved".into());
-                (prompt.to_string(), plan.clone())
-            });
+    // Check for ExitPlanMode plan snapshot.  In plan mode a turn without
+    // changes is a planning turn, so its final response is the plan even
+    // when Claude never called ExitPlanMode.
+    let plan_snapshot = ctx
+        .transcript
+        .find_exit_plan_mode_plan(tail_uuid, prompt_uuid)
+        .or_else(|| {
+            if ctx.permission_mode != Some(PermissionMode::Plan) {
+                return None;
+            }
+            Transcript::last_text_response(&ctx.transcript.turn(tail_uuid, prompt_uuid))
+        })
+        .map(|plan| {
+            hints.push("plan snapshot saved".into());
+            (prompt.to_string(), plan)
+        });
 
     // If we found a plan snapshot, that plan also becomes the pending plan.
     // If the metadata came from the transcript fallback with plan_content,
@@ -323,13 +548,14 @@ fn build_nonproductive(
         session_id: session_id.to_string(),
     };
 
-    let hint_message = if hints.is_empty() {
-        "[clautribution] nonproductive turn recorded".to_string()
-    } else {
-        format!(
+    let hint_message = match ctx.nonproductive_hint {
+        NonproductiveHint::Never => None,
+        NonproductiveHint::OnChange if hints.is_empty() => None,
+        _ if hints.is_empty() => Some("[clautribution] nonproductive turn recorded".to_string()),
+        _ => Some(format!(
             "[clautribution] {}, nonproductive turn recorded",
             hints.join(", ")
-        )
+        )),
     };
 
     StopDecision::Nonproductive {
@@ -360,13 +586,25 @@ fn build_productive(
     let impl_entries = ctx
         .transcript
         .turn_raw(tail_uuid, ctx.committed_tail.as_deref());
-    let chain_values = if !ctx.plan_entries.is_empty() {
+    let mut chain_values = if !ctx.plan_entries.is_empty() {
         let mut all = ctx.plan_entries.clone();
         all.extend(impl_entries);
         all
     } else {
         impl_entries
     };
+    if !ctx.include_sidechains_in_note {
+        chain_values.retain(|v| v["isSidechain"].as_bool() != Some(true));
+    }
+    // Keep the most recent entries, behind a marker saying how many of the
+    // oldest were dropped.
+    let max_entries = ctx.max_transcript_note_entries;
+    let mut elided = 0;
+    if max_entries > 0 && chain_values.len() > max_entries {
+        elided = chain_values.len() - max_entries;
+        chain_values.drain(..elided);
+        chain_values.insert(0, Arc::new(serde_json::json!({ "type": "elided", "count": elided })));
+    }
 
     // Full implementation span (committed_tail→tail) — used for Q&A
     // extraction and the turn summary.  The wider span ensures we capture
@@ -377,7 +615,12 @@ fn build_productive(
 
     // Turn summary covers the full committed_tail→tail span so interrupted
     // prompts and their partial responses appear naturally in the flow.
-    let turn_summary = Transcript::summarize_turn(&impl_turn, ctx.verbosity);
+    let turn_summary = if ctx.summary_current_session_only {
+        let session_turn = ctx.transcript.since_last_resume(&impl_turn);
+        Transcript::summarize_turn(&session_turn, ctx.summary.clone())
+    } else {
+        Transcript::summarize_turn(&impl_turn, ctx.summary.clone())
+    };
 
     // If a cross-session plan context exists, prefer its original prompt
     // over the plan-title fallback — it's the user's actual words.
@@ -388,10 +631,40 @@ fn build_productive(
         .unwrap_or(prompt);
 
     // Split out pasted content (large prompts) into a separate note.
-    let (commit_prompt, full_prompt) = split_long_prompt(effective_prompt);
+    let (mut commit_prompt, full_prompt) = split_long_prompt(effective_prompt);
 
     // Render commit message.
-    let mut msg = render_commit_message(ctx.commit_template, &commit_prompt)?;
+    let files = Transcript::touched_files(&impl_turn);
+    if commit_prompt.trim().is_empty()
+        && let Some(subject) = synthesize_subject(&files)
+    {
+        commit_prompt = subject;
+    }
+    let commit_type = ConventionalType::infer(&impl_turn);
+    let mut msg = render_commit_message(ctx.commit_template, &commit_prompt, &files, commit_type)?;
+    msg = wrap_subject(&msg, &ctx.subject);
+
+    // Quote the full prompt after the subject line.
+    let quote_prompt = match ctx.prompt_body {
+        PromptBody::Omit => false,
+        PromptBody::Include => full_prompt.is_none(),
+        PromptBody::Force => true,
+    };
+    if quote_prompt && !effective_prompt.trim().is_empty() {
+        let quoted: Vec<String> = effective_prompt
+            .trim()
+            .lines()
+            .map(|l| if l.is_empty() { ">".to_string() } else { format!("> {l}") })
+            .collect();
+        let quoted = quoted.join("\n");
+        let (subject, rest) = msg.split_once('\n').unwrap_or((&msg, ""));
+        let rest = rest.trim_start_matches('\n');
+        msg = if rest.is_empty() {
+            format!("{subject}\n\n{quoted}")
+        } else {
+            format!("{subject}\n\n{quoted}\n\n{rest}")
+        };
+    }
 
     // Determine whether to consume the pending plan (either from ctx or fallback).
     let has_pending_plan = ctx.pending_plan.is_some() || pending_plan_from_fallback.is_some();
@@ -399,12 +672,16 @@ fn build_productive(
         .pending_plan
         .as_deref()
         .or(pending_plan_from_fallback.as_deref());
-    let qa: Vec<String> = ctx
+    // Q&A from the planning session first, then any asked during
+    // implementation; a question re-asked with the same answer shows once.
+    let mut seen_qa = HashSet::new();
+    let qa: Vec<QaPair> = ctx
         .plan_context
-        .as_ref()
-        .filter(|pc| !pc.qa.is_empty())
-        .map(|pc| pc.qa.clone())
-        .unwrap_or_else(|| Transcript::extract_qa(&impl_turn));
+        .iter()
+        .flat_map(|pc| pc.qa.iter().cloned())
+        .chain(Transcript::extract_qa(&impl_turn))
+        .filter(|pair| seen_qa.insert(pair.clone()))
+        .collect();
 
     // Collect earlier user prompts for the git notes (refs/notes/prompt).
     let all_user_texts = ctx
@@ -418,27 +695,84 @@ fn build_productive(
         .map(|(_, text, _)| *text)
         .rev()
         .collect();
-    if !qa.is_empty() {
-        msg.push_str("\n\n## Q&A\n\n");
-        for line in &qa {
-            msg.push_str(line);
-            msg.push('\n');
+    for section in &ctx.body_sections {
+        match section {
+            BodySection::Qa => {
+                if !qa.is_empty() {
+                    msg.push_str(&format!("\n\n{}\n\n", ctx.section_heading_style.heading("Q&A")));
+                    for pair in &qa {
+                        msg.push_str(&format!("{pair}\n"));
+                    }
+                }
+            }
+            BodySection::Plan => {
+                if let Some(plan) = plan_text {
+                    msg.push_str(&format!("\n\n{}\n\n", ctx.section_heading_style.heading("Plan")));
+                    msg.push_str(plan);
+                }
+            }
+            BodySection::Summary => {
+                if let Some(summary) = &turn_summary {
+                    msg.push_str("\n\n");
+                    msg.push_str(summary);
+                }
+            }
         }
     }
-    if let Some(plan) = plan_text {
-        msg.push_str("\n\n## Plan\n\n");
-        msg.push_str(plan);
+    if let Some(changes) = &ctx.file_changes {
+        let footers = file_change_footers(changes);
+        if !footers.is_empty() {
+            msg.truncate(msg.trim_end().len());
+            msg.push_str("\n\n");
+            msg.push_str(&footers);
+        }
+    }
+
+    // Trailers go last, in their own paragraph.
+    let mut trailers = Vec::new();
+    if ctx.include_session_trailer {
+        trailers.push(format!("Claude-Session: {session_id}"));
+    }
+    if ctx.version_trailer {
+        trailers.push(format!("Generated-by: clautribution {}", env!("CARGO_PKG_VERSION")));
     }
-    if let Some(summary) = &turn_summary {
+    if !trailers.is_empty() {
+        msg.truncate(msg.trim_end().len());
         msg.push_str("\n\n");
-        msg.push_str(summary);
+        msg.push_str(&trailers.join("\n"));
     }
 
     hints.push("committed changes".into());
-    hints.push(format!(
-        "attached notes ({} transcript entries)",
-        chain_values.len()
-    ));
+    if elided > 0 {
+        hints.push(format!(
+            "attached notes ({} transcript entries, {elided} older elided)",
+            max_entries
+        ));
+    } else {
+        hints.push(format!(
+            "attached notes ({} transcript entries)",
+            chain_values.len()
+        ));
+    }
+    let thinking = Transcript::thinking_levels(&impl_turn);
+    if !thinking.is_empty() {
+        hints.push(format!("thinking: {}", thinking.join(", ")));
+    }
+    if let Some((from, to)) = Transcript::branch_change(&impl_turn) {
+        hints.push(format!("branch changed during turn ({from} → {to})"));
+    }
+    let switches = Transcript::model_switches(&impl_turn);
+    if !switches.is_empty() {
+        let switches: Vec<String> = switches.iter().map(|(from, to)| format!("{from} → {to}")).collect();
+        hints.push(format!("model switched during turn ({})", switches.join(", ")));
+    }
+    let (failed_tools, total_tools) = Transcript::tool_error_stats(&impl_turn);
+    if failed_tools > 0 {
+        hints.push(format!("{failed_tools}/{total_tools} tools failed"));
+    }
+    if Transcript::tail_stop_reason(&impl_turn) == Some("max_tokens") {
+        hints.push("response truncated (max tokens)".into());
+    }
 
     let prompt_note = if earlier_prompts.is_empty() {
         commit_prompt
@@ -459,6 +793,41 @@ fn build_productive(
     if let Some(full) = full_prompt {
         simple_notes.push(("refs/notes/prompt-full".to_string(), full));
     }
+    if let Some(mode) = Transcript::latest_permission_mode(&impl_turn) {
+        simple_notes.push(("refs/notes/permission".to_string(), mode.to_string()));
+    }
+    // TODO/FIXME lines left behind, one "path: line" per line, with paths
+    // relative to the repo when they're inside it.
+    let todos = Transcript::introduced_todos(&impl_turn);
+    if !todos.is_empty() {
+        let lines: Vec<String> = todos
+            .iter()
+            .map(|(path, line)| {
+                let path = ctx
+                    .summary
+                    .workdir
+                    .as_deref()
+                    .and_then(|dir| Path::new(path).strip_prefix(dir).ok())
+                    .map_or_else(|| path.clone(), |p| p.display().to_string());
+                format!("{path}: {line}")
+            })
+            .collect();
+        simple_notes.push(("refs/notes/todos".to_string(), lines.join("\n")));
+    }
+    // The Q&A again, as a JSON array of {question, answer} for tooling.
+    if !qa.is_empty() {
+        let json = serde_json::to_string(&qa).expect("Q&A pairs serialize");
+        simple_notes.push(("refs/notes/qa".to_string(), json));
+    }
+    // Web sources consulted: one fetched URL per line, then the searches.
+    let (urls, queries) = Transcript::web_activity(&impl_turn);
+    if !urls.is_empty() || !queries.is_empty() {
+        let sources: Vec<String> = urls
+            .into_iter()
+            .chain(queries.into_iter().map(|q| format!("search: {q}")))
+            .collect();
+        simple_notes.push(("refs/notes/sources".to_string(), sources.join("\n")));
+    }
 
     Ok(StopDecision::Productive {
         hint_message: format!("[clautribution] {}", hints.join(", ")),
@@ -470,18 +839,233 @@ fn build_productive(
     })
 }
 
+// ===================================================================
+// Conventional commit type inference
+// ===================================================================
+
+/// Conventional-commit type inferred from a turn's file activity, exposed
+/// to commit templates as `commit_type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConventionalType {
+    /// New (non-test, non-doc) files were created.
+    Feat,
+    /// Existing files were edited.
+    Fix,
+    /// Only test files were modified.
+    Test,
+    /// Only documentation files were modified.
+    Docs,
+    /// No files were modified (reads, searches, commands only).
+    Chore,
+}
+
+impl ConventionalType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConventionalType::Feat => "feat",
+            ConventionalType::Fix => "fix",
+            ConventionalType::Test => "test",
+            ConventionalType::Docs => "docs",
+            ConventionalType::Chore => "chore",
+        }
+    }
+
+    /// Infer the type from the Edit/Write/NotebookEdit calls in a turn.
+    /// A Write counts as a new file unless its tool result reports an
+    /// `update` of an existing one.
+    pub fn infer(turn: &[&TranscriptEntry]) -> Self {
+        let mut modified: Vec<(&str, bool)> = Vec::new(); // (path, created)
+        let mut updated: HashSet<&str> = HashSet::new();
+        for entry in turn {
+            match entry {
+                TranscriptEntry::Assistant(conv) => {
+                    let MessageContent::Blocks(blocks) = &conv.message.content else {
+                        continue;
+                    };
+                    for block in blocks {
+                        let ContentBlock::ToolUse(tu) = block else {
+                            continue;
+                        };
+                        let (field, created) = match tu.name.as_str() {
+                            "Write" => ("file_path", true),
+                            "Edit" => ("file_path", false),
+                            "NotebookEdit" => ("notebook_path", false),
+                            _ => continue,
+                        };
+                        if let Some(path) = tu.input[field].as_str() {
+                            modified.push((path, created));
+                        }
+                    }
+                }
+                TranscriptEntry::User(conv) => {
+                    if let Some(ToolUseResult::Write(w)) = &conv.tool_use_result
+                        && w.result_type == "update"
+                    {
+                        updated.insert(w.file_path.as_str());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if modified.is_empty() {
+            return ConventionalType::Chore;
+        }
+        if modified.iter().all(|(p, _)| is_test_path(p)) {
+            return ConventionalType::Test;
+        }
+        if modified.iter().all(|(p, _)| is_doc_path(p)) {
+            return ConventionalType::Docs;
+        }
+        let created_source = modified.iter().any(|(p, created)| {
+            *created && !updated.contains(p) && !is_test_path(p) && !is_doc_path(p)
+        });
+        if created_source {
+            ConventionalType::Feat
+        } else {
+            ConventionalType::Fix
+        }
+    }
+}
+
+/// Heuristic: a path under a `test`/`tests` directory, or a file named like
+/// `test_*`, `*_test.*`, `*.test.*`, `*.spec.*`, or `tests.rs`.
+fn is_test_path(path: &str) -> bool {
+    let p = Path::new(path);
+    if p.components().any(|c| matches!(c.as_os_str().to_str(), Some("test" | "tests"))) {
+        return true;
+    }
+    let name = p.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let stem = name.split('.').next().unwrap_or("");
+    name == "tests.rs"
+        || stem.starts_with("test_")
+        || stem.ends_with("_test")
+        || name.contains(".test.")
+        || name.contains(".spec.")
+}
+
+/// Heuristic: markdown/reStructuredText/AsciiDoc files or anything under `docs/`.
+fn is_doc_path(path: &str) -> bool {
+    let p = Path::new(path);
+    p.components().any(|c| c.as_os_str() == "docs")
+        || matches!(
+            p.extension().and_then(|e| e.to_str()),
+            Some("md" | "rst" | "adoc")
+        )
+}
+
+/// Last-resort subject for a turn without a prompt (e.g. UserPromptSubmit
+/// never fired), built from the edited files: "Update main.rs and lib.rs".
+fn synthesize_subject(files: &[String]) -> Option<String> {
+    let named = match files {
+        [] => return None,
+        [one] => one.clone(),
+        [first, second] => format!("{first} and {second}"),
+        [first, second, third] => format!("{first}, {second} and {third}"),
+        [first, second, rest @ ..] => format!("{first}, {second} and {} more files", rest.len()),
+    };
+    Some(format!("Update {named}"))
+}
+
 // ===================================================================
 // Template rendering (pure computation)
 // ===================================================================
 
-fn render_commit_message(template: &str, prompt: &str) -> Result<String, DecisionError> {
+fn render_commit_message(
+    template: &str,
+    prompt: &str,
+    files: &[String],
+    commit_type: ConventionalType,
+) -> Result<String, DecisionError> {
+    let commit_type = commit_type.as_str();
     let env = Environment::new();
     let tmpl = env
         .template_from_str(template)
         .map_err(|e| DecisionError::TemplateRender(format!("parsing template: {e}")))?;
-    tmpl.render(context! { prompt })
+    tmpl.render(context! { prompt, files, commit_type })
         .map_err(|e| DecisionError::TemplateRender(format!("rendering template: {e}")))
 }
 
+/// Apply `SubjectOptions` to the first line of a rendered message.
+/// Prefix and suffix are always kept whole; only the rendered subject text
+/// is shortened (with "...") when the line exceeds `max_len`.
+fn wrap_subject(msg: &str, opts: &SubjectOptions) -> String {
+    let (subject, rest) = match msg.split_once('\n') {
+        Some((subject, rest)) => (subject, Some(rest)),
+        None => (msg, None),
+    };
+    let mut subject = subject.to_string();
+    if opts.max_len > 0 {
+        let budget = opts
+            .max_len
+            .saturating_sub(opts.prefix.chars().count() + opts.suffix.chars().count());
+        if subject.chars().count() > budget {
+            // With almost no room left, the ellipsis itself is cut short.
+            let dots = budget.min(3);
+            subject = subject.chars().take(budget - dots).collect::<String>() + &"..."[..dots];
+        }
+    }
+    let subject = format!("{}{subject}{}", opts.prefix, opts.suffix);
+    match rest {
+        Some(rest) => format!("{subject}\n{rest}"),
+        None => subject,
+    }
+}
+
+/// The `Added:` / `Modified:` footer lines for `changes`, or `""` if both
+/// lists are empty.
+fn file_change_footers(changes: &FileChanges) -> String {
+    [("Added", &changes.added), ("Modified", &changes.modified)]
+        .into_iter()
+        .filter(|(_, paths)| !paths.is_empty())
+        .map(|(label, paths)| format!("{label}: {}", paths.join(", ")))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Narrow a rendered commit message to one group of a split commit:
+/// `scope: ` leads the subject and counts against `opts.max_len` like the
+/// prefix does, and the file-change footers built from `changes` keep only
+/// the paths `in_group` accepts.
+pub fn scope_message(
+    msg: &str,
+    scope: &str,
+    opts: &SubjectOptions,
+    changes: Option<&FileChanges>,
+    in_group: impl Fn(&str) -> bool,
+) -> String {
+    let (subject, rest) = match msg.split_once('\n') {
+        Some((subject, rest)) => (subject, Some(rest)),
+        None => (msg, None),
+    };
+    let text = subject
+        .strip_prefix(opts.prefix.as_str())
+        .and_then(|s| s.strip_suffix(opts.suffix.as_str()))
+        .unwrap_or(subject);
+    let scoped = SubjectOptions {
+        prefix: format!("{scope}: {}", opts.prefix),
+        ..opts.clone()
+    };
+    let mut msg = match rest {
+        Some(rest) => wrap_subject(&format!("{text}\n{rest}"), &scoped),
+        None => wrap_subject(text, &scoped),
+    };
+    if let Some(changes) = changes {
+        let all = file_change_footers(changes);
+        if !all.is_empty()
+            && let Some(at) = msg.rfind(&format!("\n\n{all}"))
+        {
+            let keep = |paths: &[String]| paths.iter().filter(|p| in_group(p)).cloned().collect();
+            let group = file_change_footers(&FileChanges {
+                added: keep(&changes.added),
+                modified: keep(&changes.modified),
+            });
+            let replacement = if group.is_empty() { String::new() } else { format!("\n\n{group}") };
+            msg.replace_range(at..at + all.len() + 2, &replacement);
+        }
+    }
+    msg
+}
+
 #[cfg(test)]
 mod tests;
@@ -1,5 +1,5 @@
 use super::*;
-use crate::transcript::{Transcript, Verbosity};
+use crate::transcript::{TailStrategy, Transcript, Verbosity};
 use serde_json::json;
 
 // ===================================================================
@@ -53,6 +53,58 @@ fn asst_entry(uuid: &str, parent: &str, text: &str) -> serde_json::Value {
     })
 }
 
+/// Assistant entry making a single tool call, with tool_use id `t-{uuid}`.
+fn tool_entry(uuid: &str, parent: &str, name: &str, input: serde_json::Value) -> serde_json::Value {
+    json!({
+        "type": "assistant",
+        "uuid": uuid,
+        "parentUuid": parent,
+        "isSidechain": false,
+        "userType": "external",
+        "cwd": "/tmp",
+        "sessionId": "s",
+        "timestamp": "t",
+        "version": "v",
+        "message": { "role": "assistant", "content": [
+            { "type": "tool_use", "id": format!("t-{uuid}"), "name": name, "input": input }
+        ]}
+    })
+}
+
+/// User entry carrying the tool_result for the call made by entry `tool`.
+fn result_entry(uuid: &str, tool: &str, content: serde_json::Value) -> serde_json::Value {
+    json!({
+        "type": "user",
+        "uuid": uuid,
+        "parentUuid": tool,
+        "isSidechain": false,
+        "userType": "external",
+        "cwd": "/tmp",
+        "sessionId": "s",
+        "timestamp": "t",
+        "version": "v",
+        "message": { "role": "user", "content": [
+            { "type": "tool_result", "tool_use_id": format!("t-{tool}"), "content": content }
+        ]}
+    })
+}
+
+/// AskUserQuestion tool input asking `questions`, for `tool_entry`.
+fn ask_input(questions: &[&str]) -> serde_json::Value {
+    let questions: Vec<serde_json::Value> = questions
+        .iter()
+        .map(|q| json!({ "question": q, "header": "Q", "options": [], "multiSelect": false }))
+        .collect();
+    json!({ "questions": questions })
+}
+
+/// The tool_result text Claude Code sends back for AskUserQuestion.
+fn ask_answer(answers: &str) -> serde_json::Value {
+    json!(format!(
+        "User has answered your questions: {answers}. You can now continue with the user's answers in mind."
+    ))
+}
+
 /// Progress entry (hook_progress).
 fn progress_entry(uuid: &str, parent: &str) -> serde_json::Value {
     json!({
@@ -96,10 +148,26 @@ fn make_ctx<'a>(
         plan_entries: vec![],
         session_id: "test-session",
         breadcrumb: None,
+        detect_resets: true,
         committed_tail: None,
         has_uncommitted_changes: has_uncommitted,
         commit_template: "{{ prompt }}",
-        verbosity: Verbosity::Medium,
+        summary: Verbosity::Medium.into(),
+        tail_strategy: TailStrategy::Last,
+        prompt_body: PromptBody::Omit,
+        summary_current_session_only: false,
+        subject: SubjectOptions::default(),
+        body_sections: vec![BodySection::Qa, BodySection::Plan, BodySection::Summary],
+        section_heading_style: HeadingStyle::Markdown,
+        include_sidechains_in_note: true,
+        permission_mode: None,
+        defer_to_manual_git: false,
+        max_transcript_note_entries: 0,
+        ignore_slash_command_prompts: true,
+        include_session_trailer: false,
+        version_trailer: false,
+        file_changes: None,
+        nonproductive_hint: NonproductiveHint::Always,
     }
 }
 
@@ -108,6 +176,15 @@ fn meta(prompt: &str, uuid: Option<&str>) -> PromptMetadata {
         prompt: prompt.to_string(),
         session_id: "s".to_string(),
         uuid: uuid.map(String::from),
+        submitted_at: None,
+        is_slash_command: false,
+    }
+}
+
+fn qa_pair(question: &str, answer: &str) -> QaPair {
+    QaPair {
+        question: question.to_string(),
+        answer: answer.to_string(),
     }
 }
 
@@ -158,7 +235,7 @@ fn nonproductive_turn_returns_breadcrumb() {
     let decision = decide_stop(&ctx).unwrap();
     match decision {
         StopDecision::Nonproductive {
-            hint_message,
+            hint_message: Some(hint_message),
             breadcrumb,
             plan_snapshot,
             pending_plan,
@@ -222,7 +299,7 @@ fn reset_after_productive_via_committed_tail() {
 
     let decision = decide_stop(&ctx).unwrap();
     match decision {
-        StopDecision::Nonproductive { hint_message, .. } => {
+        StopDecision::Nonproductive { hint_message: Some(hint_message), .. } => {
             assert!(hint_message.contains("reset detected"), "got: {hint_message}");
         }
         other => panic!("expected Nonproductive, got: {other:?}"),
@@ -246,7 +323,7 @@ fn reset_after_nonproductive_via_breadcrumb() {
 
     let decision = decide_stop(&ctx).unwrap();
     match decision {
-        StopDecision::Nonproductive { hint_message, .. } => {
+        StopDecision::Nonproductive { hint_message: Some(hint_message), .. } => {
             assert!(hint_message.contains("reset detected"), "got: {hint_message}");
         }
         other => panic!("expected Nonproductive, got: {other:?}"),
@@ -267,7 +344,7 @@ fn normal_continuation_no_false_reset() {
 
     let decision = decide_stop(&ctx).unwrap();
     match decision {
-        StopDecision::Nonproductive { hint_message, .. } => {
+        StopDecision::Nonproductive { hint_message: Some(hint_message), .. } => {
             assert!(!hint_message.contains("reset"), "got: {hint_message}");
         }
         other => panic!("expected Nonproductive, got: {other:?}"),
@@ -293,7 +370,7 @@ fn multiple_consecutive_resets() {
 
     let decision = decide_stop(&ctx).unwrap();
     match decision {
-        StopDecision::Nonproductive { hint_message, breadcrumb, .. } => {
+        StopDecision::Nonproductive { hint_message: Some(hint_message), breadcrumb, .. } => {
             assert!(hint_message.contains("reset detected"), "got: {hint_message}");
             assert_eq!(breadcrumb.tail_uuid, "a2");
         }
@@ -314,7 +391,7 @@ fn missing_prev_tail_no_false_reset() {
 
     let decision = decide_stop(&ctx).unwrap();
     match decision {
-        StopDecision::Nonproductive { hint_message, .. } => {
+        StopDecision::Nonproductive { hint_message: Some(hint_message), .. } => {
             assert!(!hint_message.contains("reset"), "got: {hint_message}");
         }
         other => panic!("expected Nonproductive, got: {other:?}"),
@@ -333,7 +410,7 @@ fn metadata_fallback_to_pending_plan() {
 
     let decision = decide_stop(&ctx).unwrap();
     match decision {
-        StopDecision::Nonproductive { hint_message, .. } => {
+        StopDecision::Nonproductive { hint_message: Some(hint_message), .. } => {
             assert!(hint_message.contains("nonproductive"), "got: {hint_message}");
         }
         other => panic!("expected Nonproductive, got: {other:?}"),
@@ -458,7 +535,7 @@ fn breadcrumb_priority_over_committed_tail() {
 
     let decision = decide_stop(&ctx).unwrap();
     match decision {
-        StopDecision::Nonproductive { hint_message, .. } => {
+        StopDecision::Nonproductive { hint_message: Some(hint_message), .. } => {
             // Breadcrumb takes priority, a2 is ancestor of a3 → no reset
             assert!(!hint_message.contains("reset"), "breadcrumb should take priority: {hint_message}");
         }
@@ -494,17 +571,9 @@ fn detect_reset_standalone() {
         asst_entry("a2", "u2", "done"),
     ]);
     let ctx_no_reset = StopContext {
-        transcript: &t,
-        file_metadata: None,
-        pending_plan: None,
-        plan_context: None,
-        plan_entries: vec![],
         session_id: "s",
-        breadcrumb: None,
         committed_tail: Some("a1".to_string()),
-        has_uncommitted_changes: false,
-        commit_template: "{{ prompt }}",
-        verbosity: Verbosity::Medium,
+        ..make_ctx(&t, None, false)
     };
     assert!(detect_reset(&ctx_no_reset, "a2").is_empty(), "no reset for linear chain");
 
@@ -516,17 +585,9 @@ fn detect_reset_standalone() {
         asst_entry("a2", "u2", "ok"),
     ]);
     let ctx_reset = StopContext {
-        transcript: &t2,
-        file_metadata: None,
-        pending_plan: None,
-        plan_context: None,
-        plan_entries: vec![],
         session_id: "s",
-        breadcrumb: None,
         committed_tail: Some("a1".to_string()),
-        has_uncommitted_changes: false,
-        commit_template: "{{ prompt }}",
-        verbosity: Verbosity::Medium,
+        ..make_ctx(&t2, None, false)
     };
     let hints = detect_reset(&ctx_reset, "a2");
     assert!(!hints.is_empty(), "should detect reset for branch");
@@ -687,8 +748,8 @@ fn cross_session_plan_context_with_qa() {
     ctx.plan_context = Some(PlanContext {
         original_prompt: "add user authentication".to_string(),
         qa: vec![
-            r#""Which auth method?"="JWT""#.to_string(),
-            r#""Where to store tokens?"="httpOnly cookies""#.to_string(),
+            qa_pair("Which auth method?", "JWT"),
+            qa_pair("Where to store tokens?", "httpOnly cookies"),
         ],
         planning_session_id: None,
     });
@@ -778,7 +839,7 @@ fn nonproductive_with_exit_plan_mode_captures_qa() {
             let pc = plan_context.expect("should produce plan_context");
             assert_eq!(pc.original_prompt, "plan the feature");
             assert_eq!(pc.qa.len(), 1);
-            assert!(pc.qa[0].contains("Option B"), "Q&A should contain answer: {:?}", pc.qa);
+            assert_eq!(pc.qa[0].answer, "Option B", "Q&A should contain answer: {:?}", pc.qa);
         }
         other => panic!("expected Nonproductive, got: {other:?}"),
     }
@@ -808,7 +869,7 @@ fn plan_entries_prepended_and_original_prompt_used() {
         qa: vec![],
         planning_session_id: None,
     });
-    ctx.plan_entries = vec![plan_entry1.clone(), plan_entry2.clone()];
+    ctx.plan_entries = vec![Arc::new(plan_entry1.clone()), Arc::new(plan_entry2.clone())];
 
     let decision = decide_stop(&ctx).unwrap();
     match decision {
@@ -940,11 +1001,915 @@ fn qa_from_nonproductive_turn_included_in_productive_commit() {
     }
 }
 
+// 26. Last user prompt at the committed tail → AlreadyCommitted, not NoMetadata
+#[test]
+fn committed_tail_at_last_prompt_returns_already_committed() {
+    let t = make_transcript(&[
+        user_entry("u1", None, "hello"),
+        asst_entry("a1", "u1", "hi"),
+    ]);
+    // No metadata file or pending plan: resolution falls back to the
+    // transcript, whose last user text is already committed.
+    let mut ctx = make_ctx(&t, None, false);
+    ctx.committed_tail = Some("u1".to_string());
+    let decision = decide_stop(&ctx).unwrap();
+    assert!(
+        matches!(decision, StopDecision::AlreadyCommitted),
+        "got: {decision:?}"
+    );
+
+    // Committed tail past the prompt (at the response) is also committed.
+    ctx.committed_tail = Some("a1".to_string());
+    let decision = decide_stop(&ctx).unwrap();
+    assert!(
+        matches!(decision, StopDecision::AlreadyCommitted),
+        "got: {decision:?}"
+    );
+}
+
+// 27. `files` template variable lists touched basenames, sorted and deduplicated
+#[test]
+fn commit_template_renders_touched_files() {
+    let t = make_transcript(&[
+        user_entry("u1", None, "refactor"),
+        json!({
+            "type": "assistant",
+            "uuid": "a1",
+            "parentUuid": "u1",
+            "isSidechain": false,
+            "userType": "external",
+            "cwd": "/tmp",
+            "sessionId": "s",
+            "timestamp": "t",
+            "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "tool_use", "id": "t1", "name": "Write", "input": { "file_path": "/src/util.rs", "content": "x" } },
+                { "type": "tool_use", "id": "t2", "name": "Edit", "input": { "file_path": "/src/main.rs", "old_string": "a", "new_string": "b" } },
+                { "type": "tool_use", "id": "t3", "name": "Edit", "input": { "file_path": "/src/util.rs", "old_string": "c", "new_string": "d" } },
+                { "type": "tool_use", "id": "t4", "name": "Read", "input": { "file_path": "/src/lib.rs" } }
+            ]}
+        }),
+    ]);
+    let mut ctx = make_ctx(&t, Some(meta("refactor", Some("u1"))), true);
+    ctx.commit_template = "{{ prompt }}\n{% for f in files %}- {{ f }}\n{% endfor %}";
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert!(
+                commit_message.starts_with("refactor\n- main.rs\n- util.rs\n"),
+                "files: {commit_message}"
+            );
+            assert!(!commit_message.contains("- lib.rs"), "read-only file listed: {commit_message}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 28. include_prompt_body quotes the full prompt after the subject
+#[test]
+fn prompt_body_is_quoted_after_subject() {
+    let t = make_transcript(&[
+        user_entry("u1", None, "add caching\n\nkeep it simple"),
+        asst_entry("a1", "u1", "done"),
+    ]);
+    let mut ctx = make_ctx(&t, Some(meta("add caching\n\nkeep it simple", Some("u1"))), true);
+    ctx.commit_template = "Add caching\n\nBody text.";
+    ctx.prompt_body = PromptBody::Include;
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert!(
+                commit_message.starts_with("Add caching\n\n> add caching\n>\n> keep it simple\n\nBody text."),
+                "quoted body: {commit_message}"
+            );
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+
+    // The quote is set off by a blank line even when the template's body
+    // follows the subject directly.
+    ctx.commit_template = "Add caching\nBody text.";
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert!(
+                commit_message.starts_with("Add caching\n\n> add caching\n>\n> keep it simple\n\nBody text."),
+                "quoted body: {commit_message}"
+            );
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+
+    // A prompt moved to refs/notes/prompt-full isn't quoted unless forced.
+    // (The turn summary quotes it once either way.)
+    let long = "x".repeat(5000);
+    let t = make_transcript(&[user_entry("u1", None, &long), asst_entry("a1", "u1", "done")]);
+    let mut ctx = make_ctx(&t, Some(meta(&long, Some("u1"))), true);
+    ctx.prompt_body = PromptBody::Include;
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert_eq!(commit_message.matches(&format!("> {long}")).count(), 1, "should not quote");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+    ctx.prompt_body = PromptBody::Force;
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert_eq!(commit_message.matches(&format!("> {long}")).count(), 2, "forced quote missing");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 29. Extended thinking levels used in the turn are reported in the hint
+#[test]
+fn productive_hint_reports_thinking_level() {
+    let mut prompt = user_entry("u1", None, "think hard about this");
+    prompt["thinkingMetadata"] = json!({ "level": "high", "disabled": false, "triggers": [] });
+    let mut followup = user_entry("u2", Some("a1"), "and this");
+    followup["thinkingMetadata"] = json!({ "level": "none", "disabled": true, "triggers": [] });
+    let t = make_transcript(&[
+        prompt,
+        asst_entry("a1", "u1", "thought"),
+        followup,
+        asst_entry("a2", "u2", "done"),
+    ]);
+    let ctx = make_ctx(&t, Some(meta("and this", Some("u2"))), true);
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { hint_message, .. } => {
+            assert!(hint_message.contains("thinking: high"), "hint: {hint_message}");
+            assert!(!hint_message.contains("none"), "disabled level reported: {hint_message}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 30. Conventional commit type: tests-only → test, new file → feat
+#[test]
+fn commit_type_inferred_from_tool_activity() {
+    // Edits confined to test files → test.
+    let t = make_transcript(&[
+        user_entry("u1", None, "more coverage"),
+        tool_entry("a1", "u1", "Read", json!({ "file_path": "/repo/src/lib.rs" })),
+        tool_entry("a2", "a1", "Edit", json!({ "file_path": "/repo/tests/stop.rs" })),
+        tool_entry("a3", "a2", "Write", json!({ "file_path": "/repo/src/parser/tests.rs" })),
+    ]);
+    let turn = t.turn("a3", None);
+    assert_eq!(ConventionalType::infer(&turn), ConventionalType::Test);
+
+    // A newly written source file → feat, rendered through the template.
+    let t = make_transcript(&[
+        user_entry("u1", None, "add caching"),
+        tool_entry("a1", "u1", "Edit", json!({ "file_path": "/repo/src/main.rs" })),
+        tool_entry("a2", "a1", "Write", json!({ "file_path": "/repo/src/cache.rs" })),
+    ]);
+    let turn = t.turn("a2", None);
+    assert_eq!(ConventionalType::infer(&turn), ConventionalType::Feat);
+
+    let mut ctx = make_ctx(&t, Some(meta("add caching", Some("u1"))), true);
+    ctx.commit_template = "{{ commit_type }}: {{ prompt }}";
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert!(commit_message.starts_with("feat: add caching"), "msg: {commit_message}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+
+    // Edits to existing files only → fix; no modifications → chore.
+    let t = make_transcript(&[
+        user_entry("u1", None, "it's broken"),
+        tool_entry("a1", "u1", "Edit", json!({ "file_path": "/repo/src/main.rs" })),
+    ]);
+    assert_eq!(ConventionalType::infer(&t.turn("a1", None)), ConventionalType::Fix);
+    let t = make_transcript(&[
+        user_entry("u1", None, "look around"),
+        tool_entry("a1", "u1", "Grep", json!({ "pattern": "TODO" })),
+    ]);
+    assert_eq!(ConventionalType::infer(&t.turn("a1", None)), ConventionalType::Chore);
+}
+
+// 31. summary_current_session_only drops tools from an earlier resumed session
+#[test]
+fn summary_limited_to_current_session() {
+    let mut old_prompt = user_entry("u1", None, "start");
+    old_prompt["sessionId"] = json!("old");
+    let mut old_edit = tool_entry("a1", "u1", "Edit", json!({ "file_path": "/repo/old.rs" }));
+    old_edit["sessionId"] = json!("old");
+    let t = make_transcript(&[
+        old_prompt,
+        old_edit,
+        user_entry("u2", Some("a1"), "continue"),
+        tool_entry("a2", "u2", "Edit", json!({ "file_path": "/repo/new.rs" })),
+    ]);
+    let mut ctx = make_ctx(&t, Some(meta("continue", Some("u2"))), true);
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert!(commit_message.contains("old.rs"), "full span: {commit_message}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+
+    ctx.summary_current_session_only = true;
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert!(commit_message.contains("edited: new.rs"), "msg: {commit_message}");
+            assert!(!commit_message.contains("old.rs"), "old session summarized: {commit_message}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 32. A reply cut off at max_tokens is flagged in the hint
+#[test]
+fn truncated_reply_adds_hint() {
+    let mut reply = asst_entry("a1", "u1", "partial");
+    reply["message"]["stop_reason"] = json!("max_tokens");
+    let t = make_transcript(&[user_entry("u1", None, "do it"), reply]);
+    let ctx = make_ctx(&t, Some(meta("do it", Some("u1"))), true);
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { hint_message, .. } => {
+            assert!(hint_message.contains("response truncated (max tokens)"), "hint: {hint_message}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 33. subject_prefix leads the subject and survives subject_max_len truncation
+#[test]
+fn subject_prefix_and_max_len() {
+    let t = make_transcript(&[
+        user_entry("u1", None, "rework the whole configuration loading path"),
+        asst_entry("a1", "u1", "done"),
+    ]);
+    let mut ctx = make_ctx(
+        &t,
+        Some(meta("rework the whole configuration loading path", Some("u1"))),
+        true,
+    );
+    ctx.subject = SubjectOptions {
+        prefix: "[AI] ".into(),
+        suffix: " (PROJ-1)".into(),
+        max_len: 30,
+    };
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            let subject = commit_message.lines().next().unwrap();
+            assert!(subject.starts_with("[AI] rework"), "subject: {subject}");
+            assert!(subject.ends_with("... (PROJ-1)"), "subject: {subject}");
+            assert_eq!(subject.chars().count(), 30, "subject: {subject}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+
+    // A prefix leaving room for less than "..." still fits the limit.
+    ctx.subject = SubjectOptions {
+        prefix: "[JIRA-12345-AI] ".into(),
+        suffix: String::new(),
+        max_len: 18,
+    };
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            let subject = commit_message.lines().next().unwrap();
+            assert_eq!(subject, "[JIRA-12345-AI] ..", "subject: {subject}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 34. body_sections controls section order
+#[test]
+fn body_sections_reorder_summary_before_plan() {
+    let t = make_transcript(&[
+        user_entry("u1", None, "do it"),
+        tool_entry("a1", "u1", "Edit", json!({ "file_path": "/repo/main.rs" })),
+    ]);
+    let mut ctx = make_ctx(&t, Some(meta("do it", Some("u1"))), true);
+    ctx.pending_plan = Some("Step one".into());
+    ctx.body_sections = vec![BodySection::Summary, BodySection::Plan];
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            let summary = commit_message.find("main.rs").expect("summary present");
+            let plan = commit_message.find("## Plan").expect("plan present");
+            assert!(summary < plan, "summary should precede plan: {commit_message}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+
+    ctx.body_sections = vec![BodySection::Plan];
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert!(commit_message.contains("## Plan"), "msg: {commit_message}");
+            assert!(!commit_message.contains("main.rs"), "summary omitted: {commit_message}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 35. A git_branch switch within the turn is flagged in the hint
+#[test]
+fn branch_change_during_turn_adds_hint() {
+    let mut prompt = user_entry("u1", None, "do it");
+    prompt["gitBranch"] = json!("main");
+    let mut reply = asst_entry("a1", "u1", "done");
+    reply["gitBranch"] = json!("feature");
+    let t = make_transcript(&[prompt, reply]);
+    let ctx = make_ctx(&t, Some(meta("do it", Some("u1"))), true);
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { hint_message, .. } => {
+            assert!(
+                hint_message.contains("branch changed during turn (main → feature)"),
+                "hint: {hint_message}"
+            );
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 36. A blank prompt falls back to a subject built from the edited files
+#[test]
+fn blank_prompt_synthesizes_subject_from_files() {
+    let t = make_transcript(&[
+        user_entry("u1", None, " "),
+        tool_entry("a1", "u1", "Edit", json!({ "file_path": "/repo/src/main.rs" })),
+        tool_entry("a2", "a1", "Edit", json!({ "file_path": "/repo/src/lib.rs" })),
+    ]);
+    let ctx = make_ctx(&t, Some(meta(" ", Some("u1"))), true);
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert_eq!(commit_message.lines().next(), Some("Update lib.rs and main.rs"));
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 37. include_sidechains_in_note=false drops sidechain entries from the note
+#[test]
+fn sidechain_entries_excluded_from_note() {
+    let mut side = asst_entry("s1", "u1", "subagent chatter");
+    side["isSidechain"] = json!(true);
+    let t = make_transcript(&[
+        user_entry("u1", None, "do it"),
+        side,
+        asst_entry("a1", "s1", "done"),
+    ]);
+    let mut ctx = make_ctx(&t, Some(meta("do it", Some("u1"))), true);
+
+    let uuids = |ctx: &StopContext| match decide_stop(ctx).unwrap() {
+        StopDecision::Productive { transcript_note_entries, .. } => transcript_note_entries
+            .iter()
+            .map(|v| v["uuid"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>(),
+        other => panic!("expected Productive, got: {other:?}"),
+    };
+    assert_eq!(uuids(&ctx), vec!["u1", "s1", "a1"]);
+
+    ctx.include_sidechains_in_note = false;
+    assert_eq!(uuids(&ctx), vec!["u1", "a1"]);
+}
+
+// 38. The latest permission mode in the span is written to refs/notes/permission
+#[test]
+fn permission_mode_note() {
+    let mut first = user_entry("u1", None, "start");
+    first["permissionMode"] = json!("default");
+    let mut second = user_entry("u2", Some("a1"), "go ahead");
+    second["permissionMode"] = json!("acceptEdits");
+    let t = make_transcript(&[first, asst_entry("a1", "u1", "ok"), second, asst_entry("a2", "u2", "done")]);
+    let ctx = make_ctx(&t, Some(meta("go ahead", Some("u2"))), true);
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { simple_notes, .. } => {
+            let note = simple_notes.iter().find(|(r, _)| r == "refs/notes/permission");
+            assert_eq!(note.map(|(_, v)| v.as_str()), Some("acceptEdits"));
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+
+    // No permission mode recorded → no note.
+    let t = make_transcript(&[user_entry("u1", None, "start"), asst_entry("a1", "u1", "ok")]);
+    let ctx = make_ctx(&t, Some(meta("start", Some("u1"))), true);
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { simple_notes, .. } => {
+            assert!(!simple_notes.iter().any(|(r, _)| r == "refs/notes/permission"));
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 39. In plan mode, a change-free turn's final response is captured as the plan
+#[test]
+fn plan_mode_captures_plan_without_exit_plan_mode() {
+    let t = make_transcript(&[
+        user_entry("u1", None, "plan the parser rewrite"),
+        asst_entry("a1", "u1", "1. Split the lexer\n2. Add error recovery"),
+    ]);
+    let mut ctx = make_ctx(&t, Some(meta("plan the parser rewrite", Some("u1"))), false);
+    ctx.permission_mode = Some(PermissionMode::Plan);
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Nonproductive {
+            hint_message: Some(hint_message),
+            plan_snapshot,
+            pending_plan,
+            plan_context,
+            ..
+        } => {
+            let (prompt, plan) = plan_snapshot.expect("plan snapshot in plan mode");
+            assert_eq!(prompt, "plan the parser rewrite");
+            assert!(plan.contains("Split the lexer"), "plan: {plan}");
+            assert_eq!(pending_plan.as_deref(), Some(plan.as_str()));
+            assert_eq!(plan_context.unwrap().original_prompt, "plan the parser rewrite");
+            assert!(hint_message.contains("plan snapshot saved"), "hint: {hint_message}");
+        }
+        other => panic!("expected Nonproductive, got: {other:?}"),
+    }
+
+    // Outside plan mode the same turn is just nonproductive.
+    ctx.permission_mode = Some(PermissionMode::Default);
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Nonproductive { plan_snapshot, plan_context, .. } => {
+            assert!(plan_snapshot.is_none());
+            assert!(plan_context.is_none());
+        }
+        other => panic!("expected Nonproductive, got: {other:?}"),
+    }
+}
+
+// 40. max_transcript_note_entries keeps the newest entries behind an elision marker
+#[test]
+fn transcript_note_trimmed_to_max_entries() {
+    let mut lines = vec![user_entry("u0", None, "start")];
+    for i in 1..10 {
+        let parent = if i == 1 { "u0".to_string() } else { format!("a{}", i - 1) };
+        lines.push(asst_entry(&format!("a{i}"), &parent, "working"));
+    }
+    let t = make_transcript(&lines);
+    let mut ctx = make_ctx(&t, Some(meta("start", Some("u0"))), true);
+    ctx.max_transcript_note_entries = 3;
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { transcript_note_entries, hint_message, .. } => {
+            assert_eq!(transcript_note_entries.len(), 4);
+            assert_eq!(*transcript_note_entries[0], json!({ "type": "elided", "count": 7 }));
+            let kept: Vec<&str> = transcript_note_entries[1..]
+                .iter()
+                .map(|v| v["uuid"].as_str().unwrap())
+                .collect();
+            assert_eq!(kept, ["a7", "a8", "a9"]);
+            assert!(hint_message.contains("3 transcript entries, 7 older elided"), "hint: {hint_message}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 41. A trailing slash-command prompt yields to the prior real prompt
+#[test]
+fn slash_command_prompt_uses_prior_real_prompt() {
+    let t = make_transcript(&[
+        user_entry("u1", None, "fix the off-by-one in the lexer"),
+        asst_entry("a1", "u1", "fixed"),
+        user_entry("u2", Some("a1"), "/test"),
+        asst_entry("a2", "u2", "tests pass"),
+    ]);
+    let mut ctx = make_ctx(&t, Some(meta("/test", Some("u2"))), true);
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert!(commit_message.starts_with("fix the off-by-one in the lexer"), "msg: {commit_message}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+
+    // Already committed → keep the command rather than reuse an old subject.
+    ctx.committed_tail = Some("a1".to_string());
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert!(commit_message.starts_with("/test"), "msg: {commit_message}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+
+    ctx.committed_tail = None;
+    ctx.ignore_slash_command_prompts = false;
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert!(commit_message.starts_with("/test"), "msg: {commit_message}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 42. include_session_trailer ends the message with a Claude-Session trailer
+#[test]
+fn session_trailer_appended() {
+    let t = make_transcript(&[user_entry("u1", None, "add logging"), asst_entry("a1", "u1", "added")]);
+    let mut ctx = make_ctx(&t, Some(meta("add logging", Some("u1"))), true);
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert!(!commit_message.contains("Claude-Session"), "msg: {commit_message}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+
+    ctx.include_session_trailer = true;
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert!(commit_message.starts_with("add logging"), "msg: {commit_message}");
+            assert!(commit_message.ends_with("\n\nClaude-Session: s"), "msg: {commit_message:?}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 43. Q&A shared by the plan context and the implementation turn appears once
+#[test]
+fn plan_context_and_impl_qa_deduplicated() {
+    let t = make_transcript(&[
+        user_entry("u1", None, "implement it"),
+        tool_entry("a1", "u1", "AskUserQuestion", ask_input(&["Which approach?"])),
+        result_entry("u2", "a1", ask_answer(r#""Which approach?"="Option B""#)),
+        asst_entry("a2", "u2", "done"),
+    ]);
+    let mut ctx = make_ctx(&t, Some(meta("implement it", Some("u1"))), true);
+    let impl_qa = Transcript::extract_qa(&t.turn("a2", None));
+    assert_eq!(impl_qa.len(), 1, "impl Q&A: {impl_qa:?}");
+    ctx.plan_context = Some(PlanContext {
+        original_prompt: "implement it".to_string(),
+        qa: vec![qa_pair("Where?", "src/"), impl_qa[0].clone()],
+        planning_session_id: None,
+    });
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            // Once as a line of the Q&A section (the summary has its own
+            // "Q&A: ..." line).
+            let qa_lines = commit_message.lines().filter(|l| *l == impl_qa[0].to_string()).count();
+            assert_eq!(qa_lines, 1, "msg: {commit_message}");
+            let where_at = commit_message.find("Where?").expect("plan Q&A kept");
+            let which_at = commit_message.find("Which approach?").unwrap();
+            assert!(where_at < which_at, "plan Q&A order kept: {commit_message}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 44. version_trailer adds a Generated-by trailer with the crate version
+#[test]
+fn version_trailer_appended() {
+    let t = make_transcript(&[user_entry("u1", None, "add logging"), asst_entry("a1", "u1", "added")]);
+    let mut ctx = make_ctx(&t, Some(meta("add logging", Some("u1"))), true);
+    let trailer = format!("Generated-by: clautribution {}", env!("CARGO_PKG_VERSION"));
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert!(!commit_message.contains("Generated-by"), "msg: {commit_message}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+
+    ctx.version_trailer = true;
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert!(commit_message.ends_with(&format!("\n\n{trailer}")), "msg: {commit_message:?}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+
+    // Shares the trailer paragraph with the session trailer.
+    ctx.include_session_trailer = true;
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert!(
+                commit_message.ends_with(&format!("\n\nClaude-Session: s\n{trailer}")),
+                "msg: {commit_message:?}"
+            );
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 45. Failed tool calls are reported as a ratio in the hint
+#[test]
+fn tool_failures_reported_in_hint() {
+    let result = |uuid: &str, tool: &str, is_error: bool| {
+        let mut entry = result_entry(uuid, tool, json!("out"));
+        entry["message"]["content"][0]["is_error"] = json!(is_error);
+        entry
+    };
+    let make = json!({ "command": "make" });
+    let t = make_transcript(&[
+        user_entry("u1", None, "build it"),
+        tool_entry("a1", "u1", "Bash", make.clone()),
+        result("r1", "a1", true),
+        tool_entry("a2", "r1", "Bash", make.clone()),
+        result("r2", "a2", false),
+        tool_entry("a3", "r2", "Bash", make.clone()),
+        result("r3", "a3", true),
+        tool_entry("a4", "r3", "Bash", make),
+        result("r4", "a4", false),
+        asst_entry("a5", "r4", "built"),
+    ]);
+    let turn = t.turn("a5", Some("u1"));
+    assert_eq!(Transcript::tool_error_stats(&turn), (2, 4));
+
+    let ctx = make_ctx(&t, Some(meta("build it", Some("u1"))), true);
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { hint_message, .. } => {
+            assert!(hint_message.contains("2/4 tools failed"), "hint: {hint_message}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+
+    // No failures, no hint.
+    let t = make_transcript(&[
+        user_entry("u1", None, "build it"),
+        tool_entry("a1", "u1", "Bash", json!({ "command": "make" })),
+        result("r1", "a1", false),
+        asst_entry("a2", "r1", "built"),
+    ]);
+    let ctx = make_ctx(&t, Some(meta("build it", Some("u1"))), true);
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { hint_message, .. } => {
+            assert!(!hint_message.contains("failed"), "hint: {hint_message}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 46. Fetched URLs and search queries are written to refs/notes/sources
+#[test]
+fn web_sources_note() {
+    let mut research = asst_entry("a1", "u1", "found it");
+    research["message"]["content"] = json!([
+        { "type": "tool_use", "id": "t1", "name": "WebFetch", "input": { "url": "https://example.com/spec" } },
+        { "type": "tool_use", "id": "t2", "name": "WebSearch", "input": { "query": "spec errata" } }
+    ]);
+    let t = make_transcript(&[user_entry("u1", None, "read the spec"), research]);
+    let ctx = make_ctx(&t, Some(meta("read the spec", Some("u1"))), true);
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { simple_notes, .. } => {
+            let note = simple_notes.iter().find(|(r, _)| r == "refs/notes/sources");
+            assert_eq!(
+                note.map(|(_, v)| v.as_str()),
+                Some("https://example.com/spec\nsearch: spec errata")
+            );
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+
+    // No web tools → no note.
+    let t = make_transcript(&[user_entry("u1", None, "start"), asst_entry("a1", "u1", "ok")]);
+    let ctx = make_ctx(&t, Some(meta("start", Some("u1"))), true);
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { simple_notes, .. } => {
+            assert!(!simple_notes.iter().any(|(r, _)| r == "refs/notes/sources"));
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 47. TODO/FIXME lines introduced by edits are written to refs/notes/todos
+#[test]
+fn introduced_todos_note() {
+    let mut edit = asst_entry("a1", "u1", "stubbed it");
+    edit["message"]["content"] = json!([
+        { "type": "tool_use", "id": "t1", "name": "Edit", "input": {
+            "file_path": "/repo/src/parser.rs",
+            "old_string": "fn parse() {}\n// TODO: old note",
+            "new_string": "fn parse() {\n    // TODO: handle escapes\n}\n// TODO: old note"
+        } }
+    ]);
+    let t = make_transcript(&[user_entry("u1", None, "stub the parser"), edit]);
+    let mut ctx = make_ctx(&t, Some(meta("stub the parser", Some("u1"))), true);
+    ctx.summary.workdir = Some("/repo".into());
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { simple_notes, .. } => {
+            let note = simple_notes.iter().find(|(r, _)| r == "refs/notes/todos");
+            assert_eq!(
+                note.map(|(_, v)| v.as_str()),
+                Some("src/parser.rs: // TODO: handle escapes")
+            );
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 48. nonproductive_hint: never suppresses, on_change only emits with another hint
+#[test]
+fn nonproductive_hint_preference() {
+    let t = make_transcript(&[user_entry("u1", None, "hello"), asst_entry("a1", "u1", "hi")]);
+    let mut ctx = make_ctx(&t, Some(meta("hello", Some("u1"))), false);
+    for mode in [NonproductiveHint::Never, NonproductiveHint::OnChange] {
+        ctx.nonproductive_hint = mode;
+        match decide_stop(&ctx).unwrap() {
+            StopDecision::Nonproductive { hint_message, .. } => {
+                assert_eq!(hint_message, None, "{mode:?}");
+            }
+            other => panic!("expected Nonproductive, got: {other:?}"),
+        }
+    }
+
+    // A reset is a change worth reporting under on_change.
+    let t = make_transcript(&[
+        user_entry("u1", None, "hello"),
+        asst_entry("a1", "u1", "hi"),
+        user_entry("u2", Some("u1"), "try again"),
+        asst_entry("a2", "u2", "retrying"),
+    ]);
+    let mut ctx = make_ctx(&t, Some(meta("try again", Some("u2"))), false);
+    ctx.committed_tail = Some("a1".to_string());
+    ctx.nonproductive_hint = NonproductiveHint::OnChange;
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Nonproductive { hint_message: Some(hint_message), .. } => {
+            assert!(hint_message.contains("reset detected"), "got: {hint_message}");
+        }
+        other => panic!("expected Nonproductive with a hint, got: {other:?}"),
+    }
+
+    ctx.nonproductive_hint = NonproductiveHint::Never;
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Nonproductive { hint_message, .. } => assert_eq!(hint_message, None),
+        other => panic!("expected Nonproductive, got: {other:?}"),
+    }
+}
+
+// 49. A change of model between assistant replies is flagged in the hint
+#[test]
+fn model_switch_during_turn_adds_hint() {
+    let mut first = asst_entry("a1", "u1", "looking");
+    first["message"]["model"] = json!("claude-sonnet-4-5");
+    let mut error = asst_entry("a2", "a1", "API Error");
+    error["message"]["model"] = json!("<synthetic>");
+    let mut second = asst_entry("a3", "a2", "done");
+    second["message"]["model"] = json!("claude-opus-4-5");
+    let t = make_transcript(&[user_entry("u1", None, "do it"), first, error, second]);
+
+    let turn = t.turn("a3", Some("u1"));
+    assert_eq!(
+        Transcript::model_switches(&turn),
+        vec![("claude-sonnet-4-5".to_string(), "claude-opus-4-5".to_string())]
+    );
+
+    let ctx = make_ctx(&t, Some(meta("do it", Some("u1"))), true);
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { hint_message, .. } => {
+            assert!(
+                hint_message.contains("model switched during turn (claude-sonnet-4-5 → claude-opus-4-5)"),
+                "hint: {hint_message}"
+            );
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 50. section_heading_style: "## Q&A" headings, or plain "Q&A:" labels
+#[test]
+fn section_heading_style_plain_uses_uppercase_labels() {
+    let mut user = user_entry("u1", None, "Implement the following plan:\n\n# Plan: Add auth\n\nStep 1");
+    user["planContent"] = json!("# Plan: Add auth\n\nStep 1");
+    let t = make_transcript(&[user, asst_entry("a1", "u1", "done")]);
+    let mut ctx = make_ctx(&t, None, true);
+    ctx.plan_context = Some(PlanContext {
+        original_prompt: "add user authentication".to_string(),
+        qa: vec![qa_pair("Which auth method?", "JWT")],
+        planning_session_id: None,
+    });
+
+    for (style, qa, plan) in [
+        (HeadingStyle::Markdown, "\n\n## Q&A\n\n", "\n\n## Plan\n\n"),
+        (HeadingStyle::Plain, "\n\nQ&A:\n\n", "\n\nPLAN:\n\n"),
+    ] {
+        ctx.section_heading_style = style;
+        match decide_stop(&ctx).unwrap() {
+            StopDecision::Productive { commit_message, .. } => {
+                assert!(commit_message.contains(qa), "{style:?}: {commit_message}");
+                assert!(commit_message.contains(plan), "{style:?}: {commit_message}");
+                if style == HeadingStyle::Plain {
+                    assert!(!commit_message.contains("## "), "{commit_message}");
+                }
+            }
+            other => panic!("expected Productive, got: {other:?}"),
+        }
+    }
+}
+
+// 51. defer_to_manual_git leaves a turn that ran `git commit` uncommitted
+#[test]
+fn defer_to_manual_git_routes_to_nonproductive() {
+    let t = make_transcript(&[
+        user_entry("u1", None, "commit it"),
+        tool_entry("a1", "u1", "Bash", json!({ "command": "cargo fmt && git commit -am 'fmt'" })),
+        asst_entry("a2", "a1", "committed"),
+    ]);
+    let mut ctx = make_ctx(&t, Some(meta("commit it", Some("u1"))), true);
+    assert!(matches!(decide_stop(&ctx).unwrap(), StopDecision::Productive { .. }));
+
+    ctx.defer_to_manual_git = true;
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Nonproductive { hint_message: Some(hint_message), .. } => {
+            assert!(
+                hint_message.contains("Claude ran git commit itself, not committing"),
+                "hint: {hint_message}"
+            );
+        }
+        other => panic!("expected Nonproductive, got: {other:?}"),
+    }
+}
+
+// 52. detect_resets = false suppresses the reset hint
+#[test]
+fn detect_resets_disabled_skips_reset_hint() {
+    let t = make_transcript(&[
+        user_entry("u1", None, "hello"),
+        asst_entry("a1", "u1", "hi"),
+        user_entry("u2", Some("u1"), "try again"),
+        asst_entry("a2", "u2", "retrying"),
+    ]);
+    let mut ctx = make_ctx(&t, Some(meta("try again", Some("u2"))), false);
+    ctx.committed_tail = Some("a1".to_string());
+    assert!(!detect_reset(&ctx, "a2").is_empty());
+
+    ctx.detect_resets = false;
+    assert!(detect_reset(&ctx, "a2").is_empty());
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Nonproductive { hint_message: Some(hint_message), .. } => {
+            assert!(!hint_message.contains("reset"), "got: {hint_message}");
+        }
+        other => panic!("expected Nonproductive, got: {other:?}"),
+    }
+}
+
+// 53. Q&A is also stored as JSON {question, answer} pairs in refs/notes/qa
+#[test]
+fn qa_note_stores_structured_pairs() {
+    let t = make_transcript(&[
+        user_entry("u1", None, "set up the db"),
+        tool_entry("a1", "u1", "AskUserQuestion", ask_input(&["Which database?", "Which port?"])),
+        result_entry("r1", "a1", ask_answer(r#""Which database?"="Postgres, v16", "Which port?"="5432""#)),
+        asst_entry("a2", "r1", "done"),
+    ]);
+    let ctx = make_ctx(&t, Some(meta("set up the db", Some("u1"))), true);
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { simple_notes, .. } => {
+            let (_, note) = simple_notes.iter().find(|(r, _)| r == "refs/notes/qa").expect("qa note");
+            let value: serde_json::Value = serde_json::from_str(note).unwrap();
+            assert_eq!(
+                value,
+                json!([
+                    { "question": "Which database?", "answer": "Postgres, v16" },
+                    { "question": "Which port?", "answer": "5432" },
+                ])
+            );
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
+// 54. summary_current_session_only starts at the last resume, even when that
+// resumes a session seen earlier in the file
+#[test]
+fn summary_limited_to_segment_after_last_resume() {
+    let in_session = |mut entry: serde_json::Value, session: &str| {
+        entry["sessionId"] = json!(session);
+        entry
+    };
+    let t = make_transcript(&[
+        in_session(user_entry("u1", None, "start"), "a"),
+        in_session(tool_entry("a1", "u1", "Edit", json!({ "file_path": "/repo/first.rs" })), "a"),
+        in_session(user_entry("u2", Some("a1"), "more"), "b"),
+        in_session(tool_entry("a2", "u2", "Edit", json!({ "file_path": "/repo/second.rs" })), "b"),
+        in_session(user_entry("u3", Some("a2"), "finish"), "a"),
+        in_session(tool_entry("a3", "u3", "Edit", json!({ "file_path": "/repo/third.rs" })), "a"),
+    ]);
+    let mut ctx = make_ctx(&t, Some(meta("finish", Some("u3"))), true);
+    ctx.summary_current_session_only = true;
+
+    match decide_stop(&ctx).unwrap() {
+        StopDecision::Productive { commit_message, .. } => {
+            assert!(commit_message.contains("edited: third.rs"), "msg: {commit_message}");
+            assert!(!commit_message.contains("first.rs"), "msg: {commit_message}");
+            assert!(!commit_message.contains("second.rs"), "msg: {commit_message}");
+        }
+        other => panic!("expected Productive, got: {other:?}"),
+    }
+}
+
 // Helper for debug formatting StopDecision in panic messages
 impl std::fmt::Debug for StopDecision {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             StopDecision::NoMetadata => write!(f, "NoMetadata"),
+            StopDecision::AlreadyCommitted => write!(f, "AlreadyCommitted"),
             StopDecision::NoTail => write!(f, "NoTail"),
             StopDecision::Nonproductive { hint_message, .. } => {
                 write!(f, "Nonproductive({hint_message:?})")
@@ -955,3 +1920,42 @@ impl std::fmt::Debug for StopDecision {
         }
     }
 }
+
+// 55. scope_message fits the scope into subject_max_len and keeps only the
+// group's own file-change footers
+#[test]
+fn scope_message_respects_max_len_and_filters_footers() {
+    let t = make_transcript(&[
+        user_entry("u1", None, "rework the whole configuration loading path"),
+        asst_entry("a1", "u1", "done"),
+    ]);
+    let mut ctx = make_ctx(
+        &t,
+        Some(meta("rework the whole configuration loading path", Some("u1"))),
+        true,
+    );
+    ctx.subject = SubjectOptions { prefix: "[AI] ".into(), suffix: String::new(), max_len: 30 };
+    let changes = FileChanges {
+        added: vec!["docs/guide.md".into(), "src/new.rs".into()],
+        modified: vec!["docs/index.md".into()],
+    };
+    ctx.file_changes = Some(changes.clone());
+
+    let StopDecision::Productive { commit_message, .. } = decide_stop(&ctx).unwrap() else {
+        panic!("expected Productive");
+    };
+    let in_src = |p: &str| p.starts_with("src/");
+    let scoped = scope_message(&commit_message, "src", &ctx.subject, Some(&changes), in_src);
+    let subject = scoped.lines().next().unwrap();
+    assert!(subject.starts_with("src: [AI] rework"), "subject: {subject}");
+    assert!(subject.ends_with("..."), "subject: {subject}");
+    assert_eq!(subject.chars().count(), 30, "subject: {subject}");
+    assert!(scoped.contains("\n\nAdded: src/new.rs"), "message: {scoped}");
+    assert!(!scoped.contains("docs/"), "message: {scoped}");
+
+    // A group whose changes aren't listed (e.g. only deletions) loses the
+    // footers entirely.
+    let scoped = scope_message(&commit_message, "lib", &ctx.subject, Some(&changes), |_| false);
+    assert!(!scoped.contains("Added:"), "message: {scoped}");
+    assert!(!scoped.contains("Modified:"), "message: {scoped}");
+}
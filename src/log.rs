@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much diagnostic output goes to stderr.  Set once from the global
+/// `--quiet` / `--verbose` flags; errors are always printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// `--quiet`: errors only.
+    Quiet = 0,
+    /// Default: errors and warnings.
+    Normal = 1,
+    /// `--verbose`: also debug lines (resolved session, tail, decision).
+    Verbose = 2,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Normal as u8);
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    LEVEL.load(Ordering::Relaxed) >= level as u8
+}
+
+/// Print a warning to stderr unless `--quiet` was given.
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Normal) {
+            eprintln!("clautribution: {}", format_args!($($arg)*));
+        }
+    };
+}
+
+/// Print a debug line to stderr when `--verbose` was given.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Verbose) {
+            eprintln!("clautribution: debug: {}", format_args!($($arg)*));
+        }
+    };
+}
+
+pub(crate) use {debug, warning};
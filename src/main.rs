@@ -1,4 +1,6 @@
+mod attestation;
 mod decision;
+mod log;
 mod metadata;
 mod preferences;
 mod session;
@@ -8,10 +10,21 @@ mod types;
 use anyhow::{Context, Result};
 use decision::{decide_stop, StopDecision};
 use session::Session;
-use std::io::{self, Read};
+use std::io::{self, Read, Write};
 use std::process;
 use types::{HookInput, HookOutput};
 
+/// Preferences file given with `--config`, overriding the repo's.
+static CONFIG: std::sync::OnceLock<std::path::PathBuf> = std::sync::OnceLock::new();
+
+/// `Session::open`, honoring `--config`.
+fn open_session(cwd: &str, session_id: &str) -> Result<Session> {
+    match CONFIG.get() {
+        Some(config) => Session::open_with_config(cwd, session_id, config),
+        None => Session::open(cwd, session_id),
+    }
+}
+
 fn read_stdin() -> Result<String> {
     let mut buffer = String::new();
     io::stdin().read_to_string(&mut buffer)?;
@@ -21,36 +34,79 @@ fn read_stdin() -> Result<String> {
 /// Open a session by discovering the active session ID.  Tries prompt
 /// metadata files first, then falls back to the most recent transcript.
 fn open_active_session(cwd: &str) -> Result<(Session, String)> {
-    let probe = Session::open(cwd, "")?;
+    let probe = open_session(cwd, "")?;
     // Try prompt metadata files first (most precise).
     if let Some(sid) = probe.active_session_id()? {
-        let session = Session::open(cwd, &sid)?;
+        let session = open_session(cwd, &sid)?;
         if let Some((_, transcript_path)) = session.active_transcript()? {
             return Ok((session, transcript_path));
         }
     }
-    // Fall back to most recent transcript file.
-    let (session_id, transcript_path) = probe
+    // Fall back to most recent transcript file, named for its session.
+    let (mut session_id, transcript_path) = probe
         .active_transcript()?
         .context("no active session (no transcript found)")?;
-    let session = Session::open(cwd, &session_id)?;
+    // A renamed or copied transcript's file name isn't a session its
+    // entries know; trust the session ID they carry instead.
+    let transcript = session::read_transcript(&transcript_path)?;
+    let named_session_known = transcript
+        .entries()
+        .iter()
+        .any(|e| e.session_id() == Some(session_id.as_str()));
+    if !named_session_known && let Some(embedded) = transcript.session_id() {
+        session_id = embedded.to_string();
+    }
+    let session = open_session(cwd, &session_id)?;
     Ok((session, transcript_path))
 }
 
-fn run_preview(cwd: &str) -> Result<()> {
+fn run_preview(cwd: &str, turn: Option<&str>, commit: bool) -> Result<()> {
     let (session, transcript_path) = open_active_session(cwd)?;
+    if commit {
+        // Run the real Stop decision (nothing forced) and commit if it's
+        // productive, exactly as the hook would.
+        match session.stop(&transcript_path, false, None)?.and_then(|o| o.system_message) {
+            Some(message) => println!("{message}"),
+            None => println!("Nothing to commit."),
+        }
+        return Ok(());
+    }
     let mut owned = session.build_stop_context(&transcript_path)?;
     // Force the productive path so we always render a commit message,
     // even when there are no uncommitted changes yet.
     owned.has_uncommitted_changes = true;
+    if let Some(uuid) = turn {
+        // Replay a historical turn: cut the transcript at `uuid` and
+        // resolve everything from the transcript alone, with the span
+        // starting at that turn's prompt.
+        owned.transcript = owned
+            .transcript
+            .truncated_at(uuid)
+            .with_context(|| format!("no transcript entry with uuid {uuid}"))?;
+        owned.file_metadata = None;
+        owned.pending_plan = None;
+        owned.plan_context = None;
+        owned.plan_entries.clear();
+        owned.breadcrumb = None;
+        owned.tail_strategy = transcript::TailStrategy::Last;
+        owned.committed_tail = owned
+            .transcript
+            .last_user_text()
+            .and_then(|(prompt_uuid, _, _)| owned.transcript.get(prompt_uuid))
+            .and_then(|e| e.parent_uuid())
+            .map(String::from);
+    }
     let ctx = owned.as_ref();
     let decision = decide_stop(&ctx).map_err(|e| anyhow::anyhow!("{e}"))?;
     match decision {
-        StopDecision::NoMetadata => {
-            println!("No prompt metadata — nothing to preview.");
+        StopDecision::NoMetadata | StopDecision::NoTail => {
+            println!("{}", nothing_to_preview(&transcript_path, &owned.transcript, &decision)?);
         }
-        StopDecision::NoTail => {
-            println!("No transcript tail — nothing to preview.");
+        StopDecision::AlreadyCommitted => {
+            println!(
+                "Nothing new since the last commit — nothing to preview. \
+                 Use `preview --turn <uuid>` to replay a committed turn."
+            );
         }
         StopDecision::Productive { commit_message, .. } => {
             println!("{commit_message}");
@@ -63,6 +119,41 @@ fn run_preview(cwd: &str) -> Result<()> {
     Ok(())
 }
 
+/// Why `preview` has nothing to show when the decision found no tail or
+/// no prompt: the transcript is missing or empty, couldn't be parsed, or
+/// has entries but no conversation to summarize.
+fn nothing_to_preview(
+    transcript_path: &str,
+    transcript: &transcript::Transcript,
+    decision: &StopDecision,
+) -> Result<String> {
+    if transcript.entries().is_empty() {
+        let contents = match std::fs::read(transcript_path) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("reading transcript {transcript_path}")),
+        };
+        if contents.trim().is_empty() {
+            return Ok(format!(
+                "Transcript {transcript_path} is empty — nothing to preview. \
+                 Send a prompt in this session first."
+            ));
+        }
+        let (_, errors) = transcript::Transcript::parse(&contents);
+        return Ok(format!(
+            "Transcript {transcript_path} has no readable entries ({} line(s) failed to parse) — \
+             nothing to preview. Run `clautribution validate-transcript {transcript_path}` for details.",
+            errors.len()
+        ));
+    }
+    Ok(match decision {
+        StopDecision::NoTail => format!(
+            "Transcript {transcript_path} has no user or assistant messages yet — nothing to preview."
+        ),
+        _ => "No prompt found for the latest turn — nothing to preview.".to_string(),
+    })
+}
+
 fn run_drop(cwd: &str) -> Result<()> {
     let (session, transcript_path) = open_active_session(cwd)?;
     let transcript = session::read_transcript(&transcript_path)?;
@@ -74,19 +165,347 @@ fn run_drop(cwd: &str) -> Result<()> {
     Ok(())
 }
 
+fn run_diff(cwd: &str) -> Result<()> {
+    let session = open_session(cwd, "")?;
+    print!("{}", session.pending_diff()?);
+    Ok(())
+}
+
+fn run_search(cwd: &str, needle: &str) -> Result<()> {
+    let (_, transcript_path) = open_active_session(cwd)?;
+    let transcript = session::read_transcript(&transcript_path)?;
+    let hits = transcript.search(needle);
+    if hits.is_empty() {
+        println!("No matches.");
+    }
+    for uuid in hits {
+        let snippet = transcript.search_snippet(uuid, needle, 80).unwrap_or_default();
+        println!("{uuid}  {snippet}");
+    }
+    Ok(())
+}
+
+fn run_commands(cwd: &str) -> Result<()> {
+    let (_, transcript_path) = open_active_session(cwd)?;
+    let transcript = session::read_transcript(&transcript_path)?;
+    let Some(tail) = transcript.conversation_tail() else {
+        return Ok(());
+    };
+    let session = transcript.turn(tail, None);
+    for command in transcript::Transcript::commands_run(&session) {
+        println!("{command}");
+    }
+    Ok(())
+}
+
+/// Every tool call of the active session in call order, one
+/// `uuid  tool  label` line each.
+fn run_timeline(cwd: &str) -> Result<()> {
+    let (_, transcript_path) = open_active_session(cwd)?;
+    let transcript = session::read_transcript(&transcript_path)?;
+    let Some(tail) = transcript.conversation_tail() else {
+        return Ok(());
+    };
+    let session = transcript.turn(tail, None);
+    for (uuid, tool, label) in transcript::Transcript::tool_timeline(&session) {
+        println!("{uuid}  {tool}  {label}");
+    }
+    Ok(())
+}
+
+fn run_show_prompt(cwd: &str, rev: &str) -> Result<()> {
+    let session = open_session(cwd, "")?;
+    let prompt = session
+        .stored_prompt(rev)?
+        .with_context(|| format!("no prompt note on {rev}"))?;
+    println!("{prompt}");
+    Ok(())
+}
+
+fn run_log(cwd: &str, limit: Option<usize>, all: bool) -> Result<()> {
+    let session = open_session(cwd, "")?;
+    let commits = session.iter_annotated_commits(all)?;
+    for commit in commits.take(limit.unwrap_or(usize::MAX)) {
+        let (oid, prompt) = commit?;
+        let short = &oid.to_string()[..7];
+        println!("{short}  {}", prompt.as_deref().unwrap_or("(not annotated)"));
+    }
+    Ok(())
+}
+
+fn run_doctor(cwd: &str) -> Result<()> {
+    let (_, transcript_path) = open_active_session(cwd)?;
+    let transcript = session::read_transcript(&transcript_path)?;
+    let issues = transcript.validate_dag();
+    println!("transcript: {transcript_path}");
+    if issues.is_empty() {
+        println!("No transcript issues found.");
+    }
+    for issue in issues {
+        println!("- {issue}");
+    }
+    Ok(())
+}
+
+fn run_validate_transcript(path: &str, strict: bool) -> Result<()> {
+    let bytes = std::fs::read(path).with_context(|| format!("reading {path}"))?;
+    let contents = String::from_utf8_lossy(&bytes);
+    let (transcript, errors) = if strict {
+        match transcript::Transcript::parse_strict(&contents) {
+            Ok(transcript) => (transcript, Vec::new()),
+            Err(errors) => {
+                for (line, err) in &errors {
+                    println!("line {line}: {err}");
+                }
+                anyhow::bail!("{path}: {} parse errors", errors.len());
+            }
+        }
+    } else {
+        transcript::Transcript::parse(&contents)
+    };
+    let mut counts = std::collections::BTreeMap::new();
+    for entry in transcript.entries() {
+        *counts.entry(entry.type_name()).or_insert(0usize) += 1;
+    }
+    println!("{} entries", transcript.entries().len());
+    for (type_name, count) in counts {
+        println!("  {type_name}: {count}");
+    }
+    if let Some(header) = transcript.skipped_header() {
+        println!("skipped header line: {header}");
+    }
+    if !errors.is_empty() {
+        println!("{} parse errors:", errors.len());
+        for (line, err, snippet) in &errors {
+            println!("  line {line}: {err}");
+            println!("    {snippet}");
+        }
+    }
+    let issues = transcript.validate_dag();
+    if !issues.is_empty() {
+        println!("{} DAG issues:", issues.len());
+        for issue in &issues {
+            println!("  {issue}");
+        }
+    }
+    if errors.is_empty() && issues.is_empty() {
+        println!("No problems found.");
+    }
+    Ok(())
+}
+
+fn run_decide(transcript_path: &str, committed_tail: Option<&str>, changes: bool) -> Result<()> {
+    let mut owned = session::OwnedStopContext::from_transcript(session::read_transcript(transcript_path)?);
+    owned.committed_tail = committed_tail.map(String::from);
+    owned.has_uncommitted_changes = changes;
+    let decision = decide_stop(&owned.as_ref()).map_err(|e| anyhow::anyhow!("{e}"))?;
+    println!("{}", serde_json::to_string_pretty(&decision.to_json())?);
+    Ok(())
+}
+
+/// Print the resolved inputs of the Stop decision for the active session,
+/// without deciding or changing anything.
+fn run_dump_context(cwd: &str) -> Result<()> {
+    let (session, transcript_path) = open_active_session(cwd)?;
+    let owned = session.build_stop_context(&transcript_path)?;
+    let or_none = |value: Option<&str>| value.unwrap_or("(none)").to_string();
+    println!("transcript: {transcript_path}");
+    println!("session_id: {}", owned.session_id);
+    println!(
+        "file_metadata: {}",
+        match &owned.file_metadata {
+            Some(meta) => format!("present (uuid {})", or_none(meta.uuid.as_deref())),
+            None => "(none)".to_string(),
+        }
+    );
+    println!("pending_plan: {}", if owned.pending_plan.is_some() { "present" } else { "(none)" });
+    println!("plan_context: {}", if owned.plan_context.is_some() { "present" } else { "(none)" });
+    println!("committed_tail: {}", or_none(owned.committed_tail.as_deref()));
+    println!(
+        "breadcrumb: {}",
+        match &owned.breadcrumb {
+            Some(b) => format!("{} (session {})", b.tail_uuid, b.session_id),
+            None => "(none)".to_string(),
+        }
+    );
+    println!("has_uncommitted_changes: {}", owned.has_uncommitted_changes);
+    println!("verbosity: {}", format!("{:?}", owned.summary.verbosity).to_lowercase());
+    println!("tail_strategy: {}", format!("{:?}", owned.tail_strategy).to_lowercase());
+    Ok(())
+}
+
+fn run_verify(cwd: &str, rev: &str) -> Result<()> {
+    let session = open_session(cwd, "")?;
+    let oid = session.verify_attestation(rev)?;
+    println!("{oid}: attestation valid");
+    Ok(())
+}
+
+/// `config get <cwd> <key>` prints a preference's effective value;
+/// `config set <cwd> <key> <value>` writes it to the repo's preferences.
+fn run_config(action: &str, cwd: &str, key: &str, value: Option<&str>) -> Result<()> {
+    let session = open_session(cwd, "")?;
+    match (action, value) {
+        ("get", None) => {
+            if let Some(value) = session.prefs().get(key)? {
+                println!("{value}");
+            }
+            Ok(())
+        }
+        ("set", Some(value)) => preferences::Preferences::set(session.data_dir(), key, value),
+        _ => anyhow::bail!("unknown config action {action}"),
+    }
+}
+
+/// Hook events clautribution handles, in the order they're installed.
+const HOOK_EVENTS: &[&str] = &["SessionStart", "UserPromptSubmit", "Stop", "SessionEnd"];
+
+/// Add this binary as a command hook for each of `HOOK_EVENTS` in the
+/// repo's `.claude/settings.json`, creating the file if needed.  Other
+/// settings and hooks are kept; events already running this binary are
+/// left alone.
+fn run_install_hooks(cwd: &str) -> Result<()> {
+    let repo = git2::Repository::discover(cwd)
+        .with_context(|| format!("finding git repo from {cwd}"))?;
+    let workdir = repo.workdir().context("git repo is bare, no working directory")?;
+    let path = workdir.join(".claude").join("settings.json");
+    let mut settings: serde_json::Value = match std::fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .with_context(|| format!("parsing {}", path.display()))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => serde_json::json!({}),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+    let exe = std::env::current_exe().context("locating the clautribution binary")?;
+    let command = exe.to_string_lossy().into_owned();
+
+    let hooks = settings
+        .as_object_mut()
+        .with_context(|| format!("{} is not a JSON object", path.display()))?
+        .entry("hooks")
+        .or_insert_with(|| serde_json::json!({}))
+        .as_object_mut()
+        .context("\"hooks\" in settings is not an object")?;
+    for event in HOOK_EVENTS {
+        let groups = hooks
+            .entry(*event)
+            .or_insert_with(|| serde_json::json!([]))
+            .as_array_mut()
+            .with_context(|| format!("hooks.{event} in settings is not an array"))?;
+        let installed = groups.iter().any(|group| {
+            group["hooks"]
+                .as_array()
+                .is_some_and(|hs| hs.iter().any(|h| h["command"].as_str() == Some(command.as_str())))
+        });
+        if installed {
+            println!("{event}: already installed");
+        } else {
+            groups.push(serde_json::json!({
+                "hooks": [{ "type": "command", "command": command }]
+            }));
+            println!("{event}: installed");
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("creating {}", parent.display()))?;
+    }
+    let json = serde_json::to_string_pretty(&settings).context("serializing settings")?;
+    std::fs::write(&path, json + "\n").with_context(|| format!("writing {}", path.display()))?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+fn run_export_html(cwd: &str, out: Option<&str>) -> Result<()> {
+    let (_, transcript_path) = open_active_session(cwd)?;
+    let transcript = session::read_transcript(&transcript_path)?;
+    let values: Vec<serde_json::Value> = transcript
+        .entries()
+        .iter()
+        .filter_map(|e| e.uuid().and_then(|uuid| transcript.get_raw(uuid)).cloned())
+        .collect();
+    let html = transcript::Transcript::to_html(&values);
+    match out {
+        Some(path) => std::fs::write(path, html).with_context(|| format!("writing {path}"))?,
+        None => print!("{html}"),
+    }
+    Ok(())
+}
+
+/// The active session's (prompt, reply) pairs as JSON Lines, one
+/// `{"prompt": ..., "reply": ...}` object per line.
+fn run_export_pairs(cwd: &str) -> Result<()> {
+    let (_, transcript_path) = open_active_session(cwd)?;
+    let transcript = session::read_transcript(&transcript_path)?;
+    for (prompt, reply) in transcript.as_conversation_pairs() {
+        println!("{}", serde_json::json!({ "prompt": prompt, "reply": reply }));
+    }
+    Ok(())
+}
+
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    // Global flags may appear anywhere; strip them before dispatch.
+    let mut args: Vec<String> = Vec::new();
+    let mut raw_args = std::env::args();
+    while let Some(arg) = raw_args.next() {
+        match arg.as_str() {
+            "--quiet" => log::set_level(log::Level::Quiet),
+            "--verbose" => log::set_level(log::Level::Verbose),
+            "--config" => match raw_args.next() {
+                Some(path) => {
+                    let _ = CONFIG.set(path.into());
+                }
+                None => {
+                    eprintln!("usage: clautribution --config <path> ...");
+                    process::exit(1);
+                }
+            },
+            _ => args.push(arg),
+        }
+    }
 
-    // Subcommand dispatch: `clautribution preview <cwd>`
+    // Subcommand dispatch: `clautribution preview <cwd> [--turn <uuid> | --commit]`
     //                      `clautribution drop <cwd>`
+    //                      `clautribution diff <cwd>`
+    //                      `clautribution search <cwd> <needle>`
+    //                      `clautribution export-html <cwd> [--out <file>]`
+    //                      `clautribution export-pairs <cwd>`
+    //                      `clautribution doctor <cwd>`
+    //                      `clautribution commands <cwd>`
+    //                      `clautribution timeline <cwd>`
+    //                      `clautribution validate-transcript <path> [--strict]`
+    //                      `clautribution show-prompt <cwd> [<ref>]`
+    //                      `clautribution log <cwd> [--limit <n>] [--all]`
+    //                      `clautribution decide <transcript> [--committed-tail <uuid>] [--changes]`
+    //                      `clautribution install-hooks <cwd>`
+    //                      `clautribution verify <cwd> <rev>`
+    //                      `clautribution dump-context <cwd>`
+    //                      `clautribution config get <cwd> <key>`
+    //                      `clautribution config set <cwd> <key> <value>`
+    // Any of these (or the bare hook) also accepts `--quiet` / `--verbose`
+    // and `--config <path>`.
     if args.len() >= 2 {
         let result = match args[1].as_str() {
             "preview" => {
-                if args.len() < 3 {
-                    eprintln!("usage: clautribution preview <cwd>");
+                let mut valid = args.len() >= 3;
+                let mut turn = None;
+                let mut commit = false;
+                let mut flags = args.iter().skip(3).map(String::as_str);
+                while let Some(flag) = flags.next() {
+                    match flag {
+                        "--turn" => {
+                            turn = flags.next();
+                            valid &= turn.is_some();
+                        }
+                        "--commit" => commit = true,
+                        _ => valid = false,
+                    }
+                }
+                if !valid || (commit && turn.is_some()) {
+                    eprintln!("usage: clautribution preview <cwd> [--turn <uuid> | --commit]");
                     process::exit(1);
                 }
-                run_preview(&args[2])
+                run_preview(&args[2], turn, commit)
             }
             "drop" => {
                 if args.len() < 3 {
@@ -95,6 +514,149 @@ fn main() {
                 }
                 run_drop(&args[2])
             }
+            "diff" => {
+                if args.len() < 3 {
+                    eprintln!("usage: clautribution diff <cwd>");
+                    process::exit(1);
+                }
+                run_diff(&args[2])
+            }
+            "search" => {
+                if args.len() < 4 {
+                    eprintln!("usage: clautribution search <cwd> <needle>");
+                    process::exit(1);
+                }
+                run_search(&args[2], &args[3])
+            }
+            "doctor" => {
+                if args.len() < 3 {
+                    eprintln!("usage: clautribution doctor <cwd>");
+                    process::exit(1);
+                }
+                run_doctor(&args[2])
+            }
+            "commands" => {
+                if args.len() < 3 {
+                    eprintln!("usage: clautribution commands <cwd>");
+                    process::exit(1);
+                }
+                run_commands(&args[2])
+            }
+            "timeline" => {
+                if args.len() < 3 {
+                    eprintln!("usage: clautribution timeline <cwd>");
+                    process::exit(1);
+                }
+                run_timeline(&args[2])
+            }
+            "validate-transcript" => match &args[2..] {
+                [path] => run_validate_transcript(path, false),
+                [path, flag] if flag == "--strict" => run_validate_transcript(path, true),
+                _ => {
+                    eprintln!("usage: clautribution validate-transcript <path> [--strict]");
+                    process::exit(1);
+                }
+            },
+            "show-prompt" => {
+                if args.len() < 3 || args.len() > 4 {
+                    eprintln!("usage: clautribution show-prompt <cwd> [<ref>]");
+                    process::exit(1);
+                }
+                run_show_prompt(&args[2], args.get(3).map_or("HEAD", String::as_str))
+            }
+            "log" => {
+                let mut valid = args.len() >= 3;
+                let mut limit = None;
+                let mut all = false;
+                let mut flags = args.iter().skip(3).map(String::as_str);
+                while let Some(flag) = flags.next() {
+                    match flag {
+                        "--limit" => {
+                            limit = flags.next().and_then(|n| n.parse::<usize>().ok());
+                            valid &= limit.is_some();
+                        }
+                        "--all" => all = true,
+                        _ => valid = false,
+                    }
+                }
+                if !valid {
+                    eprintln!("usage: clautribution log <cwd> [--limit <n>] [--all]");
+                    process::exit(1);
+                }
+                run_log(&args[2], limit, all)
+            }
+            "decide" => {
+                let mut valid = args.len() >= 3;
+                let mut committed_tail = None;
+                let mut changes = false;
+                let mut flags = args.iter().skip(3).map(String::as_str);
+                while let Some(flag) = flags.next() {
+                    match flag {
+                        "--committed-tail" => {
+                            committed_tail = flags.next();
+                            valid &= committed_tail.is_some();
+                        }
+                        "--changes" => changes = true,
+                        _ => valid = false,
+                    }
+                }
+                if !valid {
+                    eprintln!(
+                        "usage: clautribution decide <transcript> [--committed-tail <uuid>] [--changes]"
+                    );
+                    process::exit(1);
+                }
+                run_decide(&args[2], committed_tail, changes)
+            }
+            "verify" => {
+                if args.len() < 4 {
+                    eprintln!("usage: clautribution verify <cwd> <rev>");
+                    process::exit(1);
+                }
+                run_verify(&args[2], &args[3])
+            }
+            "dump-context" => {
+                if args.len() < 3 {
+                    eprintln!("usage: clautribution dump-context <cwd>");
+                    process::exit(1);
+                }
+                run_dump_context(&args[2])
+            }
+            "config" => match (args.get(2).map(String::as_str), args.len()) {
+                (Some("get"), 5) => run_config("get", &args[3], &args[4], None),
+                (Some("set"), 6) => run_config("set", &args[3], &args[4], Some(&args[5])),
+                _ => {
+                    eprintln!("usage: clautribution config get <cwd> <key>");
+                    eprintln!("       clautribution config set <cwd> <key> <value>");
+                    process::exit(1);
+                }
+            },
+            "install-hooks" => {
+                if args.len() < 3 {
+                    eprintln!("usage: clautribution install-hooks <cwd>");
+                    process::exit(1);
+                }
+                run_install_hooks(&args[2])
+            }
+            "export-html" => {
+                let out = match args.get(3).map(String::as_str) {
+                    None => None,
+                    Some("--out") if args.len() == 5 => Some(args[4].as_str()),
+                    Some(_) => None,
+                };
+                if args.len() < 3 || (args.len() > 3 && out.is_none()) {
+                    eprintln!("usage: clautribution export-html <cwd> [--out <file>]");
+                    process::exit(1);
+                }
+                run_export_html(&args[2], out)
+            }
+            "export-pairs" => {
+                if args.len() < 3 {
+                    eprintln!("usage: clautribution export-pairs <cwd>");
+                    process::exit(1);
+                }
+                run_export_pairs(&args[2])
+            }
             _ => {
                 // Not a recognized subcommand — fall through to hook path.
                 run_hook()
@@ -129,30 +691,56 @@ fn is_no_git_repo(err: &anyhow::Error) -> bool {
     })
 }
 
+/// Append a hook invocation to the file named by `CLAUTRIBUTION_LOG`, if
+/// set: the input JSON and the emitted output (or `none`), each on a line
+/// prefixed with a Unix timestamp.  Logging failures are reported on
+/// stderr but never fail the hook.
+fn log_hook(input: &str, output: Option<&str>) {
+    let Ok(path) = std::env::var("CLAUTRIBUTION_LOG") else {
+        return;
+    };
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    // Compact the input so each record stays on one line.
+    let input = serde_json::from_str::<serde_json::Value>(input)
+        .map(|v| v.to_string())
+        .unwrap_or_else(|_| input.trim().to_string());
+    let entry = format!("{now} input: {input}\n{now} output: {}\n", output.unwrap_or("none"));
+    let result = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut f| f.write_all(entry.as_bytes()));
+    if let Err(err) = result {
+        log::warning!("writing log {path}: {err}");
+    }
+}
+
 fn run_hook() -> Result<()> {
     let input = read_stdin()?;
     let hook_input: HookInput = serde_json::from_str(&input)?;
 
     let result: Result<Option<HookOutput>> = match &hook_input {
-        HookInput::SessionStart(e) => Session::open(&e.common.cwd, &e.common.session_id)
+        HookInput::SessionStart(e) => open_session(&e.common.cwd, &e.common.session_id)
             .and_then(|s| s.handle_session_start(e)),
-        HookInput::UserPromptSubmit(e) => Session::open(&e.common.cwd, &e.common.session_id)
+        HookInput::UserPromptSubmit(e) => open_session(&e.common.cwd, &e.common.session_id)
             .and_then(|s| s.handle_user_prompt_submit(e)),
-        HookInput::Stop(e) => Session::open(&e.common.cwd, &e.common.session_id)
+        HookInput::Stop(e) => open_session(&e.common.cwd, &e.common.session_id)
             .and_then(|s| s.handle_stop(e)),
-        HookInput::SessionEnd(e) => Session::open(&e.common.cwd, &e.common.session_id)
+        HookInput::SessionEnd(e) => open_session(&e.common.cwd, &e.common.session_id)
             .and_then(|s| s.handle_session_end(e)),
         _ => Ok(None),
     };
 
     match result {
         Ok(Some(output)) => {
-            println!(
-                "{}",
-                serde_json::to_string(&output).expect("Failed to serialize output")
-            );
+            let json = serde_json::to_string(&output).expect("Failed to serialize output");
+            log_hook(&input, Some(&json));
+            println!("{json}");
         }
-        Ok(None) => {}
+        Ok(None) => log_hook(&input, None),
         Err(err) if is_no_git_repo(&err) => {
             // Not inside a git repository — nothing to do.
         }
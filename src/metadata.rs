@@ -1,4 +1,5 @@
-use serde::{Deserialize, Serialize};
+use crate::transcript::QaPair;
+use serde::{Deserialize, Deserializer, Serialize};
 
 /// Metadata about the initial prompt that started this session.
 /// Stored as `.clautribution/prompt-{session_id}.json`.
@@ -8,6 +9,12 @@ pub struct PromptMetadata {
     pub session_id: String,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub uuid: Option<String>,
+    /// When the prompt was submitted, as an RFC 3339 UTC timestamp.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub submitted_at: Option<String>,
+    /// The prompt starts with a slash command (e.g. `/review`).
+    #[serde(default)]
+    pub is_slash_command: bool,
 }
 
 /// Breadcrumb left after a nonproductive stop so the next productive stop
@@ -37,8 +44,12 @@ pub struct PlanSnapshot {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlanContext {
     pub original_prompt: String,
-    #[serde(default, skip_serializing_if = "Vec::is_empty")]
-    pub qa: Vec<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "deserialize_qa"
+    )]
+    pub qa: Vec<QaPair>,
     /// Session ID of the planning session whose Stop hook never fired
     /// (e.g. ExitPlanMode approval).  The JSONL transcript for that session
     /// is still on disk; we re-read it at commit time rather than copying
@@ -46,3 +57,36 @@ pub struct PlanContext {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub planning_session_id: Option<String>,
 }
+
+/// Accept the `"question"="answer"` strings older releases stored in
+/// `qa` alongside structured pairs, so a plan context written before an
+/// upgrade still loads.  A legacy string becomes an answer with no question.
+fn deserialize_qa<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<QaPair>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StoredQa {
+        Pair(QaPair),
+        Legacy(String),
+    }
+    let stored = Vec::<StoredQa>::deserialize(deserializer)?;
+    Ok(stored
+        .into_iter()
+        .map(|qa| match qa {
+            StoredQa::Pair(pair) => pair,
+            StoredQa::Legacy(answer) => QaPair { question: String::new(), answer },
+        })
+        .collect())
+}
+
+/// When this session last created a commit, for `min_commit_interval_secs`
+/// debouncing.  Stored as `.clautribution/last-commit-{session_id}.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LastCommit {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub commit: String,
+    /// A later productive stop was deferred, so the working tree holds
+    /// Claude's uncommitted changes (not manual edits).
+    #[serde(default)]
+    pub deferred: bool,
+}
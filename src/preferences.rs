@@ -1,12 +1,22 @@
-use crate::transcript::Verbosity;
+use crate::decision::{BodySection, HeadingStyle, NonproductiveHint, PromptBody, SubjectOptions};
+use crate::transcript::{
+    CategoryNouns, Noun, SummaryOptions, SummaryStyle, TailStrategy, Verbosity, DEFAULT_MAX_WALK_DEPTH,
+};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 const FILENAME: &str = "clautribution.toml";
 
+/// Contents of a new per-repo file when a global file supplies defaults.
+const INHERITING_STUB: &str = "# Per-repo overrides of the global clautribution.toml.\n";
+
+/// Environment variable holding a JSON object of preference overrides.
+const ENV_VAR: &str = "CLAUTRIBUTION_PREFERENCES";
+
 const DEFAULT_WARN_BRANCHES: &[&str] = &[
     "main", "master", "develop", "dev", "staging", "production", "prod", "release", "trunk",
 ];
@@ -14,6 +24,11 @@ const DEFAULT_WARN_BRANCHES: &[&str] = &[
 /// Commit message template: either an inline Jinja2 string or a path to a
 /// template file (relative to `.clautribution/`).
 ///
+/// Templates see `prompt` (the user's prompt), `files` (sorted basenames
+/// of files edited or written during the turn), and `commit_type` (a
+/// conventional-commit type inferred from that activity: "feat", "fix",
+/// "test", "docs", or "chore").
+///
 /// In TOML this looks like one of:
 ///
 /// ```toml
@@ -40,6 +55,33 @@ impl Default for CommitTemplate {
     }
 }
 
+/// Storage format of the `refs/notes/transcript` note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptNoteFormat {
+    /// Raw JSONL entries as a JSON array (machine-readable).
+    Json,
+    /// Readable markdown rendered by `Transcript::to_markdown`.
+    Markdown,
+}
+
+/// Which hook event commits a session's work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitTrigger {
+    /// Each productive Stop commits its turn.
+    Stop,
+    /// Stops only accumulate; SessionEnd makes one commit for the session.
+    SessionEnd,
+}
+
+/// How a productive turn's changes are split into commits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitCommitsBy {
+    /// One commit per turn.
+    None,
+    /// One commit per top-level directory (root-level files share one).
+    Directory,
+}
+
 /// User-facing preferences stored in `.clautribution/clautribution.toml`.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Preferences {
@@ -55,56 +97,619 @@ pub struct Preferences {
     /// Branches that trigger a warning when clautribution is active.
     #[serde(default = "default_warn_branches")]
     pub warn_branches: Vec<String>,
+
+    /// Whether tool calls made by agents (rather than on the user's behalf)
+    /// count toward the turn summary.
+    #[serde(default = "default_true")]
+    pub count_agent_tool_calls: bool,
+
+    /// Whether files whose edits cancel out within a turn are left out of
+    /// the summary.  Otherwise they are marked "(reverted)" at full
+    /// verbosity.
+    #[serde(default)]
+    pub exclude_reverted_edits: bool,
+
+    /// Whether files edited outside the repo (which won't be committed) are
+    /// left out of the summary.  Otherwise they are marked "(external)".
+    #[serde(default)]
+    pub exclude_external_edits: bool,
+
+    /// Layout of the tool summary.
+    /// Options: "prose", "checklist" (a GitHub task list, one item per line)
+    #[serde(default = "default_summary_style")]
+    pub summary_style: String,
+
+    /// Whether prompts and assistant text follow the tool summary.  Turn
+    /// off for tool-only summaries.
+    #[serde(default = "default_true")]
+    pub include_messages: bool,
+
+    /// How to pick the transcript tail when a reset left several branches.
+    /// Options: "last" (last entry in the file), "deepest" (leaf with the
+    /// longest ancestor chain)
+    #[serde(default = "default_tail_strategy")]
+    pub tail_strategy: String,
+
+    /// Whether binary files are left out of the `refs/notes/diffstat` note
+    /// instead of being listed as `(binary)`.
+    #[serde(default)]
+    pub exclude_binary_files: bool,
+
+    /// Format of the `refs/notes/transcript` note.
+    /// Options: "json" (raw entries, for tooling), "markdown" (readable in
+    /// `git log --notes=transcript`)
+    #[serde(default = "default_transcript_note_format")]
+    pub transcript_note_format: String,
+
+    /// Nouns for Short-verbosity tool counts, keyed by category
+    /// ("edited", "wrote", "read", "ran", "searched", "fetched",
+    /// "delegated", "asked", "other").  Unlisted categories use English defaults.
+    ///
+    /// ```toml
+    /// [category_nouns]
+    /// ran = { singular = "script", plural = "scripts" }
+    /// ```
+    #[serde(default)]
+    pub category_nouns: BTreeMap<String, Noun>,
+
+    /// Per-category summary verbosity overriding `summary_verbosity`, keyed
+    /// by the same categories as `category_nouns`, plus "thinking" for the
+    /// `tag_deep_thinking` tag.
+    ///
+    /// ```toml
+    /// [category_verbosity]
+    /// edited = "full"
+    /// read = "short"
+    /// ```
+    #[serde(default)]
+    pub category_verbosity: BTreeMap<String, String>,
+
+    /// Icons placed before a category's name in the tool summary, keyed by
+    /// the same categories as `category_nouns`.  Empty by default.
+    ///
+    /// ```toml
+    /// [category_icons]
+    /// edited = "✏️"
+    /// ran = "▶️"
+    /// ```
+    #[serde(default)]
+    pub category_icons: BTreeMap<String, String>,
+
+    /// Whether to quote the full prompt in the commit body after the subject.
+    /// Skipped when a long prompt was moved to `refs/notes/prompt-full`
+    /// unless `force_prompt_body` is also set.
+    #[serde(default)]
+    pub include_prompt_body: bool,
+
+    /// Quote the prompt body even when it was moved to a note.
+    #[serde(default)]
+    pub force_prompt_body: bool,
+
+    /// Minimum seconds between commits in a session.  A productive stop
+    /// arriving sooner is recorded as nonproductive so its changes fold into
+    /// the next commit.  0 disables the limit.
+    #[serde(default)]
+    pub min_commit_interval_secs: u64,
+
+    /// When commits are made.
+    /// Options: "stop" (after every productive turn), "session_end" (once,
+    /// covering everything since the last commit, when the session ends)
+    #[serde(default = "default_commit_trigger")]
+    pub commit_trigger: String,
+
+    /// When a non-committing stop shows its "nonproductive turn recorded"
+    /// hint.  Options: "always", "on_change" (only alongside a reset or
+    /// plan snapshot hint), "never"
+    #[serde(default = "default_nonproductive_hint")]
+    pub nonproductive_hint: String,
+
+    /// Whether the turn summary covers only the current session's entries
+    /// when an earlier session was resumed into the same transcript.
+    #[serde(default)]
+    pub summary_current_session_only: bool,
+
+    /// Experimental: split a turn's changes into several commits.
+    /// Options: "none", "directory" (one commit per top-level directory,
+    /// each subject prefixed with the directory)
+    #[serde(default = "default_split_commits_by")]
+    pub split_commits_by: String,
+
+    /// Whether files deleted from the working tree are deleted in the
+    /// commit.  When false, deletions stay pending for the user.
+    #[serde(default = "default_true")]
+    pub commit_deletions: bool,
+
+    /// Leave a turn's changes uncommitted when Claude ran `git add` or
+    /// `git commit` itself during it, instead of committing on top.
+    #[serde(default)]
+    pub defer_to_manual_git: bool,
+
+    /// Whether to flag turns whose conversation branched from an earlier
+    /// point (a rewind) with a "reset detected" hint.
+    #[serde(default = "default_true")]
+    pub detect_resets: bool,
+
+    /// Text prepended to every commit subject (e.g. "[AI] ").
+    #[serde(default)]
+    pub subject_prefix: String,
+
+    /// Text appended to every commit subject (e.g. " (PROJ-123)").
+    #[serde(default)]
+    pub subject_suffix: String,
+
+    /// Maximum commit subject length in chars, including prefix and
+    /// suffix.  Longer subjects are truncated with "...".  0 disables.
+    #[serde(default)]
+    pub subject_max_len: usize,
+
+    /// Whether the subject ends with the commit's line counts, e.g.
+    /// "fix parser (+12 -3)".  Added after `subject_max_len` truncation.
+    #[serde(default)]
+    pub subject_diffstat: bool,
+
+    /// Commit body sections after the subject, in order.  Sections left out
+    /// are omitted; empty ones are skipped.  Options: "qa", "plan", "summary"
+    #[serde(default = "default_body_sections")]
+    pub body_sections: Vec<String>,
+
+    /// How body section headings are written.  Options: "markdown"
+    /// (`## Q&A`), "plain" (`Q&A:`, for viewers that don't render markdown)
+    #[serde(default = "default_section_heading_style")]
+    pub section_heading_style: String,
+
+    /// Ref namespace for commit notes.  Set to e.g. "refs/clautribution" to
+    /// keep notes out of CI fetches of `refs/notes/*`.
+    #[serde(default = "default_notes_namespace")]
+    pub notes_namespace: String,
+
+    /// Whether subagent sidechain entries are kept in the transcript note.
+    /// Turn off to keep verbose Task output out of notes.
+    #[serde(default = "default_true")]
+    pub include_sidechains_in_note: bool,
+
+    /// Cap on transcript note entries.  Longer spans keep the most recent
+    /// entries behind an "elided" marker entry.  0 means no limit.
+    #[serde(default)]
+    pub max_transcript_note_entries: usize,
+
+    /// Cap on the entries one walk up the transcript's parent chain may
+    /// visit, so a pathological transcript can't hang the hook.
+    #[serde(default = "default_max_walk_depth")]
+    pub max_walk_depth: usize,
+
+    /// Whether a slash-command prompt (`/test`, `/review`) is passed over
+    /// for the commit subject in favor of the latest real prompt.
+    #[serde(default = "default_true")]
+    pub ignore_slash_command_prompts: bool,
+
+    /// Whether status hints ("[clautribution] committed changes, ...") are
+    /// marked `suppressOutput` to keep them out of verbose output.
+    #[serde(default)]
+    pub suppress_hints: bool,
+
+    /// Whether commit messages end with a `Claude-Session: <id>` trailer.
+    #[serde(default)]
+    pub include_session_trailer: bool,
+
+    /// Commit to this branch (e.g. "claude/work") instead of the current
+    /// one.  Commits are parented on the branch tip (created from HEAD if
+    /// missing); HEAD and the index are left untouched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit_branch: Option<String>,
+
+    /// Secret for lightweight provenance without GPG.  When set, commits
+    /// end with a `Claude-Attestation` trailer: an HMAC-SHA256 of the
+    /// message and transcript tail, checked by `clautribution verify`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance_hmac_key: Option<String>,
+
+    /// Whether a productive stop amends the previous commit instead of
+    /// adding one, while that commit is clautribution's own from the same
+    /// session — growing a single commit until someone else commits.
+    #[serde(default)]
+    pub amend_consecutive: bool,
+
+    /// Whether commit messages end with a `Generated-by: clautribution
+    /// <version>` trailer, for provenance.
+    #[serde(default)]
+    pub version_trailer: bool,
+
+    /// Whether commit messages list new files under an `Added:` footer,
+    /// separate from a `Modified:` footer for edited files.
+    #[serde(default)]
+    pub separate_added_files: bool,
+
+    /// Whether Medium and Full summaries of a turn that used high extended
+    /// thinking start with a `(deep thinking)` tag.  The "thinking" entry of
+    /// `category_verbosity` overrides `summary_verbosity` for this check.
+    #[serde(default)]
+    pub tag_deep_thinking: bool,
 }
 
 fn default_summary_verbosity() -> String {
     "medium".into()
 }
 
+fn default_summary_style() -> String {
+    "prose".to_string()
+}
+
 fn default_warn_branches() -> Vec<String> {
     DEFAULT_WARN_BRANCHES.iter().map(|s| s.to_string()).collect()
 }
 
+fn default_tail_strategy() -> String {
+    "last".into()
+}
+
+fn default_transcript_note_format() -> String {
+    "json".into()
+}
+
+fn default_notes_namespace() -> String {
+    "refs/notes".into()
+}
+
+fn default_body_sections() -> Vec<String> {
+    vec!["qa".into(), "plan".into(), "summary".into()]
+}
+
+fn default_max_walk_depth() -> usize {
+    DEFAULT_MAX_WALK_DEPTH
+}
+
+fn default_section_heading_style() -> String {
+    "markdown".into()
+}
+
+fn default_commit_trigger() -> String {
+    "stop".into()
+}
+
+fn default_nonproductive_hint() -> String {
+    "always".into()
+}
+
+fn default_split_commits_by() -> String {
+    "none".into()
+}
+
+fn parse_verbosity(s: &str) -> Verbosity {
+    match s {
+        "short" => Verbosity::Short,
+        "full" => Verbosity::Full,
+        _ => Verbosity::Medium,
+    }
+}
+
+/// Layer `over` onto `base`.  Tables present in both are merged key by
+/// key, so a repo's `[category_icons]` adds to the global one rather than
+/// replacing it; any other value in `over` replaces `base`'s.
+fn merge_tables(base: &mut toml::Table, over: toml::Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(over)) => merge_tables(base, over),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
 impl Default for Preferences {
     fn default() -> Self {
         Self {
             summary_verbosity: default_summary_verbosity(),
             commit_template: CommitTemplate::default(),
             warn_branches: default_warn_branches(),
+            count_agent_tool_calls: true,
+            exclude_reverted_edits: false,
+            exclude_external_edits: false,
+            summary_style: default_summary_style(),
+            include_messages: true,
+            tail_strategy: default_tail_strategy(),
+            exclude_binary_files: false,
+            transcript_note_format: default_transcript_note_format(),
+            category_nouns: BTreeMap::new(),
+            category_verbosity: BTreeMap::new(),
+            category_icons: BTreeMap::new(),
+            include_prompt_body: false,
+            force_prompt_body: false,
+            min_commit_interval_secs: 0,
+            commit_trigger: default_commit_trigger(),
+            nonproductive_hint: default_nonproductive_hint(),
+            summary_current_session_only: false,
+            split_commits_by: default_split_commits_by(),
+            commit_deletions: true,
+            defer_to_manual_git: false,
+            detect_resets: true,
+            subject_prefix: String::new(),
+            subject_suffix: String::new(),
+            subject_max_len: 0,
+            subject_diffstat: false,
+            body_sections: default_body_sections(),
+            section_heading_style: default_section_heading_style(),
+            notes_namespace: default_notes_namespace(),
+            include_sidechains_in_note: true,
+            max_transcript_note_entries: 0,
+            max_walk_depth: default_max_walk_depth(),
+            ignore_slash_command_prompts: true,
+            suppress_hints: false,
+            include_session_trailer: false,
+            commit_branch: None,
+            provenance_hmac_key: None,
+            amend_consecutive: false,
+            version_trailer: false,
+            separate_added_files: false,
+            tag_deep_thinking: false,
         }
     }
 }
 
 impl Preferences {
-    /// Load preferences from `.clautribution/clautribution.toml`.
+    /// Load preferences from `.clautribution/clautribution.toml`, layered
+    /// over the global `<config dir>/clautribution/clautribution.toml`
+    /// (e.g. `~/.config` on Linux) when that exists.
     ///
-    /// If the file doesn't exist it is created with defaults. Missing keys
-    /// in an existing file are filled in with defaults via serde.
-    pub fn load(dir: &Path) -> Result<Self> {
+    /// If the repo file doesn't exist it is created: with defaults, or as an
+    /// empty override file when a global file is present.  Keys missing from
+    /// both files are filled in with defaults via serde.  If
+    /// `CLAUTRIBUTION_PREFERENCES` is set, its JSON object is merged over
+    /// the file's values (without being written back to the file).
+    ///
+    /// An explicit `config` file is read in place of the repo's file; it
+    /// must exist and is never written.
+    pub fn load(dir: &Path, config: Option<&Path>) -> Result<Self> {
+        let prefs = match config {
+            Some(path) => Self::load_explicit(path)?,
+            None => Self::load_file(dir)?,
+        };
+        match std::env::var(ENV_VAR) {
+            Ok(json) => prefs
+                .merge_json(&json)
+                .with_context(|| format!("applying ${ENV_VAR}")),
+            Err(_) => Ok(prefs),
+        }
+    }
+
+    fn load_file(dir: &Path) -> Result<Self> {
         let path = dir.join(FILENAME);
-        match fs::read_to_string(&path) {
-            Ok(contents) => {
-                let prefs: Preferences = toml::from_str(&contents)
-                    .with_context(|| format!("parsing {}", path.display()))?;
-                Ok(prefs)
-            }
-            Err(e) if e.kind() == io::ErrorKind::NotFound => {
-                let prefs = Preferences::default();
-                let toml_str = toml::to_string_pretty(&prefs)
-                    .context("serializing default preferences")?;
-                fs::write(&path, &toml_str)
+        let global = match Self::global_path() {
+            Some(global_path) => Self::read_table(&global_path)?,
+            None => None,
+        };
+        let local = match Self::read_table(&path)? {
+            Some(table) => table,
+            None => {
+                let contents = if global.is_some() {
+                    INHERITING_STUB.to_string()
+                } else {
+                    toml::to_string_pretty(&Preferences::default())
+                        .context("serializing default preferences")?
+                };
+                fs::write(&path, &contents)
                     .with_context(|| format!("writing default {}", path.display()))?;
-                Ok(prefs)
+                toml::Table::new()
             }
+        };
+        let mut merged = global.unwrap_or_default();
+        merge_tables(&mut merged, local);
+        toml::Value::Table(merged)
+            .try_into()
+            .with_context(|| format!("parsing {}", path.display()))
+    }
+
+    fn load_explicit(path: &Path) -> Result<Self> {
+        let local = Self::read_table(path)?
+            .with_context(|| format!("config file {} not found", path.display()))?;
+        Self::over_global(local)?
+            .try_into()
+            .with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// `local` layered over the global file's table, if there is one.
+    fn over_global(local: toml::Table) -> Result<toml::Value> {
+        let mut merged = match Self::global_path() {
+            Some(global_path) => Self::read_table(&global_path)?.unwrap_or_default(),
+            None => toml::Table::new(),
+        };
+        merge_tables(&mut merged, local);
+        Ok(toml::Value::Table(merged))
+    }
+
+    /// Every preference key, in declaration order.  Kept by hand; a test
+    /// checks it against the serialized fields.
+    pub fn keys() -> &'static [&'static str] {
+        &[
+            "summary_verbosity", "commit_template", "warn_branches", "count_agent_tool_calls",
+            "exclude_reverted_edits", "exclude_external_edits", "summary_style", "include_messages",
+            "tail_strategy", "exclude_binary_files", "transcript_note_format", "category_nouns",
+            "category_verbosity", "category_icons", "include_prompt_body", "force_prompt_body",
+            "min_commit_interval_secs", "commit_trigger", "nonproductive_hint",
+            "summary_current_session_only", "split_commits_by", "commit_deletions",
+            "defer_to_manual_git", "detect_resets", "subject_prefix", "subject_suffix",
+            "subject_max_len", "subject_diffstat", "body_sections", "section_heading_style",
+            "notes_namespace", "include_sidechains_in_note", "max_transcript_note_entries",
+            "max_walk_depth", "ignore_slash_command_prompts", "suppress_hints",
+            "include_session_trailer", "commit_branch", "provenance_hmac_key", "amend_consecutive",
+            "version_trailer", "separate_added_files", "tag_deep_thinking",
+        ]
+    }
+
+    /// The value of preference `key`, as it would appear in TOML (strings
+    /// unquoted).  `None` for an optional preference that isn't set.
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        anyhow::ensure!(Self::keys().contains(&key), "unknown preference {key}");
+        let table = toml::Table::try_from(self).context("serializing preferences")?;
+        Ok(table.get(key).map(|value| match value {
+            toml::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }))
+    }
+
+    /// Set preference `key` to `value` in the repo's
+    /// `.clautribution/clautribution.toml`.  `value` is taken as a string
+    /// for string-valued keys and as a TOML literal (`true`, `80`,
+    /// `["qa", "summary"]`) otherwise; the result must still parse.
+    pub fn set(dir: &Path, key: &str, value: &str) -> Result<()> {
+        anyhow::ensure!(Self::keys().contains(&key), "unknown preference {key}");
+        let defaults = toml::Table::try_from(Preferences::default())
+            .context("serializing default preferences")?;
+        let parsed = match defaults.get(key) {
+            Some(toml::Value::String(_)) => toml::Value::String(value.to_string()),
+            _ => toml::from_str::<toml::Table>(&format!("value = {value}"))
+                .ok()
+                .and_then(|mut t| t.remove("value"))
+                .unwrap_or_else(|| toml::Value::String(value.to_string())),
+        };
+        let path = dir.join(FILENAME);
+        let mut local = Self::read_table(&path)?.unwrap_or_default();
+        local.insert(key.to_string(), parsed);
+        Self::over_global(local.clone())?
+            .try_into::<Preferences>()
+            .with_context(|| format!("invalid value for {key}: {value}"))?;
+        let contents = toml::to_string_pretty(&local).context("serializing preferences")?;
+        fs::write(&path, contents).with_context(|| format!("writing {}", path.display()))
+    }
+
+    /// The global preferences file in the platform config directory.
+    fn global_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("clautribution").join(FILENAME))
+    }
+
+    /// Read a TOML file as a table; `None` if it doesn't exist.
+    fn read_table(path: &Path) -> Result<Option<toml::Table>> {
+        match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map(Some)
+                .with_context(|| format!("parsing {}", path.display())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
             Err(e) => Err(e).with_context(|| format!("reading {}", path.display())),
         }
     }
 
+    /// Override top-level fields with those present in a JSON object.
+    /// Fields absent from the object keep their current values.
+    pub fn merge_json(self, json: &str) -> Result<Self> {
+        let overrides: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(json).context("parsing preference overrides")?;
+        let mut merged = match serde_json::to_value(self).context("serializing preferences")? {
+            serde_json::Value::Object(map) => map,
+            _ => unreachable!("Preferences serializes to an object"),
+        };
+        merged.extend(overrides);
+        serde_json::from_value(serde_json::Value::Object(merged))
+            .context("parsing preference overrides")
+    }
+
     pub fn summary_verbosity(&self) -> Verbosity {
-        match self.summary_verbosity.as_str() {
-            "short" => Verbosity::Short,
-            "full" => Verbosity::Full,
-            _ => Verbosity::Medium,
+        parse_verbosity(&self.summary_verbosity)
+    }
+
+    pub fn tail_strategy(&self) -> TailStrategy {
+        match self.tail_strategy.as_str() {
+            "deepest" => TailStrategy::Deepest,
+            _ => TailStrategy::Last,
+        }
+    }
+
+    pub fn transcript_note_format(&self) -> TranscriptNoteFormat {
+        match self.transcript_note_format.as_str() {
+            "markdown" => TranscriptNoteFormat::Markdown,
+            _ => TranscriptNoteFormat::Json,
+        }
+    }
+
+    pub fn commit_trigger(&self) -> CommitTrigger {
+        match self.commit_trigger.as_str() {
+            "session_end" => CommitTrigger::SessionEnd,
+            _ => CommitTrigger::Stop,
+        }
+    }
+
+    pub fn nonproductive_hint(&self) -> NonproductiveHint {
+        match self.nonproductive_hint.as_str() {
+            "on_change" => NonproductiveHint::OnChange,
+            "never" => NonproductiveHint::Never,
+            _ => NonproductiveHint::Always,
+        }
+    }
+
+    pub fn split_commits_by(&self) -> SplitCommitsBy {
+        match self.split_commits_by.as_str() {
+            "directory" => SplitCommitsBy::Directory,
+            _ => SplitCommitsBy::None,
+        }
+    }
+
+    pub fn prompt_body(&self) -> PromptBody {
+        match (self.include_prompt_body, self.force_prompt_body) {
+            (_, true) => PromptBody::Force,
+            (true, false) => PromptBody::Include,
+            (false, false) => PromptBody::Omit,
+        }
+    }
+
+    /// `notes_namespace` without a trailing slash.
+    pub fn notes_namespace(&self) -> &str {
+        self.notes_namespace.trim_end_matches('/')
+    }
+
+    /// Configured body sections; unrecognized names are ignored.
+    pub fn body_sections(&self) -> Vec<BodySection> {
+        self.body_sections
+            .iter()
+            .filter_map(|s| match s.as_str() {
+                "qa" => Some(BodySection::Qa),
+                "plan" => Some(BodySection::Plan),
+                "summary" => Some(BodySection::Summary),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn section_heading_style(&self) -> HeadingStyle {
+        match self.section_heading_style.as_str() {
+            "plain" => HeadingStyle::Plain,
+            _ => HeadingStyle::Markdown,
+        }
+    }
+
+    pub fn subject_options(&self) -> SubjectOptions {
+        SubjectOptions {
+            prefix: self.subject_prefix.clone(),
+            suffix: self.subject_suffix.clone(),
+            max_len: self.subject_max_len,
+        }
+    }
+
+    pub fn summary_options(&self) -> SummaryOptions {
+        SummaryOptions {
+            verbosity: self.summary_verbosity(),
+            count_agent_tool_calls: self.count_agent_tool_calls,
+            exclude_reverted: self.exclude_reverted_edits,
+            include_messages: self.include_messages,
+            tag_deep_thinking: self.tag_deep_thinking,
+            workdir: None,
+            exclude_external: self.exclude_external_edits,
+            category_icons: self.category_icons.clone(),
+            style: match self.summary_style.as_str() {
+                "checklist" => SummaryStyle::Checklist,
+                _ => SummaryStyle::Prose,
+            },
+            nouns: CategoryNouns::with_overrides(self.category_nouns.clone()),
+            category_verbosity: self
+                .category_verbosity
+                .iter()
+                .map(|(cat, v)| (cat.clone(), parse_verbosity(v)))
+                .collect(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests;
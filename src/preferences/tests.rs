@@ -0,0 +1,18 @@
+use super::*;
+use std::collections::BTreeSet;
+
+#[test]
+fn keys_match_serialized_fields() {
+    // Optional preferences are left out when unset; set them all so every
+    // field shows up.
+    let prefs = Preferences {
+        commit_branch: Some("claude/work".into()),
+        provenance_hmac_key: Some("secret".into()),
+        ..Preferences::default()
+    };
+    let table = toml::Table::try_from(&prefs).unwrap();
+    let serialized: BTreeSet<&str> = table.keys().map(String::as_str).collect();
+    let listed: BTreeSet<&str> = Preferences::keys().iter().copied().collect();
+    assert_eq!(listed, serialized);
+    assert_eq!(listed.len(), Preferences::keys().len(), "duplicate keys");
+}
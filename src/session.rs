@@ -1,14 +1,21 @@
 use anyhow::{Context, Result};
-use crate::decision::{decide_stop, StopContext, StopDecision};
-use crate::metadata::{ContinuationBreadcrumb, PlanContext, PlanSnapshot, PromptMetadata};
-use crate::preferences::{CommitTemplate, Preferences};
-use crate::transcript::{Transcript, Verbosity};
+use crate::attestation;
+use crate::log;
+use crate::decision::{
+    decide_stop, scope_message, BodySection, FileChanges, HeadingStyle, NonproductiveHint, PromptBody, StopContext, StopDecision, SubjectOptions,
+};
+use crate::metadata::{
+    ContinuationBreadcrumb, LastCommit, PlanContext, PlanSnapshot, PromptMetadata,
+};
+use crate::preferences::{CommitTemplate, CommitTrigger, Preferences, SplitCommitsBy, TranscriptNoteFormat};
+use crate::transcript::{SummaryOptions, TailStrategy, Transcript};
 use serde::de::DeserializeOwned;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use crate::types::{
-    HookOutput, SessionEndInput, SessionStartInput, SessionStartSource, StopInput,
+    ContextEvent, HookOutput, PermissionMode, SessionEndInput, SessionStartInput, SessionStartSource, StopInput,
     UserPromptSubmitInput,
 };
 
@@ -26,6 +33,69 @@ fn read_json_file<T: DeserializeOwned>(path: &Path) -> Result<Option<T>> {
     }
 }
 
+/// Seconds since the Unix epoch.
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Format seconds since the Unix epoch as an RFC 3339 UTC timestamp
+/// ("2025-01-01T00:00:00Z").
+fn rfc3339(secs: u64) -> String {
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // Civil-from-days (Howard Hinnant's algorithm), shifted so the era
+    // starts on 0000-03-01.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+/// Whether an error chain comes from git finding a lock file (e.g.
+/// `index.lock`) held by another process.
+fn is_lock_contention(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .downcast_ref::<git2::Error>()
+            .is_some_and(|e| e.code() == git2::ErrorCode::Locked)
+    })
+}
+
+/// Run a git write, backing off and retrying while another git process
+/// (an editor, a prompt's `git status`) briefly holds a lock such as
+/// `index.lock`.
+fn retry_while_locked<T>(mut attempt_once: impl FnMut() -> Result<T>) -> Result<T> {
+    const ATTEMPTS: u32 = 3;
+    for attempt in 1..=ATTEMPTS {
+        match attempt_once() {
+            Err(err) if is_lock_contention(&err) => {
+                if attempt == ATTEMPTS {
+                    return Err(err).context(format!(
+                        "git index is locked by another process after {ATTEMPTS} attempts \
+                         (remove .git/index.lock if no git process is running)"
+                    ));
+                }
+                std::thread::sleep(std::time::Duration::from_millis(100 * u64::from(attempt)));
+            }
+            result => return result,
+        }
+    }
+    unreachable!("the last attempt always returns")
+}
+
 /// Remove a file, ignoring "not found" errors.
 fn remove_if_exists(path: &Path) -> Result<()> {
     match fs::remove_file(path) {
@@ -35,13 +105,6 @@ fn remove_if_exists(path: &Path) -> Result<()> {
     }
 }
 
-fn hint(message: String) -> Option<HookOutput> {
-    Some(HookOutput {
-        system_message: Some(message),
-        ..Default::default()
-    })
-}
-
 /// Detect whether a UserPromptSubmit prompt is a `/preview` skill invocation.
 fn is_preview_command(prompt: &str) -> bool {
     let p = prompt.trim();
@@ -54,15 +117,75 @@ fn is_drop_command(prompt: &str) -> bool {
     p == "/drop" || p == "/clautribution:drop"
 }
 
+/// Whether a repo-relative path is inside `.clautribution/` and therefore
+/// never committed.
+fn is_metadata_path(path: &Path) -> bool {
+    path.starts_with(".clautribution")
+}
+
+/// The repo-relative path a diff delta touches: the new side, or the old
+/// side for a deletion.
+fn delta_path<'a>(delta: &git2::DiffDelta<'a>) -> Option<&'a Path> {
+    delta.new_file().path().or_else(|| delta.old_file().path())
+}
+
+/// First component of a repo-relative path, or `""` for a root-level file.
+fn top_level_dir(path: &Path) -> String {
+    let mut components = path.components();
+    match (components.next(), components.next()) {
+        (Some(first), Some(_)) => first.as_os_str().to_string_lossy().into_owned(),
+        _ => String::new(),
+    }
+}
+
+/// Rotated continuations of the transcript at `path`: for `<id>.jsonl`,
+/// the existing `<id>.1.jsonl`, `<id>.2.jsonl`, ... in order, stopping at
+/// the first missing number.
+fn rotated_transcript_parts(path: &Path) -> Vec<PathBuf> {
+    let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+        return Vec::new();
+    };
+    (1..)
+        .map(|n| path.with_file_name(format!("{stem}.{n}.jsonl")))
+        .take_while(|part| part.exists())
+        .collect()
+}
+
+/// Whether a transcript file stem names a rotated part (`<id>.1`) rather
+/// than a session.
+fn is_rotated_part(stem: &str) -> bool {
+    stem.rsplit_once('.')
+        .is_some_and(|(_, n)| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Read and parse the transcript at `path`, followed by any rotated parts
+/// (see `rotated_transcript_parts`), so ancestor walks cross rotation
+/// boundaries.  A missing file is an empty transcript.
 pub fn read_transcript(path: &str) -> Result<Transcript> {
-    let contents = match fs::read_to_string(path) {
-        Ok(c) => c,
+    let mut bytes = match fs::read(path) {
+        Ok(b) => b,
         Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Transcript::empty()),
         Err(e) => return Err(e).with_context(|| format!("reading transcript {path}")),
     };
+    for part in rotated_transcript_parts(Path::new(path)) {
+        let more = fs::read(&part)
+            .with_context(|| format!("reading rotated transcript {}", part.display()))?;
+        if !bytes.is_empty() && !bytes.ends_with(b"\n") {
+            bytes.push(b'\n');
+        }
+        bytes.extend(more);
+    }
+    // Decode lossily so one bad byte costs a character, not the transcript.
+    let contents = String::from_utf8_lossy(&bytes);
+    if let std::borrow::Cow::Owned(_) = contents {
+        log::warning!("transcript {path} contains invalid UTF-8; replaced with U+FFFD");
+    }
     let (transcript, errors) = Transcript::parse(&contents);
-    for (line, err) in &errors {
-        eprintln!("clautribution: transcript parse error at line {line}: {err}");
+    if let Some(header) = transcript.skipped_header() {
+        log::warning!("skipped non-JSON header line in transcript {path}: {header}");
+    }
+    for (line, err, snippet) in &errors {
+        log::warning!("transcript parse error at line {line}: {err} (in: {snippet})");
     }
     Ok(transcript)
 }
@@ -75,16 +198,73 @@ pub struct OwnedStopContext {
     pub file_metadata: Option<PromptMetadata>,
     pub pending_plan: Option<String>,
     pub plan_context: Option<PlanContext>,
-    pub plan_entries: Vec<serde_json::Value>,
+    pub plan_entries: Vec<Arc<serde_json::Value>>,
     pub session_id: String,
     pub breadcrumb: Option<ContinuationBreadcrumb>,
+    pub detect_resets: bool,
     pub committed_tail: Option<String>,
     pub has_uncommitted_changes: bool,
     pub commit_template: String,
-    pub verbosity: Verbosity,
+    pub summary: SummaryOptions,
+    pub tail_strategy: TailStrategy,
+    pub prompt_body: PromptBody,
+    pub summary_current_session_only: bool,
+    pub subject: SubjectOptions,
+    pub body_sections: Vec<BodySection>,
+    pub section_heading_style: HeadingStyle,
+    pub include_sidechains_in_note: bool,
+    pub permission_mode: Option<PermissionMode>,
+    pub defer_to_manual_git: bool,
+    pub max_transcript_note_entries: usize,
+    pub ignore_slash_command_prompts: bool,
+    pub include_session_trailer: bool,
+    pub version_trailer: bool,
+    pub file_changes: Option<FileChanges>,
+    pub nonproductive_hint: NonproductiveHint,
 }
 
 impl OwnedStopContext {
+    /// A context built from a transcript alone, with default preferences
+    /// and no repo state: no prompt metadata, plan files, or committed
+    /// tail.  Used by the `decide` subcommand.
+    pub fn from_transcript(transcript: Transcript) -> Self {
+        let prefs = Preferences::default();
+        let commit_template = match &prefs.commit_template {
+            CommitTemplate::Inline(s) => s.clone(),
+            CommitTemplate::File(_) => unreachable!("the default template is inline"),
+        };
+        let session_id = transcript.session_id().unwrap_or_default().to_string();
+        Self {
+            transcript,
+            file_metadata: None,
+            pending_plan: None,
+            plan_context: None,
+            plan_entries: Vec::new(),
+            session_id,
+            breadcrumb: None,
+            detect_resets: prefs.detect_resets,
+            committed_tail: None,
+            has_uncommitted_changes: false,
+            commit_template,
+            summary: prefs.summary_options(),
+            tail_strategy: prefs.tail_strategy(),
+            prompt_body: prefs.prompt_body(),
+            summary_current_session_only: prefs.summary_current_session_only,
+            subject: prefs.subject_options(),
+            body_sections: prefs.body_sections(),
+            section_heading_style: prefs.section_heading_style(),
+            include_sidechains_in_note: prefs.include_sidechains_in_note,
+            permission_mode: None,
+            defer_to_manual_git: prefs.defer_to_manual_git,
+            max_transcript_note_entries: prefs.max_transcript_note_entries,
+            ignore_slash_command_prompts: prefs.ignore_slash_command_prompts,
+            include_session_trailer: prefs.include_session_trailer,
+            version_trailer: prefs.version_trailer,
+            file_changes: None,
+            nonproductive_hint: prefs.nonproductive_hint(),
+        }
+    }
+
     /// Produce a borrowed `StopContext` referencing this struct's data.
     pub fn as_ref(&self) -> StopContext<'_> {
         StopContext {
@@ -95,10 +275,26 @@ impl OwnedStopContext {
             plan_entries: self.plan_entries.clone(),
             session_id: &self.session_id,
             breadcrumb: self.breadcrumb.clone(),
+            detect_resets: self.detect_resets,
             committed_tail: self.committed_tail.clone(),
             has_uncommitted_changes: self.has_uncommitted_changes,
             commit_template: &self.commit_template,
-            verbosity: self.verbosity,
+            summary: self.summary.clone(),
+            tail_strategy: self.tail_strategy,
+            prompt_body: self.prompt_body,
+            summary_current_session_only: self.summary_current_session_only,
+            subject: self.subject.clone(),
+            body_sections: self.body_sections.clone(),
+            section_heading_style: self.section_heading_style,
+            include_sidechains_in_note: self.include_sidechains_in_note,
+            permission_mode: self.permission_mode.clone(),
+            defer_to_manual_git: self.defer_to_manual_git,
+            max_transcript_note_entries: self.max_transcript_note_entries,
+            ignore_slash_command_prompts: self.ignore_slash_command_prompts,
+            include_session_trailer: self.include_session_trailer,
+            version_trailer: self.version_trailer,
+            file_changes: self.file_changes.clone(),
+            nonproductive_hint: self.nonproductive_hint,
         }
     }
 }
@@ -114,6 +310,16 @@ impl Session {
     /// Open the git repo from `cwd`, ensure `.clautribution/` exists, load
     /// preferences, and return a `Session` ready for use.
     pub fn open(cwd: &str, session_id: &str) -> Result<Self> {
+        Self::open_impl(cwd, session_id, None)
+    }
+
+    /// Like `open`, loading preferences from `config` instead of
+    /// `.clautribution/clautribution.toml`.
+    pub fn open_with_config(cwd: &str, session_id: &str, config: &Path) -> Result<Self> {
+        Self::open_impl(cwd, session_id, Some(config))
+    }
+
+    fn open_impl(cwd: &str, session_id: &str, config: Option<&Path>) -> Result<Self> {
         let repo = git2::Repository::discover(cwd)
             .with_context(|| format!("finding git repo from {cwd}"))?;
         let workdir = repo
@@ -124,7 +330,7 @@ impl Session {
             fs::create_dir_all(&dir)
                 .with_context(|| format!("creating {}", dir.display()))?;
         }
-        let prefs = Preferences::load(&dir)?;
+        let prefs = Preferences::load(&dir, config)?;
         Ok(Self {
             repo,
             dir,
@@ -133,6 +339,16 @@ impl Session {
         })
     }
 
+    /// The repo's `.clautribution/` directory.
+    pub fn data_dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The preferences this session was opened with.
+    pub fn prefs(&self) -> &Preferences {
+        &self.prefs
+    }
+
     // ---------------------------------------------------------------
     // Private path helpers
     // ---------------------------------------------------------------
@@ -149,6 +365,14 @@ impl Session {
         self.dir.join(format!("drop-marker-{}.json", self.session_id))
     }
 
+    fn last_commit_path(&self) -> PathBuf {
+        self.dir.join(format!("last-commit-{}.json", self.session_id))
+    }
+
+    fn last_stop_tail_path(&self) -> PathBuf {
+        self.dir.join(format!("last-stop-tail-{}.txt", self.session_id))
+    }
+
     fn plan_history_path(&self) -> PathBuf {
         self.dir.join(format!("plan-history-{}.json", self.session_id))
     }
@@ -169,73 +393,542 @@ impl Session {
 
     /// Check whether the repo has any uncommitted or untracked changes,
     /// excluding `.clautribution/` (which is never staged by `commit_changes`).
+    /// With `commit_branch`, "uncommitted" means the working tree differs
+    /// from that branch's tip rather than from HEAD.
     fn has_uncommitted_changes(&self) -> Result<bool> {
+        if self.prefs.commit_branch.is_some() {
+            let diff = self.workdir_diff(self.commit_parent().as_ref())?;
+            return Ok(diff
+                .deltas()
+                .filter_map(|delta| delta_path(&delta))
+                .any(|path| self.stages(path, &|_| true)));
+        }
         let mut opts = git2::StatusOptions::new();
         opts.include_untracked(true).include_ignored(false);
         let statuses = self.repo.statuses(Some(&mut opts))
             .context("checking git status")?;
         let all_in_metadata = statuses.iter().all(|s| {
             s.path()
-                .is_some_and(|p| std::path::Path::new(p).starts_with(".clautribution"))
+                .is_some_and(|p| is_metadata_path(Path::new(p)))
         });
         Ok(!statuses.is_empty() && !all_in_metadata)
     }
 
+    /// Commit pending changes according to `split_commits_by` and return
+    /// the new commit OIDs in creation order.  With "directory", changes are
+    /// grouped by top-level directory and each group is committed with the
+    /// directory as a subject prefix (`src: <message>`) and only its own
+    /// paths in the file-change footers.  `tail` is the
+    /// transcript tail the commits record, for `provenance_hmac_key`.
+    fn commit_split(&self, message: &str, tail: &str) -> Result<Vec<git2::Oid>> {
+        if self.prefs.split_commits_by() == SplitCommitsBy::None {
+            return Ok(vec![self.commit_changes(message, tail)?]);
+        }
+        let groups = self.changed_top_level_dirs()?;
+        if groups.len() <= 1 {
+            return Ok(vec![self.commit_changes(message, tail)?]);
+        }
+        let changes = if self.prefs.separate_added_files {
+            Some(self.pending_file_changes()?)
+        } else {
+            None
+        };
+        let subject = self.prefs.subject_options();
+        let mut oids = Vec::new();
+        for dir in &groups {
+            let scope = if dir.is_empty() { "(root)" } else { dir.as_str() };
+            let scoped = scope_message(message, scope, &subject, changes.as_ref(), |path| {
+                top_level_dir(Path::new(path)) == *dir
+            });
+            oids.push(self.commit_paths(&scoped, tail, |path| top_level_dir(path) == *dir)?);
+        }
+        Ok(oids)
+    }
+
+    /// Committable changes split by git status into new (untracked or newly
+    /// staged) and modified files, each sorted.  Deletions and renames are
+    /// left out.
+    fn pending_file_changes(&self) -> Result<FileChanges> {
+        let diff = self.diff_against_tip(&mut git2::DiffOptions::new())?;
+        let mut changes = FileChanges::default();
+        for delta in diff.deltas() {
+            let Some(path) = delta.new_file().path() else { continue };
+            if is_metadata_path(path) {
+                continue;
+            }
+            let path = path.display().to_string();
+            match delta.status() {
+                git2::Delta::Added | git2::Delta::Untracked => changes.added.push(path),
+                git2::Delta::Modified => changes.modified.push(path),
+                _ => {}
+            }
+        }
+        changes.added.sort();
+        changes.modified.sort();
+        Ok(changes)
+    }
+
+    /// Distinct top-level directories (`""` for root-level files) of all
+    /// committable changes, sorted.
+    fn changed_top_level_dirs(&self) -> Result<std::collections::BTreeSet<String>> {
+        let diff = self.diff_against_tip(&mut git2::DiffOptions::new())?;
+        Ok(diff
+            .deltas()
+            .filter_map(|d| d.new_file().path().or_else(|| d.old_file().path()))
+            .filter(|p| !is_metadata_path(p))
+            .map(top_level_dir)
+            .collect())
+    }
+
     /// Stage all changes (including untracked files) except `.clautribution/`,
     /// commit, and return the new commit OID.
-    fn commit_changes(&self, message: &str) -> Result<git2::Oid> {
+    fn commit_changes(&self, message: &str, tail: &str) -> Result<git2::Oid> {
+        self.commit_paths(message, tail, |_| true)
+    }
+
+    /// Like `commit_changes`, staging only paths for which `include` holds.
+    fn commit_paths(
+        &self,
+        message: &str,
+        tail: &str,
+        include: impl Fn(&Path) -> bool,
+    ) -> Result<git2::Oid> {
+        retry_while_locked(|| self.try_commit_paths(message, tail, &include))
+    }
+
+    fn try_commit_paths(
+        &self,
+        message: &str,
+        tail: &str,
+        include: &impl Fn(&Path) -> bool,
+    ) -> Result<git2::Oid> {
+        self.refuse_conflicts()?;
+        if let Some(branch) = &self.prefs.commit_branch {
+            return self.commit_to_branch(branch, message, tail, include);
+        }
+        let tree_oid = self.stage_paths(include)?;
+        let tree = self.repo.find_tree(tree_oid).context("finding tree")?;
+        let sig = self.repo
+            .signature()
+            .context("reading git signature (user.name / user.email)")?;
+        let parent = self.repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let message = self.with_subject_diffstat(message, parent.as_ref(), &tree)?;
+        let message = self.attest(&message, tail);
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        let oid = self.repo
+            .commit(Some("HEAD"), &sig, &sig, &message, &tree, &parents)
+            .context("creating commit")?;
+        Ok(oid)
+    }
+
+    /// Replace the tip commit `oid` with one that also includes all pending
+    /// changes and carries `message`, keeping its parents.
+    fn amend_commit(&self, oid: git2::Oid, message: &str, tail: &str) -> Result<git2::Oid> {
+        retry_while_locked(|| {
+            self.refuse_conflicts()?;
+            let commit = self.repo.find_commit(oid).context("finding commit to amend")?;
+            let (tree_oid, update_ref) = match &self.prefs.commit_branch {
+                Some(branch) => (
+                    self.snapshot_tree(Some(&commit), &|_| true)?,
+                    format!("refs/heads/{branch}"),
+                ),
+                None => (self.stage_paths(&|_| true)?, "HEAD".to_string()),
+            };
+            let tree = self.repo.find_tree(tree_oid).context("finding tree")?;
+            let sig = self.repo
+                .signature()
+                .context("reading git signature (user.name / user.email)")?;
+            let message = self.with_subject_diffstat(message, commit.parent(0).ok().as_ref(), &tree)?;
+            let message = self.attest(&message, tail);
+            commit
+                .amend(Some(&update_ref), None, Some(&sig), None, Some(&message), Some(&tree))
+                .context("amending commit")
+        })
+    }
+
+    /// Fail when the index has unresolved merge conflicts: staging would
+    /// commit the conflict markers as resolved content.
+    fn refuse_conflicts(&self) -> Result<()> {
+        let index = self.repo.index().context("opening index")?;
+        if !index.has_conflicts() {
+            return Ok(());
+        }
+        let mut paths = std::collections::BTreeSet::new();
+        for conflict in index.conflicts().context("reading index conflicts")? {
+            let conflict = conflict.context("reading index conflict")?;
+            if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
+                paths.insert(String::from_utf8_lossy(&entry.path).into_owned());
+            }
+        }
+        let paths: Vec<String> = paths.into_iter().collect();
+        anyhow::bail!("refusing to commit: unresolved merge conflicts in {}", paths.join(", "))
+    }
+
+    /// Whether the staging callback takes `path`: outside `.clautribution/`,
+    /// accepted by `include`, and not a deletion unless `commit_deletions`.
+    fn stages(&self, path: &Path, include: &impl Fn(&Path) -> bool) -> bool {
+        if is_metadata_path(path) || !include(path) {
+            return false;
+        }
+        self.prefs.commit_deletions
+            || self
+                .repo
+                .workdir()
+                .is_some_and(|wd| wd.join(path).symlink_metadata().is_ok())
+    }
+
+    /// Stage working-tree paths (outside `.clautribution/`) for which
+    /// `include` holds into the repo index, write it, and return its tree.
+    fn stage_paths(&self, include: &impl Fn(&Path) -> bool) -> Result<git2::Oid> {
         let mut index = self.repo.index().context("opening index")?;
         index
             .add_all(
                 ["*"].iter(),
                 git2::IndexAddOption::DEFAULT,
                 Some(&mut |path: &std::path::Path, _matched: &[u8]| {
-                    if path.starts_with(".clautribution") {
-                        1 // skip
-                    } else {
+                    if self.stages(path, include) {
                         0 // add
+                    } else {
+                        1 // skip
                     }
                 }),
             )
             .context("staging changes")?;
         index.write().context("writing index")?;
-        let tree_oid = index.write_tree().context("writing tree")?;
+        index.write_tree().context("writing tree")
+    }
+
+    /// Commit the working tree onto `branch` without touching HEAD or the
+    /// index: the tree is the branch tip's plus the included working-tree
+    /// paths, and only `refs/heads/<branch>` moves.
+    fn commit_to_branch(
+        &self,
+        branch: &str,
+        message: &str,
+        tail: &str,
+        include: &impl Fn(&Path) -> bool,
+    ) -> Result<git2::Oid> {
+        let parent = self.commit_parent();
+        let tree_oid = self.snapshot_tree(parent.as_ref(), include)?;
         let tree = self.repo.find_tree(tree_oid).context("finding tree")?;
         let sig = self.repo
             .signature()
             .context("reading git signature (user.name / user.email)")?;
-        let parent = self.repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let message = self.with_subject_diffstat(message, parent.as_ref(), &tree)?;
+        let message = self.attest(&message, tail);
         let parents: Vec<&git2::Commit> = parent.iter().collect();
         let oid = self.repo
-            .commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)
-            .context("creating commit")?;
+            .commit(Some(&format!("refs/heads/{branch}")), &sig, &sig, &message, &tree, &parents)
+            .with_context(|| format!("creating commit on branch {branch}"))?;
         Ok(oid)
     }
 
-    /// Return the OID of the current HEAD commit, if one exists.
-    fn head_oid(&self) -> Option<git2::Oid> {
+    /// `message` with " (+N -M)" appended to its subject, counting the lines
+    /// `tree` adds and deletes relative to `parent`, when `subject_diffstat`
+    /// is set.  Needs the staged tree, so it runs just before committing
+    /// rather than when the message is built.
+    fn with_subject_diffstat(
+        &self,
+        message: &str,
+        parent: Option<&git2::Commit>,
+        tree: &git2::Tree,
+    ) -> Result<String> {
+        if !self.prefs.subject_diffstat {
+            return Ok(message.to_string());
+        }
+        let parent_tree = parent.map(|c| c.tree()).transpose().context("reading parent tree")?;
+        let stats = self
+            .repo
+            .diff_tree_to_tree(parent_tree.as_ref(), Some(tree), None)
+            .and_then(|diff| diff.stats())
+            .context("computing diffstat")?;
+        let counts = format!(" (+{} -{})", stats.insertions(), stats.deletions());
+        Ok(match message.split_once('\n') {
+            Some((subject, rest)) => format!("{subject}{counts}\n{rest}"),
+            None => format!("{message}{counts}"),
+        })
+    }
+
+    /// `message` with a `Claude-Attestation` trailer over it and `tail`
+    /// when `provenance_hmac_key` is set.  Runs last, on the final message.
+    fn attest(&self, message: &str, tail: &str) -> String {
+        match &self.prefs.provenance_hmac_key {
+            Some(key) => attestation::sign(message, tail, key),
+            None => message.to_string(),
+        }
+    }
+
+    /// Check the `Claude-Attestation` trailer of commit `rev` against its
+    /// message and `refs/notes/tail` note, using `provenance_hmac_key`.
+    pub fn verify_attestation(&self, rev: &str) -> Result<git2::Oid> {
+        let key = self
+            .prefs
+            .provenance_hmac_key
+            .as_deref()
+            .context("provenance_hmac_key is not set")?;
+        let commit = self
+            .repo
+            .revparse_single(rev)
+            .and_then(|obj| obj.peel_to_commit())
+            .with_context(|| format!("resolving {rev}"))?;
+        let message = commit.message().context("commit message is not UTF-8")?;
+        let tail = self
+            .read_note("refs/notes/tail", commit.id())
+            .with_context(|| format!("{rev} has no tail note"))?;
+        match attestation::verify(message, &tail, key) {
+            Some(true) => Ok(commit.id()),
+            Some(false) => anyhow::bail!("attestation of {rev} does not match"),
+            None => anyhow::bail!("{rev} has no attestation trailer"),
+        }
+    }
+
+    /// Write a tree of `parent`'s contents plus every working-tree path
+    /// (outside `.clautribution/`) for which `include` holds.  The tree is
+    /// built in a standalone in-memory index, so the repo index and the
+    /// user's staging area are never touched.
+    fn snapshot_tree(
+        &self,
+        parent: Option<&git2::Commit>,
+        include: &impl Fn(&Path) -> bool,
+    ) -> Result<git2::Oid> {
+        let workdir = self.repo.workdir().context("repository has no working tree")?;
+        let mut index = git2::Index::new().context("creating index")?;
+        if let Some(commit) = parent {
+            index
+                .read_tree(&commit.tree().context("reading parent tree")?)
+                .context("reading parent tree into index")?;
+        }
+        let diff = self.workdir_diff(parent)?;
+        for delta in diff.deltas() {
+            let Some(path) = delta_path(&delta) else { continue };
+            if !self.stages(path, include) {
+                continue;
+            }
+            if delta.status() == git2::Delta::Deleted {
+                index.remove_path(path).context("removing deleted path")?;
+                continue;
+            }
+            let mode = delta.new_file().mode();
+            let full = workdir.join(path);
+            let id = match mode {
+                git2::FileMode::Link => {
+                    let target = std::fs::read_link(&full)
+                        .with_context(|| format!("reading symlink {}", path.display()))?;
+                    self.repo
+                        .blob(target.as_os_str().as_encoded_bytes())
+                        .context("writing symlink blob")?
+                }
+                git2::FileMode::Blob | git2::FileMode::BlobExecutable => self
+                    .repo
+                    .blob_path(&full)
+                    .with_context(|| format!("hashing {}", path.display()))?,
+                _ => continue,
+            };
+            let size = full.symlink_metadata().map(|m| m.len()).unwrap_or(0);
+            index
+                .add(&git2::IndexEntry {
+                    ctime: git2::IndexTime::new(0, 0),
+                    mtime: git2::IndexTime::new(0, 0),
+                    dev: 0,
+                    ino: 0,
+                    mode: u32::from(mode),
+                    uid: 0,
+                    gid: 0,
+                    file_size: size as u32,
+                    id,
+                    flags: 0,
+                    flags_extended: 0,
+                    path: path.as_os_str().as_encoded_bytes().to_vec(),
+                })
+                .context("staging changes")?;
+        }
+        index.write_tree_to(&self.repo).context("writing tree")
+    }
+
+    /// Diff of `parent`'s tree straight against the working tree, untracked
+    /// files included, bypassing the index (which `commit_branch` leaves at
+    /// HEAD).
+    fn workdir_diff(&self, parent: Option<&git2::Commit>) -> Result<git2::Diff<'_>> {
+        let tree = parent.map(|c| c.tree()).transpose().context("reading parent tree")?;
+        let mut opts = git2::DiffOptions::new();
+        opts.include_untracked(true).recurse_untracked_dirs(true);
         self.repo
-            .head()
-            .ok()
-            .and_then(|h| h.peel_to_commit().ok())
-            .map(|c| c.id())
+            .diff_tree_to_workdir(tree.as_ref(), Some(&mut opts))
+            .context("diffing working tree")
+    }
+
+    /// The commit new commits are parented on: the tip of `commit_branch`
+    /// when set (HEAD if that branch doesn't exist yet), otherwise HEAD.
+    fn commit_parent(&self) -> Option<git2::Commit<'_>> {
+        let head = || self.repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        match &self.prefs.commit_branch {
+            Some(branch) => self
+                .repo
+                .find_branch(branch, git2::BranchType::Local)
+                .ok()
+                .and_then(|b| b.get().peel_to_commit().ok())
+                .or_else(head),
+            None => head(),
+        }
+    }
+
+    /// Per-file line counts for commit `oid` against its first parent, one
+    /// `path +added -deleted` line per file.  Binary files are listed as
+    /// `path (binary)`, or omitted when `exclude_binary_files` is set.
+    fn diffstat(&self, oid: git2::Oid) -> Result<String> {
+        let commit = self.repo.find_commit(oid).context("finding commit")?;
+        let tree = commit.tree().context("reading commit tree")?;
+        let parent_tree = match commit.parent(0) {
+            Ok(parent) => Some(parent.tree().context("reading parent tree")?),
+            Err(_) => None,
+        };
+        let diff = self
+            .repo
+            .diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)
+            .context("diffing commit")?;
+        let mut lines = Vec::new();
+        for idx in 0..diff.deltas().len() {
+            let Some(patch) = git2::Patch::from_diff(&diff, idx).context("computing patch")? else {
+                continue;
+            };
+            let delta = patch.delta();
+            let path = delta
+                .new_file()
+                .path()
+                .or_else(|| delta.old_file().path())
+                .map(|p| p.display().to_string())
+                .unwrap_or_default();
+            if delta.flags().is_binary() {
+                if !self.prefs.exclude_binary_files {
+                    lines.push(format!("{path} (binary)"));
+                }
+            } else {
+                let (_, added, deleted) = patch.line_stats().context("counting lines")?;
+                lines.push(format!("{path} +{added} -{deleted}"));
+            }
+        }
+        Ok(lines.join("\n"))
+    }
+
+    /// Unified diff of the commit tip against the working tree (including
+    /// untracked files) for exactly the paths `commit_changes` would stage.
+    pub fn pending_diff(&self) -> Result<String> {
+        let diff = self.diff_against_tip(git2::DiffOptions::new().show_untracked_content(true))?;
+        let mut out = String::new();
+        diff.print(git2::DiffFormat::Patch, |delta, _hunk, line| {
+            let path = delta.new_file().path().or_else(|| delta.old_file().path());
+            if path.is_some_and(is_metadata_path) {
+                return true;
+            }
+            if matches!(line.origin(), '+' | '-' | ' ') {
+                out.push(line.origin());
+            }
+            out.push_str(&String::from_utf8_lossy(line.content()));
+            true
+        })
+        .context("formatting diff")?;
+        Ok(out)
+    }
+
+    /// Diff of the tree at `commit_parent` against the working tree and
+    /// index, untracked files included.  With `commit_branch` HEAD and the
+    /// index never move, so the branch tip is diffed against the working
+    /// tree alone, which is what `commit_to_branch` commits.
+    fn diff_against_tip(&self, opts: &mut git2::DiffOptions) -> Result<git2::Diff<'_>> {
+        let tip = self.commit_parent();
+        let tip_tree = match &tip {
+            Some(commit) => Some(commit.tree().context("reading tip tree")?),
+            None => None,
+        };
+        opts.include_untracked(true).recurse_untracked_dirs(true);
+        if self.prefs.commit_branch.is_some() {
+            return self
+                .repo
+                .diff_tree_to_workdir(tip_tree.as_ref(), Some(opts))
+                .context("diffing working tree");
+        }
+        self.repo
+            .diff_tree_to_workdir_with_index(tip_tree.as_ref(), Some(opts))
+            .context("diffing working tree")
+    }
+
+    /// Return the OID of the commit the last clautribution commit landed
+    /// on: the `commit_branch` tip when set, otherwise HEAD.
+    fn tip_oid(&self) -> Option<git2::Oid> {
+        self.commit_parent().map(|c| c.id())
     }
 
     /// Read a plain-text git note from `ref_name` on the given commit OID.
     /// Returns `None` if no note exists.
     fn read_note(&self, ref_name: &str, oid: git2::Oid) -> Option<String> {
         self.repo
-            .find_note(Some(ref_name), oid)
+            .find_note(Some(&self.notes_ref(ref_name)), oid)
             .ok()
             .and_then(|note| note.message().map(|s| s.trim().to_string()))
     }
 
+    /// The prompt recorded on the commit `rev` resolves to: the full text
+    /// from `refs/notes/prompt-full` if the prompt was long, otherwise
+    /// `refs/notes/prompt`.
+    pub fn stored_prompt(&self, rev: &str) -> Result<Option<String>> {
+        let commit = self
+            .repo
+            .revparse_single(rev)
+            .and_then(|obj| obj.peel_to_commit())
+            .with_context(|| format!("resolving {rev}"))?;
+        Ok(self
+            .read_note("refs/notes/prompt-full", commit.id())
+            .or_else(|| self.read_note("refs/notes/prompt", commit.id())))
+    }
+
+    /// Commits reachable from HEAD, newest first, with the first line of
+    /// each one's `refs/notes/prompt` note.  Commits without one are
+    /// skipped unless `include_all`, in which case their prompt is `None`.
+    /// The history is walked lazily, so taking a prefix stops early.
+    pub fn iter_annotated_commits(
+        &self,
+        include_all: bool,
+    ) -> Result<impl Iterator<Item = Result<(git2::Oid, Option<String>)>> + '_> {
+        let mut walk = self.repo.revwalk().context("walking history")?;
+        walk.push_head().context("walking history from HEAD")?;
+        Ok(walk.filter_map(move |oid| {
+            let oid = match oid.context("walking history") {
+                Ok(oid) => oid,
+                Err(e) => return Some(Err(e)),
+            };
+            let prompt = self
+                .read_note("refs/notes/prompt", oid)
+                .map(|p| p.lines().next().unwrap_or_default().to_string());
+            (include_all || prompt.is_some()).then_some(Ok((oid, prompt)))
+        }))
+    }
+
+    /// A status hint for the user, hidden from verbose output when
+    /// `suppress_hints` is set.
+    fn hint(&self, message: impl Into<String>) -> HookOutput {
+        let mut output = HookOutput::hint(message);
+        if self.prefs.suppress_hints {
+            output.suppress_output = Some(true);
+        }
+        output
+    }
+
+    /// Map a canonical `refs/notes/<name>` ref into the configured
+    /// `notes_namespace`.
+    fn notes_ref(&self, ref_name: &str) -> String {
+        match ref_name.strip_prefix("refs/notes/") {
+            Some(name) => format!("{}/{name}", self.prefs.notes_namespace()),
+            None => ref_name.to_string(),
+        }
+    }
+
     /// Write a set of per-category git notes on a commit.
     fn write_notes(&self, oid: git2::Oid, notes: &[(&str, &str)]) -> Result<()> {
         let sig = self.repo.signature().context("reading git signature")?;
         for (ref_name, content) in notes {
+            let ref_name = self.notes_ref(ref_name);
             self.repo
-                .note(&sig, &sig, Some(ref_name), oid, content, true)
+                .note(&sig, &sig, Some(&ref_name), oid, content, true)
                 .with_context(|| format!("writing note to {ref_name}"))?;
         }
         Ok(())
@@ -310,6 +1003,8 @@ impl Session {
             prompt: input.prompt.clone(),
             session_id: self.session_id.clone(),
             uuid: transcript.find_user_prompt(&input.prompt).map(String::from),
+            submitted_at: Some(rfc3339(unix_now())),
+            is_slash_command: input.prompt.trim_start().starts_with('/'),
         };
         let json = serde_json::to_string_pretty(&meta).context("serializing prompt metadata")?;
         fs::write(&path, json).with_context(|| format!("writing {}", path.display()))
@@ -367,6 +1062,79 @@ impl Session {
         remove_if_exists(&self.drop_marker_path())
     }
 
+    // ---------------------------------------------------------------
+    // Last stop tail
+    // ---------------------------------------------------------------
+
+    /// Read the conversation tail seen by this session's previous Stop.
+    fn read_last_stop_tail(&self) -> Result<Option<String>> {
+        let path = self.last_stop_tail_path();
+        match fs::read_to_string(&path) {
+            Ok(s) => Ok(Some(s.trim().to_string())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("reading {}", path.display())),
+        }
+    }
+
+    fn write_last_stop_tail(&self, tail_uuid: &str) -> Result<()> {
+        let path = self.last_stop_tail_path();
+        fs::write(&path, tail_uuid).with_context(|| format!("writing {}", path.display()))
+    }
+
+    fn clear_last_stop_tail(&self) -> Result<()> {
+        remove_if_exists(&self.last_stop_tail_path())
+    }
+
+    // ---------------------------------------------------------------
+    // Commit debouncing
+    // ---------------------------------------------------------------
+
+    /// Record that this session just created commit `oid`.
+    fn write_last_commit(&self, oid: git2::Oid) -> Result<()> {
+        let path = self.last_commit_path();
+        let last = LastCommit {
+            timestamp: unix_now(),
+            commit: oid.to_string(),
+            deferred: false,
+        };
+        let json = serde_json::to_string_pretty(&last).context("serializing last commit")?;
+        fs::write(&path, json).with_context(|| format!("writing {}", path.display()))
+    }
+
+    /// Note that a productive stop was deferred, leaving its changes
+    /// uncommitted in the working tree.  Before the session's first commit
+    /// the record has no commit and a zero timestamp.
+    fn mark_commit_deferred(&self) -> Result<()> {
+        let path = self.last_commit_path();
+        let mut last = read_json_file::<LastCommit>(&path)?.unwrap_or_default();
+        last.deferred = true;
+        let json = serde_json::to_string_pretty(&last).context("serializing last commit")?;
+        fs::write(&path, json).with_context(|| format!("writing {}", path.display()))
+    }
+
+    /// Whether a productive stop since this session's last commit was
+    /// deferred (by `min_commit_interval_secs` or `commit_trigger`), so
+    /// uncommitted changes are Claude's rather than manual edits.
+    fn has_deferred_changes(&self) -> Result<bool> {
+        let last: Option<LastCommit> = read_json_file(&self.last_commit_path())?;
+        Ok(last.is_some_and(|l| l.deferred))
+    }
+
+    /// Whether this session committed less than `min_commit_interval_secs`
+    /// ago.
+    fn within_commit_interval(&self) -> Result<bool> {
+        let interval = self.prefs.min_commit_interval_secs;
+        if interval == 0 {
+            return Ok(false);
+        }
+        let last: Option<LastCommit> = read_json_file(&self.last_commit_path())?;
+        Ok(last.is_some_and(|l| unix_now().saturating_sub(l.timestamp) < interval))
+    }
+
+    fn clear_last_commit(&self) -> Result<()> {
+        remove_if_exists(&self.last_commit_path())
+    }
+
     // ---------------------------------------------------------------
     // Plan history
     // ---------------------------------------------------------------
@@ -426,7 +1194,7 @@ impl Session {
         &self,
         current_transcript_path: &str,
         planning_session_id: &str,
-    ) -> Result<Vec<serde_json::Value>> {
+    ) -> Result<Vec<Arc<serde_json::Value>>> {
         let dir = match std::path::Path::new(current_transcript_path).parent() {
             Some(d) => d,
             None => return Ok(vec![]),
@@ -553,7 +1321,7 @@ impl Session {
             // commit ended; user texts after that point are the planning
             // discussion.
             let committed_tail = self
-                .head_oid()
+                .tip_oid()
                 .and_then(|oid| self.read_note("refs/notes/tail", oid));
             let user_texts =
                 transcript.user_texts_until(tail, committed_tail.as_deref());
@@ -585,6 +1353,14 @@ impl Session {
     pub fn handle_session_start(&self, input: &SessionStartInput) -> Result<Option<HookOutput>> {
         let mut warnings: Vec<String> = Vec::new();
 
+        // Tell a resumed Claude about work accumulated since the last commit
+        // (read before the cleanup below discards the tracking state).
+        let resume_context = if input.source == SessionStartSource::Resume {
+            self.resume_context(&input.common.transcript_path)?
+        } else {
+            None
+        };
+
         // On resume/clear, clean up this session's stale prompt metadata
         // so tracking starts fresh.  Compact preserves the original prompt
         // metadata so the commit message uses the real user prompt rather
@@ -644,30 +1420,68 @@ impl Session {
             );
         }
 
-        if warnings.is_empty() {
-            Ok(None)
-        } else {
-            Ok(hint(format!(
-                "[clautribution] warning: {}",
-                warnings.join("; ")
-            )))
+        let warning = (!warnings.is_empty())
+            .then(|| format!("[clautribution] warning: {}", warnings.join("; ")));
+        match (resume_context, warning) {
+            (None, None) => Ok(None),
+            (None, Some(warning)) => Ok(Some(HookOutput::hint(warning))),
+            (Some(context), warning) => {
+                let mut output = HookOutput::additional_context(ContextEvent::SessionStart, context);
+                output.system_message = warning;
+                Ok(Some(output))
+            }
+        }
+    }
+
+    /// Summarize uncommitted accumulated work (the tracked prompt and any
+    /// pending plan) for a resumed session, or `None` if there is none.
+    /// Tracking state alone (e.g. the breadcrumb of a turn that changed
+    /// nothing) isn't work: it counts only with changes or a pending plan.
+    fn resume_context(&self, transcript_path: &str) -> Result<Option<String>> {
+        let metadata = self.read_prompt_metadata()?;
+        let breadcrumb = self.read_breadcrumb()?;
+        let pending_plan = self.read_pending_plan()?;
+        if metadata.is_none() && breadcrumb.is_none() && pending_plan.is_none() {
+            return Ok(None);
+        }
+        if pending_plan.is_none() && !self.has_uncommitted_changes()? {
+            return Ok(None);
+        }
+        let prompt = match metadata {
+            Some(m) => Some(m.prompt),
+            None => read_transcript(&self.resolve_transcript_path(transcript_path)?)?
+                .last_user_text()
+                .map(|(_, text, _)| text.to_string()),
+        };
+        let mut lines =
+            vec!["clautribution: work since the last commit has not been committed yet.".to_string()];
+        if let Some(prompt) = prompt {
+            lines.push(format!("Last prompt: {prompt}"));
+        }
+        if let Some(plan) = pending_plan {
+            lines.push(format!("Pending plan:\n{}", plan.trim()));
         }
+        Ok(Some(lines.join("\n")))
     }
 
     pub fn handle_user_prompt_submit(
         &self,
         input: &UserPromptSubmitInput,
     ) -> Result<Option<HookOutput>> {
+        let transcript_path = self.resolve_transcript_path(&input.common.transcript_path)?;
+
         // Intercept /preview and /drop skill invocations so the output
         // is relayed verbatim via the block reason (skills get paraphrased).
         if is_preview_command(&input.prompt) {
-            return self.handle_preview_command(&input.common.transcript_path);
+            return self.handle_preview_command(&transcript_path);
         }
         if is_drop_command(&input.prompt) {
-            return self.handle_drop_command(&input.common.transcript_path);
+            return self.handle_drop_command(&transcript_path);
         }
 
-        if self.has_uncommitted_changes()? {
+        // Changes left behind by a debounced (deferred) stop are Claude's,
+        // not manual edits — let them accumulate into the next commit.
+        if self.has_uncommitted_changes()? && !self.has_deferred_changes()? {
             // If HEAD is a clautribution commit, this may be a post-/rewind
             // state where Claude Code restored files but git still has our
             // commits.  Undo them to align git with the rewind.
@@ -677,19 +1491,14 @@ impl Session {
                 self.clear_breadcrumb()?;
                 self.clear_drop_marker()?;
             } else {
-                return Ok(Some(HookOutput {
-                    decision: Some("block".into()),
-                    reason: Some(
-                        "There are uncommitted changes. Please commit your manual changes \
-                         before prompting Claude."
-                            .into(),
-                    ),
-                    ..Default::default()
-                }));
+                return Ok(Some(HookOutput::block(
+                    "There are uncommitted changes. Please commit your manual changes \
+                     before prompting Claude.",
+                )));
             }
         }
 
-        let transcript = read_transcript(&input.common.transcript_path)?;
+        let transcript = read_transcript(&transcript_path)?;
 
         if self.read_prompt_metadata()?.is_some() {
             // A previous prompt was being tracked but never reached a
@@ -707,7 +1516,7 @@ impl Session {
 
         self.write_prompt_metadata(input, &transcript)?;
 
-        Ok(hint("[clautribution] tracking prompt".into()))
+        Ok(Some(self.hint("[clautribution] tracking prompt")))
     }
 
     /// Handle a `/preview` skill invocation: build the stop context,
@@ -722,15 +1531,14 @@ impl Session {
         let decision = decide_stop(&ctx).map_err(|e| anyhow::anyhow!("{e}"))?;
         let message = match decision {
             StopDecision::NoMetadata => "No prompt metadata — nothing to preview.".to_string(),
+            StopDecision::AlreadyCommitted => {
+                "Nothing new since the last commit — nothing to preview.".to_string()
+            }
             StopDecision::NoTail => "No transcript tail — nothing to preview.".to_string(),
             StopDecision::Productive { commit_message, .. } => commit_message,
             StopDecision::Nonproductive { .. } => "No preview available.".to_string(),
         };
-        Ok(Some(HookOutput {
-            decision: Some("block".into()),
-            reason: Some(message),
-            ..Default::default()
-        }))
+        Ok(Some(HookOutput::block(message)))
     }
 
     /// Handle a `/drop` skill invocation: record the current transcript
@@ -741,14 +1549,9 @@ impl Session {
             self.write_drop_marker(tail)?;
         }
         self.drop_accumulated()?;
-        Ok(Some(HookOutput {
-            decision: Some("block".into()),
-            reason: Some(
-                "Accumulated state dropped. Future commits will start from this point."
-                    .into(),
-            ),
-            ..Default::default()
-        }))
+        Ok(Some(HookOutput::block(
+            "Accumulated state dropped. Future commits will start from this point.",
+        )))
     }
 
     /// Discover the active session ID by scanning for `prompt-*.json`
@@ -815,7 +1618,8 @@ impl Session {
                 .and_then(|s| s.to_str())
                 .unwrap_or("")
                 .to_string();
-            if sid.is_empty() {
+            // Rotated parts are read along with their session's file.
+            if sid.is_empty() || is_rotated_part(&sid) {
                 continue;
             }
             let mtime = entry
@@ -830,11 +1634,34 @@ impl Session {
         }))
     }
 
+    /// Return `transcript_path` if it exists.  Otherwise (the file was
+    /// rotated or moved) fall back to this session's transcript in the
+    /// Claude Code projects directory, then to the most recent transcript.
+    /// If discovery finds nothing the original path is returned unchanged.
+    pub fn resolve_transcript_path(&self, transcript_path: &str) -> Result<String> {
+        if std::path::Path::new(transcript_path).exists() {
+            return Ok(transcript_path.to_string());
+        }
+        if let Ok(dir) = self.claude_projects_dir() {
+            let own = dir.join(format!("{}.jsonl", self.session_id));
+            if own.exists() {
+                return Ok(own.to_string_lossy().into_owned());
+            }
+        }
+        Ok(self
+            .active_transcript()
+            .ok()
+            .flatten()
+            .map(|(_, path)| path)
+            .unwrap_or_else(|| transcript_path.to_string()))
+    }
+
     /// Gather all I/O-derived state needed for `decide_stop` into an
     /// owned struct.  Used by both `handle_stop` (hook path) and the
     /// `preview` subcommand.
     pub fn build_stop_context(&self, transcript_path: &str) -> Result<OwnedStopContext> {
-        let transcript = read_transcript(transcript_path)?;
+        let mut transcript = read_transcript(transcript_path)?;
+        transcript.set_max_walk_depth(self.prefs.max_walk_depth);
         let plan_context = self.read_plan_context()?;
         let plan_entries = match plan_context
             .as_ref()
@@ -851,16 +1678,62 @@ impl Session {
             plan_entries,
             session_id: self.session_id.clone(),
             breadcrumb: self.read_breadcrumb()?,
+            detect_resets: self.prefs.detect_resets,
             committed_tail: self.read_drop_marker()?.or_else(|| {
-                self.head_oid()
+                self.tip_oid()
                     .and_then(|oid| self.read_note("refs/notes/tail", oid))
             }),
             has_uncommitted_changes: self.has_uncommitted_changes()?,
             commit_template: self.load_commit_template()?,
-            verbosity: self.prefs.summary_verbosity(),
+            summary: SummaryOptions {
+                workdir: self.repo.workdir().map(Path::to_path_buf),
+                ..self.prefs.summary_options()
+            },
+            tail_strategy: self.prefs.tail_strategy(),
+            prompt_body: self.prefs.prompt_body(),
+            summary_current_session_only: self.prefs.summary_current_session_only,
+            subject: self.prefs.subject_options(),
+            body_sections: self.prefs.body_sections(),
+            section_heading_style: self.prefs.section_heading_style(),
+            include_sidechains_in_note: self.prefs.include_sidechains_in_note,
+            permission_mode: None,
+            defer_to_manual_git: self.prefs.defer_to_manual_git,
+            max_transcript_note_entries: self.prefs.max_transcript_note_entries,
+            ignore_slash_command_prompts: self.prefs.ignore_slash_command_prompts,
+            include_session_trailer: self.prefs.include_session_trailer,
+            version_trailer: self.prefs.version_trailer,
+            file_changes: if self.prefs.separate_added_files {
+                Some(self.pending_file_changes()?)
+            } else {
+                None
+            },
+            nonproductive_hint: self.prefs.nonproductive_hint(),
         })
     }
 
+    /// With `amend_consecutive`, the commit a productive stop should amend:
+    /// the tip, when it's a clautribution commit from the same session and
+    /// nothing has been dropped since.
+    fn amend_target(&self, owned: &OwnedStopContext) -> Result<Option<git2::Oid>> {
+        if !self.prefs.amend_consecutive
+            || !owned.has_uncommitted_changes
+            || self.read_drop_marker()?.is_some()
+        {
+            return Ok(None);
+        }
+        let Some(tip) = self.tip_oid() else {
+            return Ok(None);
+        };
+        // The session the decision will attribute the commit to.
+        let session_id = owned
+            .file_metadata
+            .as_ref()
+            .map_or(owned.session_id.as_str(), |m| m.session_id.as_str());
+        let same_session = self.read_note("refs/notes/tail", tip).is_some()
+            && self.read_note("refs/notes/session", tip).as_deref() == Some(session_id);
+        Ok(same_session.then_some(tip))
+    }
+
     /// Clear accumulated nonproductive state (prompt metadata and
     /// continuation breadcrumb), resetting to the state as of the last
     /// commit.
@@ -871,15 +1744,106 @@ impl Session {
     }
 
     pub fn handle_stop(&self, input: &StopInput) -> Result<Option<HookOutput>> {
-        let owned = self.build_stop_context(&input.common.transcript_path)?;
+        let transcript_path = self.resolve_transcript_path(&input.common.transcript_path)?;
+        self.stop(
+            &transcript_path,
+            input.stop_hook_active,
+            input.common.permission_mode.clone(),
+        )
+    }
+
+    /// Decide and act on a stop for an already-resolved transcript path.
+    /// Shared by the Stop hook and `preview --commit`.
+    pub fn stop(
+        &self,
+        transcript_path: &str,
+        stop_hook_active: bool,
+        permission_mode: Option<PermissionMode>,
+    ) -> Result<Option<HookOutput>> {
+        self.stop_at(transcript_path, stop_hook_active, permission_mode, CommitTrigger::Stop)
+    }
+
+    /// `stop`, fired by the `trigger` event.  Productive stops commit only
+    /// when `trigger` is the configured `commit_trigger`; otherwise they're
+    /// recorded as nonproductive so the work accumulates.
+    fn stop_at(
+        &self,
+        transcript_path: &str,
+        stop_hook_active: bool,
+        permission_mode: Option<PermissionMode>,
+        trigger: CommitTrigger,
+    ) -> Result<Option<HookOutput>> {
+        let mut owned = self.build_stop_context(transcript_path)?;
+        owned.permission_mode = permission_mode;
+        log::debug!("session {} (transcript {transcript_path})", self.session_id);
+        log::debug!(
+            "tail {}",
+            owned.transcript.select_tail(owned.tail_strategy).unwrap_or("(none)")
+        );
+
+        // The transcript hasn't grown since the previous Stop and there's
+        // nothing to commit: the decision would come out the same, so skip
+        // it entirely.
+        if let Some(conv_tail) = owned.transcript.conversation_tail() {
+            if !owned.has_uncommitted_changes
+                && self.read_last_stop_tail()?.as_deref() == Some(conv_tail)
+            {
+                return Ok(None);
+            }
+            self.write_last_stop_tail(conv_tail)?;
+        }
+
+        // The hook is re-firing because an earlier Stop hook blocked
+        // continuation.  If nothing happened since we committed, the tail is
+        // still the one recorded on HEAD — don't commit it a second time.
+        if stop_hook_active {
+            let head_tail = self
+                .tip_oid()
+                .and_then(|oid| self.read_note("refs/notes/tail", oid));
+            if head_tail.is_some()
+                && head_tail.as_deref() == owned.transcript.select_tail(owned.tail_strategy)
+            {
+                return Ok(None);
+            }
+        }
+
+        // Debounce: too soon after the last commit, treat the stop as
+        // nonproductive so the changes accumulate into the next commit.
+        // Session end is the last chance to commit, so it never waits.
+        let deferred = owned.has_uncommitted_changes
+            && trigger == CommitTrigger::Stop
+            && self.within_commit_interval()?;
+        // Commits are left to a later event (session end).
+        let deferred_to_trigger = owned.has_uncommitted_changes
+            && trigger == CommitTrigger::Stop
+            && self.prefs.commit_trigger() == CommitTrigger::SessionEnd;
+        if deferred || deferred_to_trigger {
+            owned.has_uncommitted_changes = false;
+        }
+
+        // Growing the previous commit: describe everything since the one
+        // before it, so the amended message and notes cover both stops.
+        let amend = self.amend_target(&owned)?;
+        if let Some(oid) = amend {
+            owned.committed_tail = self
+                .repo
+                .find_commit(oid)
+                .ok()
+                .and_then(|c| c.parent_id(0).ok())
+                .and_then(|parent| self.read_note("refs/notes/tail", parent));
+        }
+
         let ctx = owned.as_ref();
 
         // --- Decide (pure) ---
         let decision = decide_stop(&ctx).map_err(|e| anyhow::anyhow!("{e}"))?;
+        log::debug!("decision {}", decision.variant_name());
 
         // --- Execute ---
         match decision {
-            StopDecision::NoMetadata | StopDecision::NoTail => Ok(None),
+            StopDecision::NoMetadata | StopDecision::AlreadyCommitted | StopDecision::NoTail => {
+                Ok(None)
+            }
             StopDecision::Nonproductive {
                 hint_message,
                 breadcrumb,
@@ -897,7 +1861,23 @@ impl Session {
                     self.write_plan_context(&pc)?;
                 }
                 self.write_breadcrumb(&breadcrumb)?;
-                Ok(hint(hint_message))
+                if deferred || deferred_to_trigger {
+                    self.mark_commit_deferred()?;
+                }
+                let Some(hint_message) = hint_message else {
+                    return Ok(None);
+                };
+                if deferred_to_trigger {
+                    return Ok(Some(self.hint(format!(
+                        "{hint_message} (commit deferred to session end)"
+                    ))));
+                }
+                if deferred {
+                    return Ok(Some(self.hint(format!(
+                        "{hint_message} (commit deferred: min_commit_interval_secs not elapsed)"
+                    ))));
+                }
+                Ok(Some(self.hint(hint_message)))
             }
             StopDecision::Productive {
                 hint_message,
@@ -913,30 +1893,99 @@ impl Session {
                 if consumed_plan_context {
                     self.clear_plan_context()?;
                 }
-                let oid = self.commit_changes(&commit_message)?;
-                let json = serde_json::to_string_pretty(&transcript_note_entries)
-                    .context("serializing transcript")?;
-                let mut notes: Vec<(&str, &str)> = vec![("refs/notes/transcript", &json)];
-                notes.extend(
-                    simple_notes
-                        .iter()
-                        .map(|(r, c)| (r.as_str(), c.as_str())),
-                );
-                self.write_notes(oid, &notes)?;
+                let tail = simple_notes
+                    .iter()
+                    .find(|(r, _)| r == "refs/notes/tail")
+                    .map_or("", |(_, c)| c.as_str());
+                let oids = match amend {
+                    Some(oid) => vec![self.amend_commit(oid, &commit_message, tail)?],
+                    None => self.commit_split(&commit_message, tail)?,
+                };
+                let transcript_note = match self.prefs.transcript_note_format() {
+                    TranscriptNoteFormat::Json => serde_json::to_string_pretty(&transcript_note_entries)
+                        .context("serializing transcript")?,
+                    TranscriptNoteFormat::Markdown => Transcript::to_markdown(&transcript_note_entries),
+                };
+                // A split turn's transcript, prompt and other notes go on its
+                // last commit only.  The earlier commits keep their tail
+                // (which marks them as clautribution commits for rewind and
+                // attestation) and point at the last one in refs/notes/split.
+                let last = oids.last().copied();
+                let last_oid = last.map(|oid| oid.to_string()).unwrap_or_default();
+                for &oid in &oids {
+                    let diffstat = self.diffstat(oid)?;
+                    let mut notes: Vec<(&str, &str)> = Vec::new();
+                    if Some(oid) == last {
+                        notes.push(("refs/notes/transcript", &transcript_note));
+                        notes.extend(
+                            simple_notes
+                                .iter()
+                                .map(|(r, c)| (r.as_str(), c.as_str())),
+                        );
+                    } else {
+                        notes.push(("refs/notes/tail", tail));
+                        notes.push(("refs/notes/split", &last_oid));
+                    }
+                    if !diffstat.is_empty() {
+                        notes.push(("refs/notes/diffstat", &diffstat));
+                    }
+                    // The commit is already made; a failed note (e.g. the
+                    // notes ref is locked by a concurrent git process)
+                    // shouldn't turn the whole stop into a hook failure.
+                    if let Err(err) = self.write_notes(oid, &notes) {
+                        // `err` names the notes ref that failed; the ones
+                        // after it weren't written either.
+                        if self.read_note("refs/notes/tail", oid).is_none() {
+                            log::warning!(
+                                "committed {oid} but {err:#}; its tail note is missing, so the next stop will describe this turn again"
+                            );
+                        } else {
+                            log::warning!("committed {oid} but {err:#}; later notes were skipped");
+                        }
+                    }
+                }
+                if let Some(last) = last {
+                    self.write_last_commit(last)?;
+                }
                 self.clear_breadcrumb()?;
                 self.clear_drop_marker()?;
-                Ok(hint(hint_message))
+                if oids.len() > 1 {
+                    return Ok(Some(self.hint(format!(
+                        "{hint_message} (split into {} commits)",
+                        oids.len()
+                    ))));
+                }
+                Ok(Some(self.hint(hint_message)))
             }
         }
     }
 
-    pub fn handle_session_end(&self, _input: &SessionEndInput) -> Result<Option<HookOutput>> {
+    pub fn handle_session_end(&self, input: &SessionEndInput) -> Result<Option<HookOutput>> {
+        // Commit everything the session's stops accumulated (or deferred
+        // for min_commit_interval_secs) before the tracking state below is
+        // cleared; left uncommitted, the next session would take Claude's
+        // changes for manual edits.
+        let accumulated = self.prefs.commit_trigger() == CommitTrigger::SessionEnd
+            || self.has_deferred_changes()?;
+        let output = if accumulated {
+            let transcript_path = self.resolve_transcript_path(&input.common.transcript_path)?;
+            self.stop_at(
+                &transcript_path,
+                false,
+                input.common.permission_mode.clone(),
+                CommitTrigger::SessionEnd,
+            )?
+        } else {
+            None
+        };
         self.clear_prompt_metadata()?;
         self.clear_breadcrumb()?;
         self.clear_drop_marker()?;
         self.clear_pending_plan()?;
         self.clear_plan_history()?;
-        Ok(None)
+        self.clear_last_commit()?;
+        self.clear_last_stop_tail()?;
+        Ok(output)
     }
 
     // ---------------------------------------------------------------
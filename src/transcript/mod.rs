@@ -1,6 +1,9 @@
-use serde::Deserialize;
-use std::collections::{HashMap, HashSet};
-use std::path::Path;
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::cell::OnceCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 // ===================================================================
 // Verbosity — controls how much tool detail appears in turn summaries
@@ -17,6 +20,150 @@ pub enum Verbosity {
     Full,
 }
 
+/// How the tool summary's items are laid out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SummaryStyle {
+    /// "edited: a, b" lines (or one comma-joined line of counts).
+    #[default]
+    Prose,
+    /// A GitHub task list: "- [x] edited a", one item per line.
+    Checklist,
+}
+
+/// Options for `summarize_turn`, resolved from user preferences.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SummaryOptions {
+    pub verbosity: Verbosity,
+    /// When `false`, tool calls whose `caller` is an agent (e.g. a subagent
+    /// acting on the main session's behalf) are left out of the tool summary.
+    pub count_agent_tool_calls: bool,
+    /// Nouns used when counting tool calls at Short verbosity.
+    pub nouns: CategoryNouns,
+    /// Per-category verbosity ("edited", "read", ...) overriding `verbosity`.
+    pub category_verbosity: BTreeMap<String, Verbosity>,
+    /// When `true`, files whose edits within the turn cancel out are left
+    /// out of the summary instead of being marked "(reverted)".
+    pub exclude_reverted: bool,
+    /// Prose lines or a GitHub task list.
+    pub style: SummaryStyle,
+    /// When `false`, prompts and assistant text after the `---` are left
+    /// out and only the tool summary (and Q&A) remain.
+    pub include_messages: bool,
+    /// When `true`, a turn that ran with high extended thinking gets a
+    /// `(deep thinking)` tag in front of its summary, unless the "thinking"
+    /// category's verbosity is Short.
+    pub tag_deep_thinking: bool,
+    /// The repo's working directory.  When set, edited or written files
+    /// outside it are marked "(external)", since they won't be committed.
+    pub workdir: Option<PathBuf>,
+    /// When `true`, files outside `workdir` are left out of the summary
+    /// instead of being marked "(external)".
+    pub exclude_external: bool,
+    /// Icons shown before a category's name ("✏️ edited: main.rs"), keyed
+    /// by category.  Categories without one are shown bare.
+    pub category_icons: BTreeMap<String, String>,
+}
+
+impl SummaryOptions {
+    /// The verbosity for one tool category.
+    pub fn verbosity_for(&self, category: &str) -> Verbosity {
+        self.category_verbosity
+            .get(category)
+            .copied()
+            .unwrap_or(self.verbosity)
+    }
+}
+
+/// Singular and plural forms of the noun counted by a summary category.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Noun {
+    pub singular: String,
+    pub plural: String,
+}
+
+/// Nouns for Short-verbosity counts ("edited 2 files"), keyed by category
+/// ("edited", "ran", ...).  Categories without an override use English.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CategoryNouns {
+    overrides: BTreeMap<String, Noun>,
+}
+
+impl CategoryNouns {
+    pub fn with_overrides(overrides: BTreeMap<String, Noun>) -> Self {
+        Self { overrides }
+    }
+
+    /// The noun for `count` items in `category`.
+    pub fn noun(&self, category: &str, count: usize) -> &str {
+        if let Some(noun) = self.overrides.get(category) {
+            return if count == 1 { &noun.singular } else { &noun.plural };
+        }
+        let (singular, plural) = match category {
+            "edited" | "wrote" | "read" => ("file", "files"),
+            "ran" => ("command", "commands"),
+            "searched" => ("pattern", "patterns"),
+            "fetched" => ("url", "urls"),
+            "delegated" => ("task", "tasks"),
+            "asked" => ("question", "questions"),
+            "other" => ("tool", "tools"),
+            _ => ("item", "items"),
+        };
+        if count == 1 { singular } else { plural }
+    }
+}
+
+/// How to choose the tail entry when the transcript has several leaves
+/// (e.g. after a reset left an abandoned branch behind).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TailStrategy {
+    /// The last entry in file order (`Transcript::tail`).
+    Last,
+    /// The leaf with the longest ancestor chain (`Transcript::deepest_chain`).
+    Deepest,
+}
+
+/// A structural problem in the transcript's parentUuid DAG, as reported
+/// by `Transcript::validate_dag`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DagIssue {
+    /// `uuid`'s parent isn't in the transcript, so ancestor walks from it
+    /// stop early.
+    DanglingParent { uuid: String, parent: String },
+    /// More than one entry has no parent (listed in file order).
+    MultipleRoots(Vec<String>),
+}
+
+impl std::fmt::Display for DagIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DagIssue::DanglingParent { uuid, parent } => {
+                write!(f, "entry {uuid} references missing parent {parent}")
+            }
+            DagIssue::MultipleRoots(roots) => {
+                write!(f, "{} root entries: {}", roots.len(), roots.join(", "))
+            }
+        }
+    }
+}
+
+impl From<Verbosity> for SummaryOptions {
+    fn from(verbosity: Verbosity) -> Self {
+        Self {
+            verbosity,
+            count_agent_tool_calls: true,
+            nouns: CategoryNouns::default(),
+            category_verbosity: BTreeMap::new(),
+            exclude_reverted: false,
+            style: SummaryStyle::Prose,
+            include_messages: true,
+            tag_deep_thinking: false,
+            workdir: None,
+            exclude_external: false,
+            category_icons: BTreeMap::new(),
+        }
+    }
+}
+
 // ===================================================================
 // Top-level transcript entry — one per JSONL line
 // ===================================================================
@@ -114,9 +261,10 @@ pub struct Message {
     pub id: Option<String>,
     #[serde(default, rename = "type")]
     pub message_type: Option<String>,
-    #[serde(default)]
+    // API message fields are snake_case, unlike the envelope.
+    #[serde(default, rename = "stop_reason")]
     pub stop_reason: Option<String>,
-    #[serde(default)]
+    #[serde(default, rename = "stop_sequence")]
     pub stop_sequence: Option<String>,
     #[serde(default)]
     pub usage: Option<Usage>,
@@ -146,6 +294,15 @@ pub enum ContentBlock {
     ToolUse(ToolUseBlock),
     #[serde(rename = "tool_result")]
     ToolResult(ToolResultBlock),
+    #[serde(rename = "server_tool_use")]
+    ServerToolUse(ServerToolUseBlock),
+    #[serde(rename = "web_search_tool_result")]
+    WebSearchToolResult(WebSearchToolResultBlock),
+    /// A block type this version doesn't know about.  Parsed as a
+    /// placeholder so the rest of the entry (and its place in the DAG)
+    /// survives.
+    #[serde(other)]
+    Unknown,
 }
 
 #[derive(Debug, Deserialize)]
@@ -175,6 +332,46 @@ pub struct Caller {
     pub caller_type: String,
 }
 
+impl Caller {
+    /// Whether the tool call was issued by an agent rather than directly
+    /// on the user's behalf.  Claude Code records `{"type": "direct"}` for
+    /// calls the model makes itself (see `tests/fixtures/direct_tool_use.jsonl`);
+    /// any other caller, such as code execution calling tools for it, is an
+    /// agent.
+    pub fn is_agent(&self) -> bool {
+        self.caller_type != "direct"
+    }
+}
+
+/// A tool executed by the API itself (e.g. `web_search`), rather than by
+/// Claude Code.
+#[derive(Debug, Deserialize)]
+pub struct ServerToolUseBlock {
+    pub id: String,
+    pub name: String,
+    pub input: serde_json::Value,
+}
+
+impl ServerToolUseBlock {
+    /// The Claude Code tool this server tool corresponds to, for
+    /// summarizing alongside client-side calls.
+    pub fn client_tool_name(&self) -> &str {
+        match self.name.as_str() {
+            "web_search" => "WebSearch",
+            "web_fetch" => "WebFetch",
+            other => other,
+        }
+    }
+}
+
+/// Results of a server-side `web_search`: a list of hits, or an error
+/// object.
+#[derive(Debug, Deserialize)]
+pub struct WebSearchToolResultBlock {
+    pub tool_use_id: String,
+    pub content: serde_json::Value,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ToolResultBlock {
     pub tool_use_id: String,
@@ -430,6 +627,18 @@ pub struct SystemEntry {
 }
 
 impl TranscriptEntry {
+    /// The entry's JSON `type` tag ("user", "progress", ...).
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::User(_) => "user",
+            Self::Assistant(_) => "assistant",
+            Self::Progress(_) => "progress",
+            Self::FileHistorySnapshot(_) => "file-history-snapshot",
+            Self::QueueOperation(_) => "queue-operation",
+            Self::System(_) => "system",
+        }
+    }
+
     /// Return the UUID if this entry type carries one.
     pub fn uuid(&self) -> Option<&str> {
         match self {
@@ -449,6 +658,29 @@ impl TranscriptEntry {
             Self::FileHistorySnapshot(_) | Self::QueueOperation(_) => None,
         }
     }
+
+    /// Return the session ID if this entry type carries a non-empty one.
+    pub fn session_id(&self) -> Option<&str> {
+        let sid = match self {
+            Self::User(e) | Self::Assistant(e) => &e.session_id,
+            Self::Progress(e) => &e.session_id,
+            Self::System(e) => &e.session_id,
+            Self::QueueOperation(e) => &e.session_id,
+            Self::FileHistorySnapshot(_) => return None,
+        };
+        (!sid.is_empty()).then_some(sid.as_str())
+    }
+
+    /// Return the git branch recorded on this entry, if any.
+    pub fn git_branch(&self) -> Option<&str> {
+        let branch = match self {
+            Self::User(e) | Self::Assistant(e) => &e.git_branch,
+            Self::Progress(e) => &e.git_branch,
+            Self::System(e) => &e.git_branch,
+            Self::FileHistorySnapshot(_) | Self::QueueOperation(_) => return None,
+        };
+        branch.as_deref().filter(|b| !b.is_empty())
+    }
 }
 
 // ===================================================================
@@ -462,7 +694,56 @@ impl TranscriptEntry {
 pub struct Transcript {
     entries: Vec<TranscriptEntry>,
     by_uuid: HashMap<String, usize>, // uuid → index into entries
-    raw: HashMap<String, serde_json::Value>, // uuid → original JSONL value
+    raw: HashMap<String, Arc<serde_json::Value>>, // uuid → original JSONL value
+    skipped_header: Option<String>, // leading non-JSON line, if any
+    ancestry: OnceCell<Ancestry>, // built on the first ancestry query
+    max_walk_depth: usize, // cap on entries yielded by one ancestor walk
+}
+
+/// Default cap on an ancestor walk, far beyond any real session but low
+/// enough that a pathological transcript can't hang the hook.
+pub const DEFAULT_MAX_WALK_DEPTH: usize = 100_000;
+
+/// Parent links and depths by entry index, so ancestry checks are integer
+/// walks instead of repeated UUID lookups.
+struct Ancestry {
+    parent: Vec<Option<usize>>,
+    /// Distance from the root of the entry's chain; `None` when the chain
+    /// runs into a cycle.
+    depth: Vec<Option<usize>>,
+}
+
+impl Ancestry {
+    fn build(entries: &[TranscriptEntry], by_uuid: &HashMap<String, usize>) -> Self {
+        let parent: Vec<Option<usize>> = entries
+            .iter()
+            .map(|e| e.parent_uuid().and_then(|p| by_uuid.get(p).copied()))
+            .collect();
+        // `Some(None)` marks an entry on the chain currently being walked.
+        let mut depth: Vec<Option<Option<usize>>> = vec![None; entries.len()];
+        for start in 0..entries.len() {
+            let mut path = Vec::new();
+            let mut next = Some(start);
+            // Depth of the node above the walked path (None: cycle).
+            let base = loop {
+                let Some(i) = next else { break Some(0) };
+                match depth[i] {
+                    Some(Some(d)) => break Some(d + 1),
+                    Some(None) => break None,
+                    None => {
+                        depth[i] = Some(None);
+                        path.push(i);
+                        next = parent[i];
+                    }
+                }
+            };
+            for (offset, &i) in path.iter().rev().enumerate() {
+                depth[i] = Some(base.map(|b| b + offset));
+            }
+        }
+        let depth = depth.into_iter().map(Option::flatten).collect();
+        Self { parent, depth }
+    }
 }
 
 // ===================================================================
@@ -495,6 +776,196 @@ fn assistant_blocks_by_entry<'a>(
     })
 }
 
+/// Page header for `Transcript::to_html`, up to the opening `<main>`.
+const HTML_HEAD: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Conversation</title>
+<style>
+body { font-family: system-ui, sans-serif; background: #f4f4f5; margin: 0; }
+main { max-width: 48rem; margin: 2rem auto; padding: 0 1rem; }
+.msg { border-radius: 0.75rem; padding: 0.75rem 1rem; margin: 0.75rem 0; }
+.user { background: #dbeafe; margin-left: 4rem; }
+.assistant { background: #fff; margin-right: 4rem; }
+.role { font-size: 0.75rem; font-weight: 600; color: #52525b; margin-bottom: 0.25rem; }
+.text { white-space: pre-wrap; margin: 0.25rem 0; }
+.tool summary { cursor: pointer; font-family: ui-monospace, monospace; font-size: 0.85rem; color: #3f3f46; }
+.tool pre { background: #f4f4f5; padding: 0.5rem; overflow-x: auto; font-size: 0.8rem; }
+.elided { text-align: center; font-style: italic; color: #71717a; }
+</style>
+</head>
+<body>
+<main>
+"#;
+
+/// One rendered piece of a note transcript, shared by `to_markdown` and
+/// `to_html`, which differ only in how they format these.
+enum NoteItem {
+    /// Marker left by `max_transcript_note_entries` trimming.
+    Elided(serde_json::Value),
+    /// A user prompt, trimmed.
+    User(String),
+    /// An assistant message's text and tool calls, in order.
+    Assistant(Vec<NotePart>),
+}
+
+enum NotePart {
+    Text(String),
+    Tool { name: String, label: String, input: serde_json::Value },
+}
+
+/// Walk raw transcript entries into the items a note rendering shows:
+/// non-empty user text, and assistant text and tool calls.  Tool results,
+/// thinking, and non-conversation entries are dropped, as are entries that
+/// fail to parse.
+fn note_items<V: Borrow<serde_json::Value>>(entries: &[V]) -> impl Iterator<Item = NoteItem> + '_ {
+    entries.iter().filter_map(|value| {
+        let value = value.borrow();
+        if value["type"] == "elided" {
+            return Some(NoteItem::Elided(value["count"].clone()));
+        }
+        let entry = serde_json::from_value::<TranscriptEntry>(value.clone()).ok()?;
+        match &entry {
+            TranscriptEntry::User(conv) => match &conv.message.content {
+                MessageContent::Text(t) if !t.trim().is_empty() => {
+                    Some(NoteItem::User(t.trim().to_string()))
+                }
+                _ => None,
+            },
+            TranscriptEntry::Assistant(conv) => {
+                let MessageContent::Blocks(blocks) = &conv.message.content else {
+                    return None;
+                };
+                let parts: Vec<NotePart> = blocks
+                    .iter()
+                    .filter_map(|block| match block {
+                        ContentBlock::Text(t) if !t.text.trim().is_empty() => {
+                            Some(NotePart::Text(t.text.trim().to_string()))
+                        }
+                        ContentBlock::ToolUse(tu) => Some(NotePart::Tool {
+                            name: tu.name.clone(),
+                            label: ToolCategories::label(&tu.name, &tu.input),
+                            input: tu.input.clone(),
+                        }),
+                        _ => None,
+                    })
+                    .collect();
+                (!parts.is_empty()).then_some(NoteItem::Assistant(parts))
+            }
+            _ => None,
+        }
+    })
+}
+
+/// Escape text for inclusion in HTML element content.
+fn html_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Text of a tool_result `content`, which is either a plain string or an
+/// array of `{"type": "text", "text": ...}` blocks (joined with newlines).
+fn tool_result_text(content: &serde_json::Value) -> Option<String> {
+    if let Some(text) = content.as_str() {
+        return Some(text.to_string());
+    }
+    let parts: Vec<&str> = content
+        .as_array()?
+        .iter()
+        .filter_map(|block| block.get("text").and_then(|t| t.as_str()))
+        .collect();
+    (!parts.is_empty()).then(|| parts.join("\n"))
+}
+
+/// Searchable text of an entry: user prompt text, assistant text blocks,
+/// and string values in tool_use inputs (e.g. a Bash `command`).
+fn entry_texts(entry: &TranscriptEntry) -> Vec<&str> {
+    fn strings<'a>(value: &'a serde_json::Value, out: &mut Vec<&'a str>) {
+        match value {
+            serde_json::Value::String(s) => out.push(s),
+            serde_json::Value::Array(items) => items.iter().for_each(|v| strings(v, out)),
+            serde_json::Value::Object(map) => map.values().for_each(|v| strings(v, out)),
+            _ => {}
+        }
+    }
+    let mut texts = Vec::new();
+    match entry {
+        TranscriptEntry::User(conv) => {
+            if let MessageContent::Text(t) = &conv.message.content {
+                texts.push(t.as_str());
+            }
+        }
+        TranscriptEntry::Assistant(conv) => {
+            if let MessageContent::Blocks(blocks) = &conv.message.content {
+                for block in blocks {
+                    match block {
+                        ContentBlock::Text(t) => texts.push(&t.text),
+                        ContentBlock::ToolUse(tu) => strings(&tu.input, &mut texts),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+    texts
+}
+
+/// One AskUserQuestion question and the answer the user chose.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct QaPair {
+    pub question: String,
+    pub answer: String,
+}
+
+impl std::fmt::Display for QaPair {
+    /// `"question"="answer"`, the form Claude Code reports answers in; just
+    /// the answer when the question isn't known.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.question.is_empty() {
+            f.write_str(&self.answer)
+        } else {
+            write!(f, "\"{}\"=\"{}\"", self.question, self.answer)
+        }
+    }
+}
+
+/// Find each asked question's answer in an AskUserQuestion result of the
+/// form `"Q1"="A1", "Q2"="A2"`.  `None` if any question can't be found.
+fn match_answers(questions: &[&str], text: &str) -> Option<Vec<QaPair>> {
+    if questions.is_empty() {
+        return None;
+    }
+    let mut pairs = Vec::new();
+    let mut rest = text;
+    for (i, question) in questions.iter().enumerate() {
+        rest = rest.strip_prefix(&format!("\"{question}\"=\""))?;
+        let end = match questions.get(i + 1) {
+            Some(next) => rest.find(&format!("\", \"{next}\"=\""))?,
+            None => rest.strip_suffix('"')?.len(),
+        };
+        pairs.push(QaPair {
+            question: question.to_string(),
+            answer: rest[..end].to_string(),
+        });
+        rest = &rest[end..];
+        if i + 1 < questions.len() {
+            rest = &rest[3..];
+        }
+    }
+    Some(pairs)
+}
+
 impl Transcript {
     /// An empty transcript (no entries).
     pub fn empty() -> Self {
@@ -502,22 +973,46 @@ impl Transcript {
             entries: Vec::new(),
             by_uuid: HashMap::new(),
             raw: HashMap::new(),
+            skipped_header: None,
+            ancestry: OnceCell::new(),
+            max_walk_depth: DEFAULT_MAX_WALK_DEPTH,
         }
     }
 
+    /// The leading non-JSON line `parse` skipped, if there was one.
+    pub fn skipped_header(&self) -> Option<&str> {
+        self.skipped_header.as_deref()
+    }
+
     /// Parse a JSONL transcript string. Returns the transcript and any
-    /// lines that failed to parse (with 1-based line number and error).
-    pub fn parse(contents: &str) -> (Self, Vec<(usize, String)>) {
+    /// lines that failed to parse, as (1-based physical line number, error,
+    /// the line's content truncated to 80 chars).  Blank lines are skipped
+    /// but still counted, so numbers match what an editor shows.  A line
+    /// holding several entries run together (`}{`) yields all of them.
+    pub fn parse(contents: &str) -> (Self, Vec<(usize, String, String)>) {
         let mut entries = Vec::new();
         let mut errors = Vec::new();
         let mut by_uuid = HashMap::new();
         let mut raw = HashMap::new();
+        let mut skipped_header = None;
+
+        // Tolerate a UTF-8 BOM and a stray non-JSON first line (e.g. a shell
+        // redirect header) so the first real entry still parses.
+        let contents = contents.strip_prefix('\u{feff}').unwrap_or(contents);
+        let mut seen_content = false;
 
         for (i, line) in contents.lines().enumerate() {
             let line = line.trim();
             if line.is_empty() {
                 continue;
             }
+            if !seen_content {
+                seen_content = true;
+                if !line.starts_with('{') {
+                    skipped_header = Some(line.to_string());
+                    continue;
+                }
+            }
             // Parse the line once as a raw Value, then deserialize the typed
             // entry from the already-parsed tree to avoid double tokenization.
             match serde_json::from_str::<TranscriptEntry>(line) {
@@ -528,16 +1023,67 @@ impl Transcript {
                         // UUID (needed by turn_raw). This avoids cloning every
                         // parsed Value upfront.
                         if let Ok(val) = serde_json::from_str::<serde_json::Value>(line) {
-                            raw.insert(uuid.to_string(), val);
+                            raw.insert(uuid.to_string(), Arc::new(val));
                         }
                     }
                     entries.push(entry);
                 }
-                Err(e) => errors.push((i + 1, format!("{e}"))),
+                Err(e) => match Self::split_concatenated(line) {
+                    // Entries written back to back with no newline (`}{`).
+                    Some(values) => {
+                        for (entry, val) in values {
+                            if let Some(uuid) = entry.uuid() {
+                                by_uuid.insert(uuid.to_string(), entries.len());
+                                raw.insert(uuid.to_string(), Arc::new(val));
+                            }
+                            entries.push(entry);
+                        }
+                    }
+                    None => errors.push((i + 1, format!("{e}"), ToolCategories::truncate(line, 80))),
+                },
             }
         }
 
-        (Self { entries, by_uuid, raw }, errors)
+        (
+            Self {
+                entries,
+                by_uuid,
+                raw,
+                skipped_header,
+                ancestry: OnceCell::new(),
+                max_walk_depth: DEFAULT_MAX_WALK_DEPTH,
+            },
+            errors,
+        )
+    }
+
+    /// Recover several entries from a line that failed to parse because
+    /// they were concatenated without newline separators.  `None` unless
+    /// the line contains `}{` and every value in it is a valid entry.
+    fn split_concatenated(line: &str) -> Option<Vec<(TranscriptEntry, serde_json::Value)>> {
+        if !line.contains("}{") {
+            return None;
+        }
+        serde_json::Deserializer::from_str(line)
+            .into_iter::<serde_json::Value>()
+            .map(|val| {
+                let val = val.ok()?;
+                let entry = TranscriptEntry::deserialize(&val).ok()?;
+                Some((entry, val))
+            })
+            .collect()
+    }
+
+    /// Like `parse`, but any line that fails to parse is an error: returns
+    /// every failure as (1-based physical line number, error).  A skipped
+    /// header line is still tolerated.
+    pub fn parse_strict(contents: &str) -> Result<Self, Vec<(usize, String)>> {
+        let (transcript, errors) = Self::parse(contents);
+        if errors.is_empty() {
+            Ok(transcript)
+        } else {
+            Err(errors.into_iter().map(|(line, err, _)| (line, err)).collect())
+        }
     }
 
     /// Look up a typed entry by UUID.
@@ -547,7 +1093,7 @@ impl Transcript {
 
     /// Look up the original raw JSON value by UUID.
     pub fn get_raw(&self, uuid: &str) -> Option<&serde_json::Value> {
-        self.raw.get(uuid)
+        self.raw.get(uuid).map(|v| &**v)
     }
 
     /// The UUID of the last entry in the transcript that has one.
@@ -567,6 +1113,114 @@ impl Transcript {
         })
     }
 
+    /// The UUID of the leaf entry (one that no other entry names as its
+    /// parent) whose ancestor chain is longest.  Ties go to the leaf that
+    /// appears later in the file, matching `tail`.
+    pub fn deepest_chain(&self) -> Option<&str> {
+        let parents: HashSet<&str> = self.entries.iter().filter_map(|e| e.parent_uuid()).collect();
+        let mut depths: HashMap<&str, usize> = HashMap::new();
+        let mut best: Option<(usize, &str)> = None;
+        for uuid in self.entries.iter().filter_map(|e| e.uuid()) {
+            if parents.contains(uuid) {
+                continue;
+            }
+            let depth = self.chain_depth(uuid, &mut depths);
+            if best.is_none_or(|(d, _)| depth >= d) {
+                best = Some((depth, uuid));
+            }
+        }
+        best.map(|(_, uuid)| uuid)
+    }
+
+    /// Number of entries on the ancestor chain starting at `uuid`
+    /// (inclusive), memoized in `depths` so shared prefixes are walked once.
+    fn chain_depth<'a>(&'a self, uuid: &'a str, depths: &mut HashMap<&'a str, usize>) -> usize {
+        let mut path: Vec<&str> = Vec::new();
+        let mut base = 0;
+        for entry in self.ancestors(uuid) {
+            let Some(u) = entry.uuid() else { break };
+            if let Some(&d) = depths.get(u) {
+                base = d;
+                break;
+            }
+            path.push(u);
+        }
+        for (i, u) in path.iter().rev().enumerate() {
+            depths.insert(u, base + i + 1);
+        }
+        base + path.len()
+    }
+
+    /// Choose the tail UUID according to `strategy`.
+    pub fn select_tail(&self, strategy: TailStrategy) -> Option<&str> {
+        match strategy {
+            TailStrategy::Last => self.tail(),
+            TailStrategy::Deepest => self.deepest_chain(),
+        }
+    }
+
+    /// The session ID of the first user or assistant entry that carries
+    /// one — the session the transcript was started by.
+    pub fn session_id(&self) -> Option<&str> {
+        self.entries
+            .iter()
+            .filter(|e| matches!(e, TranscriptEntry::User(_) | TranscriptEntry::Assistant(_)))
+            .find_map(TranscriptEntry::session_id)
+    }
+
+    /// Indices into `entries()` where the session ID changes from the
+    /// previous entry that has one — i.e. where a resumed session was
+    /// appended to this file.  Entries without a session ID are skipped.
+    pub fn resume_points(&self) -> Vec<usize> {
+        let mut points = Vec::new();
+        let mut prev: Option<&str> = None;
+        for (i, entry) in self.entries.iter().enumerate() {
+            let Some(sid) = entry.session_id() else {
+                continue;
+            };
+            if prev.is_some_and(|p| p != sid) {
+                points.push(i);
+            }
+            prev = Some(sid);
+        }
+        points
+    }
+
+    /// The entries of `turn` written after the last resume point — the
+    /// part of it that belongs to the session running now.
+    pub fn since_last_resume<'a>(&self, turn: &[&'a TranscriptEntry]) -> Vec<&'a TranscriptEntry> {
+        let boundary = self.resume_points().last().copied().unwrap_or(0);
+        turn.iter()
+            .copied()
+            .filter(|e| {
+                e.uuid()
+                    .and_then(|u| self.by_uuid.get(u))
+                    .is_some_and(|&i| i >= boundary)
+            })
+            .collect()
+    }
+
+    /// The transcript as it stood when `uuid` was written: entries in file
+    /// order up to and including it.  `None` if `uuid` isn't present.
+    /// Entries without a UUID (file-history snapshots, queue operations)
+    /// are dropped.
+    pub fn truncated_at(&self, uuid: &str) -> Option<Transcript> {
+        let &end = self.by_uuid.get(uuid)?;
+        let lines: Vec<String> = self.entries[..=end]
+            .iter()
+            .filter_map(|e| e.uuid().and_then(|u| self.get_raw(u)))
+            .map(|v| v.to_string())
+            .collect();
+        let mut truncated = Self::parse(&lines.join("\n")).0;
+        truncated.max_walk_depth = self.max_walk_depth;
+        Some(truncated)
+    }
+
+    /// Cap the number of entries a single `ancestors` / `turn` walk yields.
+    pub fn set_max_walk_depth(&mut self, depth: usize) {
+        self.max_walk_depth = depth;
+    }
+
     /// All typed entries in parse order.
     pub fn entries(&self) -> &[TranscriptEntry] {
         &self.entries
@@ -578,18 +1232,70 @@ impl Transcript {
 
     /// Iterate ancestors starting from `uuid`, walking `parentUuid` links.
     /// Yields entries from the starting node upward (inclusive).
-    /// Tracks visited UUIDs to guard against cycles.
+    /// Tracks visited UUIDs to guard against cycles, and stops (with a
+    /// warning) after the walk depth cap.
     pub fn ancestors<'a>(&'a self, uuid: &'a str) -> AncestorIter<'a> {
         AncestorIter {
             transcript: self,
             next_uuid: Some(uuid),
             visited: HashSet::new(),
+            remaining: self.max_walk_depth,
         }
     }
 
+    /// Report entries whose parent UUID isn't in the transcript, and
+    /// multiple parentless roots.  An empty result means every ancestor walk
+    /// ends at the single root.
+    pub fn validate_dag(&self) -> Vec<DagIssue> {
+        let mut issues = Vec::new();
+        let mut roots = Vec::new();
+        for entry in &self.entries {
+            let Some(uuid) = entry.uuid() else {
+                continue;
+            };
+            match entry.parent_uuid() {
+                Some(parent) if !self.by_uuid.contains_key(parent) => {
+                    issues.push(DagIssue::DanglingParent {
+                        uuid: uuid.to_string(),
+                        parent: parent.to_string(),
+                    });
+                }
+                Some(_) => {}
+                None => roots.push(uuid.to_string()),
+            }
+        }
+        if roots.len() > 1 {
+            issues.push(DagIssue::MultipleRoots(roots));
+        }
+        issues
+    }
+
     /// Check whether `ancestor` is reachable from `uuid` via parentUuid links.
     pub fn is_ancestor(&self, uuid: &str, ancestor: &str) -> bool {
-        self.ancestors(uuid).any(|e| e.uuid() == Some(ancestor))
+        let (Some(&from), Some(&target)) = (self.by_uuid.get(uuid), self.by_uuid.get(ancestor))
+        else {
+            return false;
+        };
+        let ancestry = self
+            .ancestry
+            .get_or_init(|| Ancestry::build(&self.entries, &self.by_uuid));
+        match (ancestry.depth[from], ancestry.depth[target]) {
+            (Some(from_depth), Some(target_depth)) => {
+                if target_depth > from_depth {
+                    return false;
+                }
+                let mut i = from;
+                for _ in target_depth..from_depth {
+                    match ancestry.parent[i] {
+                        Some(p) => i = p,
+                        None => return false,
+                    }
+                }
+                i == target
+            }
+            // Cyclic chains keep the visited-set walk.
+            _ => self.ancestors(uuid).any(|e| e.uuid() == Some(ancestor)),
+        }
     }
 
     // ---------------------------------------------------------------
@@ -620,9 +1326,10 @@ impl Transcript {
     }
 
     /// Like `turn`, but returns the original raw JSON values in
-    /// chronological order.
-    pub fn turn_raw(&self, tail: &str, prompt_uuid: Option<&str>) -> Vec<serde_json::Value> {
-        let mut values: Vec<serde_json::Value> = self
+    /// chronological order.  Values are shared with the transcript, so
+    /// this doesn't copy the JSON trees.
+    pub fn turn_raw(&self, tail: &str, prompt_uuid: Option<&str>) -> Vec<Arc<serde_json::Value>> {
+        let mut values: Vec<Arc<serde_json::Value>> = self
             .turn(tail, prompt_uuid)
             .iter()
             .filter_map(|e| e.uuid().and_then(|uuid| self.raw.get(uuid).cloned()))
@@ -656,12 +1363,27 @@ impl Transcript {
     /// fallback when UserPromptSubmit didn't fire (e.g. plan implementation
     /// prompts auto-injected after ExitPlanMode approval).
     pub fn last_user_text(&self) -> Option<(&str, &str, Option<&str>)> {
+        self.last_user_text_matching(|_| true)
+    }
+
+    /// Like `last_user_text`, but skipping slash-command prompts (`/test`,
+    /// `/review ...`), which make poor commit subjects.
+    pub fn last_non_command_user_text(&self) -> Option<(&str, &str, Option<&str>)> {
+        self.last_user_text_matching(|text| !text.trim_start().starts_with('/'))
+    }
+
+    fn last_user_text_matching(
+        &self,
+        accept: impl Fn(&str) -> bool,
+    ) -> Option<(&str, &str, Option<&str>)> {
         self.entries.iter().rev().find_map(|entry| {
             if let TranscriptEntry::User(conv) = entry {
                 if conv.is_compact_summary {
                     return None;
                 }
-                if let MessageContent::Text(t) = &conv.message.content {
+                if let MessageContent::Text(t) = &conv.message.content
+                    && accept(t)
+                {
                     return Some((
                         conv.uuid.as_str(),
                         t.as_str(),
@@ -696,6 +1418,77 @@ impl Transcript {
         result
     }
 
+    /// (prompt, reply) pairs along the main chain ending at the
+    /// conversation tail, oldest first: each plain-text user prompt with
+    /// the text of the assistant replies before the next prompt, joined by
+    /// blank lines.  Tool results, meta and compaction entries, sidechains
+    /// and API error replies are left out, as are prompts with no reply
+    /// text.
+    pub fn as_conversation_pairs(&self) -> Vec<(String, String)> {
+        let Some(tail) = self.conversation_tail() else {
+            return Vec::new();
+        };
+        let chain: Vec<&TranscriptEntry> = self.ancestors(tail).collect();
+        let mut pairs = Vec::new();
+        let mut current: Option<(&str, Vec<&str>)> = None;
+        let mut flush = |current: Option<(&str, Vec<&str>)>| {
+            if let Some((prompt, replies)) = current
+                && !replies.is_empty()
+            {
+                pairs.push((prompt.to_string(), replies.join("\n\n")));
+            }
+        };
+        for entry in chain.into_iter().rev() {
+            match entry {
+                TranscriptEntry::User(conv) if !conv.is_sidechain => {
+                    if conv.is_compact_summary || conv.is_meta == Some(true) {
+                        continue;
+                    }
+                    if let MessageContent::Text(t) = &conv.message.content {
+                        flush(current.take());
+                        current = Some((t.as_str(), Vec::new()));
+                    }
+                }
+                TranscriptEntry::Assistant(conv) if !conv.is_sidechain && !conv.is_api_error_message => {
+                    let (Some((_, replies)), MessageContent::Blocks(blocks)) =
+                        (current.as_mut(), &conv.message.content)
+                    else {
+                        continue;
+                    };
+                    replies.extend(blocks.iter().filter_map(|b| match b {
+                        ContentBlock::Text(t) => Some(t.text.as_str()),
+                        _ => None,
+                    }));
+                }
+                _ => {}
+            }
+        }
+        flush(current);
+        pairs
+    }
+
+    /// UUIDs of entries (in file order) whose user text, assistant text, or
+    /// tool input contains `needle`, ignoring case.
+    pub fn search(&self, needle: &str) -> Vec<&str> {
+        let needle = needle.to_lowercase();
+        self.entries
+            .iter()
+            .filter(|e| entry_texts(e).iter().any(|t| t.to_lowercase().contains(&needle)))
+            .filter_map(|e| e.uuid())
+            .collect()
+    }
+
+    /// The first line of entry `uuid` containing `needle` (ignoring case),
+    /// trimmed and truncated to `max` chars.  For displaying search hits.
+    pub fn search_snippet(&self, uuid: &str, needle: &str, max: usize) -> Option<String> {
+        let needle = needle.to_lowercase();
+        entry_texts(self.get(uuid)?)
+            .into_iter()
+            .flat_map(str::lines)
+            .find(|line| line.to_lowercase().contains(&needle))
+            .map(|line| ToolCategories::truncate(line.trim(), max))
+    }
+
     /// Check whether a UUID appears as any user entry in the transcript.
     pub fn uuid_exists(&self, uuid: &str) -> bool {
         self.by_uuid.contains_key(uuid)
@@ -725,50 +1518,127 @@ impl Transcript {
     // Q&A extraction
     // ---------------------------------------------------------------
 
-    /// Extract Q&A answer strings from AskUserQuestion interactions in a
-    /// turn.  Collects the user's answer text from tool_result entries that
-    /// match AskUserQuestion tool_use IDs, stripping Claude Code's framing.
-    /// Returns answers in chronological order.
-    pub fn extract_qa(turn: &[&TranscriptEntry]) -> Vec<String> {
-        let mut ask_ids: HashSet<String> = HashSet::new();
-        for block in assistant_blocks(turn) {
-            if let ContentBlock::ToolUse(tu) = block {
-                if tu.name == "AskUserQuestion" {
-                    ask_ids.insert(tu.id.clone());
+    /// Render raw transcript entries (as stored in the transcript note) as
+    /// readable markdown: user prompts, assistant text, and one bullet per
+    /// tool call.  Tool results, thinking, and non-conversation entries are
+    /// omitted; entries that fail to parse are skipped.
+    pub fn to_markdown<V: Borrow<serde_json::Value>>(entries: &[V]) -> String {
+        let sections: Vec<String> = note_items(entries)
+            .map(|item| match item {
+                NoteItem::Elided(count) => format!("_{count} earlier entries elided_"),
+                NoteItem::User(text) => format!("### User\n\n{text}"),
+                NoteItem::Assistant(parts) => {
+                    let parts: Vec<String> = parts
+                        .into_iter()
+                        .map(|part| match part {
+                            NotePart::Text(text) => text,
+                            NotePart::Tool { name, label, .. } if label.is_empty() => format!("- {name}"),
+                            NotePart::Tool { name, label, .. } => format!("- {name} {label}"),
+                        })
+                        .collect();
+                    format!("### Assistant\n\n{}", parts.join("\n\n"))
                 }
+            })
+            .collect();
+        sections.join("\n\n")
+    }
+
+    /// Render raw transcript entries as a self-contained HTML page (inline
+    /// CSS): user and assistant bubbles, with each tool call as a collapsible
+    /// `<details>` holding its input.  Covers the same entries as
+    /// `to_markdown`.
+    pub fn to_html<V: Borrow<serde_json::Value>>(entries: &[V]) -> String {
+        let mut body = String::new();
+        for item in note_items(entries) {
+            match item {
+                NoteItem::Elided(count) => body.push_str(&format!(
+                    "<p class=\"elided\">{count} earlier entries elided</p>\n"
+                )),
+                NoteItem::User(text) => body.push_str(&format!(
+                    "<div class=\"msg user\"><div class=\"role\">User</div><div class=\"text\">{}</div></div>\n",
+                    html_escape(&text)
+                )),
+                NoteItem::Assistant(parts) => {
+                    let parts: String = parts
+                        .into_iter()
+                        .map(|part| match part {
+                            NotePart::Text(text) => {
+                                format!("<div class=\"text\">{}</div>", html_escape(&text))
+                            }
+                            NotePart::Tool { name, label, input } => format!(
+                                "<details class=\"tool\"><summary>{} {}</summary><pre>{}</pre></details>",
+                                html_escape(&name),
+                                html_escape(&label),
+                                html_escape(&serde_json::to_string_pretty(&input).unwrap_or_default())
+                            ),
+                        })
+                        .collect();
+                    body.push_str(&format!(
+                        "<div class=\"msg assistant\"><div class=\"role\">Assistant</div>{parts}</div>\n"
+                    ));
+                }
+            }
+        }
+        format!("{HTML_HEAD}{body}</main>\n</body>\n</html>\n")
+    }
+
+    /// Extract the Q&A pairs from AskUserQuestion interactions in a turn,
+    /// in chronological order.  Questions come from the tool_use input;
+    /// each answer is located in the matching tool_result by its question.
+    /// A result that can't be matched up that way is kept whole as the
+    /// answer (to the lone question, if only one was asked).
+    pub fn extract_qa(turn: &[&TranscriptEntry]) -> Vec<QaPair> {
+        let mut asked: HashMap<&str, Vec<&str>> = HashMap::new();
+        for block in assistant_blocks(turn) {
+            if let ContentBlock::ToolUse(tu) = block
+                && tu.name == "AskUserQuestion"
+            {
+                let questions = tu.input["questions"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|q| q["question"].as_str())
+                    .collect();
+                asked.insert(tu.id.as_str(), questions);
             }
         }
-        if ask_ids.is_empty() {
+        if asked.is_empty() {
             return Vec::new();
         }
         let mut qa = Vec::new();
-        for entry in turn.iter() {
-            if let TranscriptEntry::User(conv) = entry {
-                if let MessageContent::Blocks(blocks) = &conv.message.content {
-                    for block in blocks {
-                        if let ContentBlock::ToolResult(tr) = block {
-                            if ask_ids.contains(&tr.tool_use_id) {
-                                if let Some(text) = tr.content.as_str() {
-                                    let cleaned = text
-                                        .strip_prefix(
-                                            "User has answered your questions: ",
-                                        )
-                                        .unwrap_or(text);
-                                    let cleaned = cleaned
-                                        .strip_suffix(
-                                            ". You can now continue with the user's answers in mind.",
-                                        )
-                                        .unwrap_or(cleaned);
-                                    qa.push(cleaned.to_string());
-                                }
-                            }
-                        }
+        // Turn is reverse-chronological; walk it oldest-first so answers to
+        // several questions come out in the order they were given.
+        for entry in turn.iter().rev() {
+            let TranscriptEntry::User(conv) = entry else {
+                continue;
+            };
+            let MessageContent::Blocks(blocks) = &conv.message.content else {
+                continue;
+            };
+            for block in blocks {
+                if let ContentBlock::ToolResult(tr) = block
+                    && let Some(questions) = asked.get(tr.tool_use_id.as_str())
+                    && let Some(text) = tool_result_text(&tr.content)
+                {
+                    let cleaned = text
+                        .strip_prefix("User has answered your questions: ")
+                        .unwrap_or(&text);
+                    let cleaned = cleaned
+                        .strip_suffix(". You can now continue with the user's answers in mind.")
+                        .unwrap_or(cleaned);
+                    match match_answers(questions, cleaned) {
+                        Some(pairs) => qa.extend(pairs),
+                        None => qa.push(QaPair {
+                            question: match questions.as_slice() {
+                                [only] => only.to_string(),
+                                _ => String::new(),
+                            },
+                            answer: cleaned.to_string(),
+                        }),
                     }
                 }
             }
         }
-        // Turn is reverse-chronological; put answers in chronological order.
-        qa.reverse();
         qa
     }
 
@@ -776,6 +1646,436 @@ impl Transcript {
     // Turn summarization
     // ---------------------------------------------------------------
 
+    /// Distinct extended-thinking levels (e.g. "high") enabled on user
+    /// entries in the turn, in chronological order.  Entries whose thinking
+    /// was disabled are ignored.
+    pub fn thinking_levels(turn: &[&TranscriptEntry]) -> Vec<String> {
+        let mut levels: Vec<String> = Vec::new();
+        for entry in turn.iter().rev() {
+            let TranscriptEntry::User(conv) = entry else {
+                continue;
+            };
+            if let Some(tm) = &conv.thinking_metadata
+                && !tm.disabled
+                && !levels.contains(&tm.level)
+            {
+                levels.push(tm.level.clone());
+            }
+        }
+        levels
+    }
+
+    /// The earliest and latest `git_branch` recorded in the turn, when they
+    /// differ — Claude may have worked across a branch switch.
+    pub fn branch_change<'a>(turn: &[&'a TranscriptEntry]) -> Option<(&'a str, &'a str)> {
+        // Turn entries come newest-first.
+        let latest = turn.iter().find_map(|e| e.git_branch())?;
+        let earliest = turn.iter().rev().find_map(|e| e.git_branch())?;
+        (earliest != latest).then_some((earliest, latest))
+    }
+
+    /// (from, to) for each change of `message.model` between consecutive
+    /// assistant entries of the turn, oldest first.  `<synthetic>`
+    /// (locally generated error replies) is not a model and is skipped.
+    pub fn model_switches(turn: &[&TranscriptEntry]) -> Vec<(String, String)> {
+        let mut switches = Vec::new();
+        let mut current: Option<&str> = None;
+        // Turn entries come newest-first.
+        for entry in turn.iter().rev() {
+            let TranscriptEntry::Assistant(conv) = entry else {
+                continue;
+            };
+            let Some(model) = conv.message.model.as_deref().filter(|m| *m != "<synthetic>") else {
+                continue;
+            };
+            if let Some(prev) = current
+                && prev != model
+            {
+                switches.push((prev.to_string(), model.to_string()));
+            }
+            current = Some(model);
+        }
+        switches
+    }
+
+    /// Files whose content at the end of the turn equals their content
+    /// before its first Edit/Write — the turn's changes to them cancel out.
+    /// Derived from the tool results' `originalFile`; files created by the
+    /// turn are never reported.
+    pub fn reverted_files(turn: &[&TranscriptEntry]) -> BTreeSet<String> {
+        let mut first_original: HashMap<&str, Option<&str>> = HashMap::new();
+        let mut last_content: HashMap<&str, Option<String>> = HashMap::new();
+        // Turn entries come newest-first.
+        for entry in turn.iter().rev() {
+            let TranscriptEntry::User(conv) = entry else {
+                continue;
+            };
+            let (path, original, content) = match &conv.tool_use_result {
+                Some(ToolUseResult::Edit(e)) => {
+                    let content = e.original_file.as_ref().map(|o| {
+                        if e.replace_all == Some(true) {
+                            o.replace(&e.old_string, &e.new_string)
+                        } else {
+                            o.replacen(&e.old_string, &e.new_string, 1)
+                        }
+                    });
+                    (e.file_path.as_str(), e.original_file.as_deref(), content)
+                }
+                Some(ToolUseResult::Write(w)) => {
+                    (w.file_path.as_str(), w.original_file.as_deref(), Some(w.content.clone()))
+                }
+                _ => continue,
+            };
+            first_original.entry(path).or_insert(original);
+            last_content.insert(path, content);
+        }
+        first_original
+            .into_iter()
+            .filter(|(path, original)| {
+                original.is_some() && last_content.get(path).is_some_and(|c| c.as_deref() == *original)
+            })
+            .map(|(path, _)| path.to_string())
+            .collect()
+    }
+
+    /// Absolute paths the turn edited or wrote that lie outside `workdir`.
+    /// Relative paths are taken to be inside it.
+    pub fn external_files(turn: &[&TranscriptEntry], workdir: &Path) -> BTreeSet<String> {
+        let mut external = BTreeSet::new();
+        for entry in turn {
+            let TranscriptEntry::Assistant(conv) = entry else {
+                continue;
+            };
+            let MessageContent::Blocks(blocks) = &conv.message.content else {
+                continue;
+            };
+            for block in blocks {
+                let ContentBlock::ToolUse(tu) = block else {
+                    continue;
+                };
+                let field = match tu.name.as_str() {
+                    "Edit" | "Write" => "file_path",
+                    "NotebookEdit" => "notebook_path",
+                    _ => continue,
+                };
+                if let Some(path) = tu.input[field].as_str()
+                    && Path::new(path).is_absolute()
+                    && !Path::new(path).starts_with(workdir)
+                {
+                    external.insert(path.to_string());
+                }
+            }
+        }
+        external
+    }
+
+    /// Size in bytes of each file the turn wrote with `Write`, keyed by its
+    /// summary label.  Sizes come from the matching tool result; the latest
+    /// write to a file wins.
+    pub fn write_sizes(turn: &[&TranscriptEntry]) -> HashMap<String, usize> {
+        let mut labels: HashMap<&str, String> = HashMap::new();
+        let mut sizes = HashMap::new();
+        // Turn entries come newest-first; walk oldest-first so each result
+        // follows its tool_use.
+        for entry in turn.iter().rev() {
+            let (TranscriptEntry::User(conv) | TranscriptEntry::Assistant(conv)) = entry else {
+                continue;
+            };
+            let MessageContent::Blocks(blocks) = &conv.message.content else {
+                continue;
+            };
+            match entry {
+                TranscriptEntry::Assistant(_) => {
+                    for block in blocks {
+                        if let ContentBlock::ToolUse(tu) = block
+                            && tu.name == "Write"
+                            && let Some((_, label)) = ToolCategories::labels(&tu.name, &tu.input).pop()
+                        {
+                            labels.insert(&tu.id, label);
+                        }
+                    }
+                }
+                _ => {
+                    let Some(ToolUseResult::Write(w)) = &conv.tool_use_result else {
+                        continue;
+                    };
+                    for block in blocks {
+                        if let ContentBlock::ToolResult(tr) = block
+                            && let Some(label) = labels.get(tr.tool_use_id.as_str())
+                        {
+                            sizes.insert(label.clone(), w.content.len());
+                        }
+                    }
+                }
+            }
+        }
+        sizes
+    }
+
+    /// `TODO` / `FIXME` lines the turn's Edit and Write calls introduced, as
+    /// (file path, trimmed line) in call order.  A line an Edit's
+    /// `old_string` already had doesn't count.
+    pub fn introduced_todos(turn: &[&TranscriptEntry]) -> Vec<(String, String)> {
+        let mut todos: Vec<(String, String)> = Vec::new();
+        // Turn entries come newest-first.
+        for entry in turn.iter().rev() {
+            let TranscriptEntry::Assistant(conv) = entry else {
+                continue;
+            };
+            let MessageContent::Blocks(blocks) = &conv.message.content else {
+                continue;
+            };
+            for block in blocks {
+                let ContentBlock::ToolUse(tu) = block else {
+                    continue;
+                };
+                let (new, old) = match tu.name.as_str() {
+                    "Edit" => (tu.input["new_string"].as_str(), tu.input["old_string"].as_str()),
+                    "Write" => (tu.input["content"].as_str(), None),
+                    _ => continue,
+                };
+                let (Some(path), Some(new)) = (tu.input["file_path"].as_str(), new) else {
+                    continue;
+                };
+                let old: HashSet<&str> = old.unwrap_or("").lines().map(str::trim).collect();
+                for line in new.lines().map(str::trim) {
+                    if (line.contains("TODO") || line.contains("FIXME"))
+                        && !old.contains(line)
+                        && !todos.iter().any(|(p, l)| p == path && l == line)
+                    {
+                        todos.push((path.to_string(), line.to_string()));
+                    }
+                }
+            }
+        }
+        todos
+    }
+
+    /// URLs fetched and queries searched by the turn's WebFetch/WebSearch
+    /// calls (client or server side), each deduplicated in call order.
+    pub fn web_activity(turn: &[&TranscriptEntry]) -> (Vec<String>, Vec<String>) {
+        let mut urls: Vec<String> = Vec::new();
+        let mut queries: Vec<String> = Vec::new();
+        // Turn entries come newest-first.
+        for entry in turn.iter().rev() {
+            let TranscriptEntry::Assistant(conv) = entry else {
+                continue;
+            };
+            let MessageContent::Blocks(blocks) = &conv.message.content else {
+                continue;
+            };
+            for block in blocks {
+                let (name, input) = match block {
+                    ContentBlock::ToolUse(tu) => (tu.name.as_str(), &tu.input),
+                    ContentBlock::ServerToolUse(stu) => (stu.client_tool_name(), &stu.input),
+                    _ => continue,
+                };
+                let (list, field) = match name {
+                    "WebFetch" => (&mut urls, "url"),
+                    "WebSearch" => (&mut queries, "query"),
+                    _ => continue,
+                };
+                if let Some(value) = input[field].as_str()
+                    && !list.iter().any(|v| v == value)
+                {
+                    list.push(value.to_string());
+                }
+            }
+        }
+        (urls, queries)
+    }
+
+    /// The most recent `permissionMode` ("default", "acceptEdits", ...)
+    /// recorded on a conversation entry in the turn.
+    pub fn latest_permission_mode<'a>(turn: &[&'a TranscriptEntry]) -> Option<&'a str> {
+        // Turn entries come newest-first.
+        turn.iter().find_map(|entry| match entry {
+            TranscriptEntry::User(conv) | TranscriptEntry::Assistant(conv) => {
+                conv.permission_mode.as_deref()
+            }
+            _ => None,
+        })
+    }
+
+    /// `stop_reason` of the turn's final assistant message (`end_turn`,
+    /// `tool_use`, `max_tokens`, ...).  A `max_tokens` stop means the
+    /// reply was cut off mid-work.
+    pub fn tail_stop_reason<'a>(turn: &[&'a TranscriptEntry]) -> Option<&'a str> {
+        // Turn entries come newest-first.
+        turn.iter().find_map(|entry| match entry {
+            TranscriptEntry::Assistant(conv) => Some(conv.message.stop_reason.as_deref()),
+            _ => None,
+        })?
+    }
+
+    /// Tool calls in the turn in chronological order, as `(entry uuid,
+    /// tool name, label)`.  Labels are the ones the turn summary uses
+    /// ("main.rs", a Bash description, ...); tools the summary doesn't
+    /// categorize get an empty label.
+    pub fn tool_timeline(turn: &[&TranscriptEntry]) -> Vec<(String, String, String)> {
+        let mut timeline = Vec::new();
+        // Turn entries come newest-first.
+        for entry in turn.iter().rev() {
+            let TranscriptEntry::Assistant(conv) = entry else {
+                continue;
+            };
+            let MessageContent::Blocks(blocks) = &conv.message.content else {
+                continue;
+            };
+            for block in blocks {
+                if let ContentBlock::ToolUse(tu) = block {
+                    timeline.push((
+                        conv.uuid.clone(),
+                        tu.name.clone(),
+                        ToolCategories::label(&tu.name, &tu.input),
+                    ));
+                }
+            }
+        }
+        timeline
+    }
+
+    /// Failed and total tool calls in the turn, as `(failures, total)`.  A
+    /// call counts as failed when the tool_result answering its id has
+    /// `is_error: true`; a server-side web search, when its result is an
+    /// error object rather than a list of hits.
+    pub fn tool_error_stats(turn: &[&TranscriptEntry]) -> (usize, usize) {
+        let mut ids = HashSet::new();
+        let mut failed = HashSet::new();
+        for entry in turn {
+            let (TranscriptEntry::User(conv) | TranscriptEntry::Assistant(conv)) = entry else {
+                continue;
+            };
+            let MessageContent::Blocks(blocks) = &conv.message.content else {
+                continue;
+            };
+            for block in blocks {
+                match block {
+                    ContentBlock::ToolUse(tu) => {
+                        ids.insert(tu.id.as_str());
+                    }
+                    ContentBlock::ServerToolUse(stu) => {
+                        ids.insert(stu.id.as_str());
+                    }
+                    ContentBlock::ToolResult(tr) if tr.is_error == Some(true) => {
+                        failed.insert(tr.tool_use_id.as_str());
+                    }
+                    ContentBlock::WebSearchToolResult(wr) if !wr.content.is_array() => {
+                        failed.insert(wr.tool_use_id.as_str());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        (failed.intersection(&ids).count(), ids.len())
+    }
+
+    /// Bash commands issued in the turn, verbatim and in chronological
+    /// order.  Background commands get a trailing ` &`; commands whose
+    /// result reports an interruption get a trailing `  # interrupted`.
+    pub fn commands_run(turn: &[&TranscriptEntry]) -> Vec<String> {
+        // Tool-use ids whose Bash result was interrupted.
+        let mut interrupted = HashSet::new();
+        for entry in turn {
+            let TranscriptEntry::User(conv) = entry else {
+                continue;
+            };
+            let Some(ToolUseResult::Bash(result)) = &conv.tool_use_result else {
+                continue;
+            };
+            if result.interrupted != Some(true) {
+                continue;
+            }
+            if let MessageContent::Blocks(blocks) = &conv.message.content {
+                for block in blocks {
+                    if let ContentBlock::ToolResult(tr) = block {
+                        interrupted.insert(tr.tool_use_id.as_str());
+                    }
+                }
+            }
+        }
+
+        let mut commands = Vec::new();
+        // Turn entries come newest-first.
+        for entry in turn.iter().rev() {
+            let TranscriptEntry::Assistant(conv) = entry else {
+                continue;
+            };
+            let MessageContent::Blocks(blocks) = &conv.message.content else {
+                continue;
+            };
+            for block in blocks {
+                let ContentBlock::ToolUse(tu) = block else {
+                    continue;
+                };
+                let Some(command) = tu.input["command"].as_str().filter(|_| tu.name == "Bash") else {
+                    continue;
+                };
+                let mut line = command.to_string();
+                if tu.input["run_in_background"].as_bool() == Some(true) {
+                    line.push_str(" &");
+                }
+                if interrupted.contains(tu.id.as_str()) {
+                    line.push_str("  # interrupted");
+                }
+                commands.push(line);
+            }
+        }
+        commands
+    }
+
+    /// The `git add` / `git commit` subcommands Claude ran through Bash in
+    /// the turn, each once, in order of first use.  Every stage of a
+    /// pipeline or command list is checked, so `git add -A && git commit`
+    /// reports both.
+    pub fn git_commits_run(turn: &[&TranscriptEntry]) -> Vec<&'static str> {
+        let mut found = Vec::new();
+        // Turn entries come newest-first.
+        let chronological: Vec<&TranscriptEntry> = turn.iter().rev().copied().collect();
+        for block in assistant_blocks(&chronological) {
+            let ContentBlock::ToolUse(tu) = block else {
+                continue;
+            };
+            let Some(command) = tu.input["command"].as_str().filter(|_| tu.name == "Bash") else {
+                continue;
+            };
+            for (stage, _) in ToolCategories::command_stages(command) {
+                let mut words = stage.split_whitespace();
+                if words.next() != Some("git") {
+                    continue;
+                }
+                let sub = match words.next() {
+                    Some("add") => "add",
+                    Some("commit") => "commit",
+                    _ => continue,
+                };
+                if !found.contains(&sub) {
+                    found.push(sub);
+                }
+            }
+        }
+        found
+    }
+
+    /// Basenames of files touched by Edit/Write/NotebookEdit calls in the
+    /// turn, deduplicated and sorted.
+    pub fn touched_files(turn: &[&TranscriptEntry]) -> Vec<String> {
+        let mut files = BTreeSet::new();
+        for block in assistant_blocks(turn) {
+            if let ContentBlock::ToolUse(tu) = block {
+                let field = match tu.name.as_str() {
+                    "Edit" | "Write" => "file_path",
+                    "NotebookEdit" => "notebook_path",
+                    _ => continue,
+                };
+                if tu.input[field].is_string() {
+                    files.insert(ToolCategories::extract_filename(&tu.input, field));
+                }
+            }
+        }
+        files.into_iter().collect()
+    }
+
     /// Summarize a turn's tool activity and assistant text messages at the
     /// given verbosity level. `turn` should be in reverse-chronological
     /// order (as returned by `Transcript::turn`).
@@ -783,8 +2083,9 @@ impl Transcript {
     /// Returns `None` if the turn has no tool activity and no text messages.
     pub fn summarize_turn(
         turn: &[&TranscriptEntry],
-        verbosity: Verbosity,
+        options: impl Into<SummaryOptions>,
     ) -> Option<String> {
+        let options = options.into();
         let mut cats = ToolCategories::default();
         let mut messages: Vec<String> = Vec::new();
 
@@ -813,8 +2114,16 @@ impl Transcript {
                         for block in blocks {
                             match block {
                                 ContentBlock::ToolUse(tu) => {
+                                    if !options.count_agent_tool_calls
+                                        && tu.caller.as_ref().is_some_and(Caller::is_agent)
+                                    {
+                                        continue;
+                                    }
                                     cats.categorize(&tu.name, &tu.input);
                                 }
+                                ContentBlock::ServerToolUse(stu) => {
+                                    cats.categorize(stu.client_tool_name(), &stu.input);
+                                }
                                 ContentBlock::Text(t) => {
                                     let trimmed = t.text.trim();
                                     if !trimmed.is_empty() {
@@ -830,17 +2139,20 @@ impl Transcript {
             }
         }
 
+        cats.mark_reverted(&Self::reverted_files(turn), &options);
+        cats.mark_write_sizes(&Self::write_sizes(turn), &options);
+        cats.mark_replaced_all(&options);
+        if let Some(workdir) = &options.workdir {
+            cats.mark_external(&Self::external_files(turn, workdir), &options);
+        }
+
         // Extract Q&A from AskUserQuestion interactions.
-        let qa_lines = Self::extract_qa(turn);
+        let qa_lines: Vec<String> = Self::extract_qa(turn).iter().map(QaPair::to_string).collect();
 
         // Messages were collected newest-first; reverse to chronological.
         messages.reverse();
 
-        let tool_summary = match verbosity {
-            Verbosity::Short => cats.format_short(),
-            Verbosity::Medium => cats.format_detailed(Some(3)),
-            Verbosity::Full => cats.format_detailed(None),
-        };
+        let tool_summary = cats.format(&options);
 
         let qa_section = if qa_lines.is_empty() {
             None
@@ -848,7 +2160,7 @@ impl Transcript {
             Some(format!("Q&A: {}", qa_lines.join("; ")))
         };
 
-        let messages_section = if messages.is_empty() {
+        let messages_section = if messages.is_empty() || !options.include_messages {
             None
         } else {
             Some(messages.join("\n\n"))
@@ -873,14 +2185,23 @@ impl Transcript {
             parts.push(&msg_str);
         }
 
-        if parts.is_empty() {
-            None
+        let summary = if parts.is_empty() {
+            return None;
         } else if messages_section.is_some() && parts.len() > 1 {
             // Put a --- separator before the messages section.
             let non_msg: Vec<&str> = parts[..parts.len() - 1].to_vec();
-            Some(format!("{}\n---\n{}", non_msg.join("\n"), parts.last().unwrap()))
+            format!("{}\n---\n{}", non_msg.join("\n"), parts.last().unwrap())
         } else {
-            Some(parts.join("\n"))
+            parts.join("\n")
+        };
+
+        let deep_thinking = options.tag_deep_thinking
+            && options.verbosity_for("thinking") != Verbosity::Short
+            && Self::thinking_levels(turn).iter().any(|level| level == "high");
+        if deep_thinking {
+            Some(format!("(deep thinking) {summary}"))
+        } else {
+            Some(summary)
         }
     }
 
@@ -901,6 +2222,13 @@ struct ToolCategories {
     fetched: Vec<String>,
     delegated: Vec<String>,
     asked: Vec<String>,
+    /// Tools whose input isn't a JSON object, by name.
+    other: Vec<String>,
+    /// Labels of files edited with `replace_all: true`.
+    replaced_all: HashSet<String>,
+    /// Full paths behind each edited/written file label; several files can
+    /// share a basename.
+    file_paths: HashMap<String, BTreeSet<String>>,
 }
 
 impl ToolCategories {
@@ -912,27 +2240,152 @@ impl ToolCategories {
         }
     }
 
+    /// A label for a shell command.  Pipelines and command lists, which
+    /// lose their meaning when truncated, are reduced stage by stage to the
+    /// program each runs (plus the subcommand for tools like `cargo` and
+    /// `git`), joined by its operator — so `cargo test --workspace 2>&1 |
+    /// grep -v ok` becomes `cargo test | grep`.  Simple commands are kept
+    /// as-is.
+    fn command_label(command: &str) -> String {
+        const SUBCOMMAND_TOOLS: &[&str] = &[
+            "cargo", "docker", "gh", "git", "go", "kubectl", "npm", "pip", "pnpm", "rustup", "uv",
+            "yarn",
+        ];
+        let stages = Self::command_stages(command);
+        if stages.len() < 2 {
+            return command.to_string();
+        }
+        let stages: Vec<(String, &str)> = stages
+            .into_iter()
+            .filter_map(|(stage, op)| {
+                // Skip leading `VAR=value` environment assignments.
+                let mut words = stage.split_whitespace().skip_while(|w| {
+                    w.split_once('=').is_some_and(|(var, _)| {
+                        !var.is_empty() && var.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                    })
+                });
+                let program = words.next()?.rsplit('/').next().unwrap_or_default();
+                let label = match words.next() {
+                    Some(sub) if SUBCOMMAND_TOOLS.contains(&program) && !sub.starts_with('-') => {
+                        format!("{program} {sub}")
+                    }
+                    _ => program.to_string(),
+                };
+                Some((label, op))
+            })
+            .collect();
+        let mut label = String::new();
+        for (i, (stage, op)) in stages.iter().enumerate() {
+            label.push_str(stage);
+            if i + 1 < stages.len() {
+                label.push_str(&format!(" {op} "));
+            }
+        }
+        if label.is_empty() {
+            command.trim().to_string()
+        } else {
+            label
+        }
+    }
+
+    /// Split a shell command on the unquoted `|`, `||`, `&&` and `;`
+    /// operators into `(stage, operator after it)` pairs; the last stage's
+    /// operator is empty.
+    fn command_stages(command: &str) -> Vec<(String, &'static str)> {
+        let mut stages = Vec::new();
+        let mut current = String::new();
+        let mut quote: Option<char> = None;
+        let mut chars = command.chars().peekable();
+        while let Some(c) = chars.next() {
+            if let Some(q) = quote {
+                if c == q {
+                    quote = None;
+                }
+                current.push(c);
+                continue;
+            }
+            let op = match c {
+                '\'' | '"' => {
+                    quote = Some(c);
+                    current.push(c);
+                    continue;
+                }
+                '|' if chars.peek() == Some(&'|') => {
+                    chars.next();
+                    "||"
+                }
+                '|' => "|",
+                '&' if chars.peek() == Some(&'&') => {
+                    chars.next();
+                    "&&"
+                }
+                ';' => ";",
+                _ => {
+                    current.push(c);
+                    continue;
+                }
+            };
+            stages.push((std::mem::take(&mut current), op));
+        }
+        stages.push((current, ""));
+        stages
+    }
+
     /// Classify a tool_use block into the appropriate category.
     fn categorize(&mut self, name: &str, input: &serde_json::Value) {
+        for (category, label) in Self::labels(name, input) {
+            if name == "Edit" && input["replace_all"].as_bool() == Some(true) {
+                self.replaced_all.insert(label.clone());
+            }
+            let field = if name == "NotebookEdit" { "notebook_path" } else { "file_path" };
+            if matches!(category, "edited" | "wrote")
+                && let Some(path) = input[field].as_str()
+            {
+                self.file_paths.entry(label.clone()).or_default().insert(path.to_string());
+            }
+            self.push(category, label);
+        }
+    }
+
+    /// Display label for a tool call: its summary labels joined with "; ",
+    /// or empty if the tool isn't summarized.
+    fn label(name: &str, input: &serde_json::Value) -> String {
+        Self::labels(name, input)
+            .into_iter()
+            .map(|(_, label)| label)
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    /// Summary category and display label for a tool call, e.g.
+    /// `("edited", "main.rs")`.  Empty for tools that aren't summarized;
+    /// AskUserQuestion yields one entry per question.
+    fn labels(name: &str, input: &serde_json::Value) -> Vec<(&'static str, String)> {
+        // A malformed (non-object) input has no fields to label with; don't
+        // file it under its usual category as "(unknown)".
+        if !input.is_object() {
+            return vec![("other", name.to_string())];
+        }
+        let mut labels = Vec::new();
         match name {
-            "Edit" => self.push("edited", Self::extract_filename(input, "file_path")),
-            "NotebookEdit" => self.push("edited", Self::extract_filename(input, "notebook_path")),
-            "Write" => self.push("wrote", Self::extract_filename(input, "file_path")),
+            "Edit" => labels.push(("edited", Self::extract_filename(input, "file_path"))),
+            "NotebookEdit" => labels.push(("edited", Self::extract_filename(input, "notebook_path"))),
+            "Write" => labels.push(("wrote", Self::extract_filename(input, "file_path"))),
             "Read" => {
                 let mut label = Self::extract_filename(input, "file_path");
                 if let Some(offset) = input["offset"].as_i64() {
                     let limit = input["limit"].as_i64().unwrap_or(2000);
                     label = format!("{label}:{offset}-{}", offset + limit);
                 }
-                self.push("read", label);
+                labels.push(("read", label));
             }
             "Bash" => {
                 let label = input["description"]
                     .as_str()
                     .map(|s| Self::truncate(s, 80))
-                    .or_else(|| input["command"].as_str().map(|s| Self::truncate(s, 80)))
+                    .or_else(|| input["command"].as_str().map(|s| Self::truncate(&Self::command_label(s), 80)))
                     .unwrap_or_else(|| "(unknown)".to_string());
-                self.push("ran", label);
+                labels.push(("ran", label));
             }
             "Grep" => {
                 let mut label = input["pattern"].as_str().unwrap_or("(unknown)").to_string();
@@ -942,40 +2395,132 @@ impl ToolCategories {
                 if let Some(glob) = input["glob"].as_str() {
                     label = format!("{label} ({glob})");
                 }
-                self.push("searched", label);
+                labels.push(("searched", label));
             }
             "Glob" => {
                 let mut label = input["pattern"].as_str().unwrap_or("(unknown)").to_string();
                 if let Some(path) = input["path"].as_str() {
                     label = format!("{label} in {path}");
                 }
-                self.push("searched", label);
+                labels.push(("searched", label));
             }
             "WebFetch" => {
                 let label = input["url"].as_str()
                     .map(|s| Self::truncate(s, 80))
                     .unwrap_or_else(|| "(unknown)".to_string());
-                self.push("fetched", label);
+                labels.push(("fetched", label));
             }
             "WebSearch" => {
                 let label = input["query"].as_str().unwrap_or("(unknown)").to_string();
-                self.push("fetched", label);
+                labels.push(("fetched", label));
             }
             "Task" => {
                 let label = input["description"].as_str().unwrap_or("(unknown)").to_string();
-                self.push("delegated", label);
+                labels.push(("delegated", label));
             }
             "AskUserQuestion" => {
                 if let Some(questions) = input["questions"].as_array() {
                     for q in questions {
                         if let Some(text) = q["question"].as_str() {
-                            self.push("asked", text.to_string());
+                            labels.push(("asked", text.to_string()));
                         }
                     }
                 }
             }
             _ => {}
         }
+        labels
+    }
+
+    /// Mark edited/written files whose paths are all in `reverted` as
+    /// "(reverted)" at Full verbosity, or drop them when `exclude_reverted`
+    /// is set.
+    fn mark_reverted(&mut self, reverted: &BTreeSet<String>, options: &SummaryOptions) {
+        if reverted.is_empty() {
+            return;
+        }
+        let file_paths = &self.file_paths;
+        let is_reverted = |item: &str| {
+            Self::lookup(file_paths, item).is_some_and(|paths| paths.is_subset(reverted))
+        };
+        for (cat, items) in [("edited", &mut self.edited), ("wrote", &mut self.wrote)] {
+            if options.exclude_reverted {
+                items.retain(|item| !is_reverted(item));
+            } else if options.verbosity_for(cat) == Verbosity::Full {
+                for item in items.iter_mut() {
+                    if is_reverted(item) {
+                        item.push_str(" (reverted)");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Mark edited/written files whose paths are all in `external` as
+    /// "(external)", or drop them when `exclude_external` is set.  Runs
+    /// after the other markers, so items may already carry annotations.
+    fn mark_external(&mut self, external: &BTreeSet<String>, options: &SummaryOptions) {
+        if external.is_empty() {
+            return;
+        }
+        let file_paths = &self.file_paths;
+        let is_external = |item: &str| {
+            Self::lookup(file_paths, item).is_some_and(|paths| paths.is_subset(external))
+        };
+        for items in [&mut self.edited, &mut self.wrote] {
+            if options.exclude_external {
+                items.retain(|item| !is_external(item));
+            } else {
+                for item in items.iter_mut() {
+                    if is_external(item) {
+                        item.push_str(" (external)");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Mark files edited with `replace_all` as "(replace all)" at Full
+    /// verbosity, since one call may have changed many occurrences.
+    fn mark_replaced_all(&mut self, options: &SummaryOptions) {
+        if self.replaced_all.is_empty() || options.verbosity_for("edited") != Verbosity::Full {
+            return;
+        }
+        for item in self.edited.iter_mut() {
+            if self.replaced_all.iter().any(|label| Self::has_label(item, label)) {
+                item.push_str(" (replace all)");
+            }
+        }
+    }
+
+    /// Whether a summary item is `label`, possibly followed by annotations
+    /// like " (reverted)" added by an earlier marker.
+    fn has_label(item: &str, label: &str) -> bool {
+        item.strip_prefix(label)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(" ("))
+    }
+
+    /// The value kept under a summary item's label, ignoring any
+    /// annotations on the item.  The longest matching label wins, so
+    /// "a (1).rs" isn't taken for "a" annotated with "(1).rs".
+    fn lookup<'a, V>(map: &'a HashMap<String, V>, item: &str) -> Option<&'a V> {
+        map.iter()
+            .filter(|(label, _)| Self::has_label(item, label))
+            .max_by_key(|(label, _)| label.len())
+            .map(|(_, value)| value)
+    }
+
+    /// Annotate written files with their size ("new.rs (+1240 bytes)") at
+    /// Full verbosity.  The content itself never goes into the summary.
+    fn mark_write_sizes(&mut self, sizes: &HashMap<String, usize>, options: &SummaryOptions) {
+        if options.verbosity_for("wrote") != Verbosity::Full {
+            return;
+        }
+        for item in self.wrote.iter_mut() {
+            if let Some(bytes) = Self::lookup(sizes, item) {
+                item.push_str(&format!(" (+{bytes} bytes)"));
+            }
+        }
     }
 
     /// Push a value into the named category, deduplicating.
@@ -989,6 +2534,7 @@ impl ToolCategories {
             "fetched" => &mut self.fetched,
             "delegated" => &mut self.delegated,
             "asked" => &mut self.asked,
+            "other" => &mut self.other,
             _ => return,
         };
         if !vec.contains(&value) {
@@ -1000,16 +2546,19 @@ impl ToolCategories {
     fn extract_filename(input: &serde_json::Value, field: &str) -> String {
         input[field]
             .as_str()
-            .map(|p| {
-                Path::new(p)
-                    .file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or(p)
-                    .to_string()
-            })
+            .map(Self::filename)
             .unwrap_or_else(|| "(unknown)".to_string())
     }
 
+    /// The final component of a path, as used in file labels.
+    fn filename(path: &str) -> String {
+        Path::new(path)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(path)
+            .to_string()
+    }
+
     /// Ordered (label, items) pairs for formatting.
     fn as_pairs(&self) -> Vec<(&str, &Vec<String>)> {
         vec![
@@ -1021,65 +2570,86 @@ impl ToolCategories {
             ("fetched", &self.fetched),
             ("delegated", &self.delegated),
             ("asked", &self.asked),
+            ("other", &self.other),
         ]
     }
 
-    /// Format at Short verbosity: "edited 2 files, ran 3 commands"
-    fn format_short(&self) -> Option<String> {
+    /// Format the non-empty categories, each at its own verbosity:
+    /// Short is a count ("edited 2 files"), Medium lists up to 3 items with
+    /// "+ N more", Full lists all.  When every category is Short the counts
+    /// share one line ("edited 2 files, ran 3 commands"); otherwise each
+    /// category gets a line.
+    fn format(&self, options: &SummaryOptions) -> Option<String> {
+        let checklist = options.style == SummaryStyle::Checklist;
+        let mut all_short = true;
         let parts: Vec<String> = self
             .as_pairs()
             .iter()
             .filter(|(_, items)| !items.is_empty())
             .map(|(cat, items)| {
-                let count = items.len();
-                let noun = match *cat {
-                    "edited" | "wrote" | "read" => {
-                        if count == 1 { "file" } else { "files" }
-                    }
-                    "ran" => {
-                        if count == 1 { "command" } else { "commands" }
-                    }
-                    "searched" => {
-                        if count == 1 { "pattern" } else { "patterns" }
-                    }
-                    "fetched" => {
-                        if count == 1 { "url" } else { "urls" }
-                    }
-                    "delegated" => {
-                        if count == 1 { "task" } else { "tasks" }
-                    }
-                    "asked" => {
-                        if count == 1 { "question" } else { "questions" }
+                let label = match options.category_icons.get(*cat) {
+                    Some(icon) => format!("{icon} {cat}"),
+                    None => cat.to_string(),
+                };
+                let cap = match options.verbosity_for(cat) {
+                    Verbosity::Short => {
+                        let count = items.len();
+                        let line = format!("{label} {count} {}", options.nouns.noun(cat, count));
+                        return if checklist { format!("- [x] {line}") } else { line };
                     }
-                    _ => "items",
+                    Verbosity::Medium => Some(3),
+                    Verbosity::Full => None,
                 };
-                format!("{cat} {count} {noun}")
+                all_short = false;
+                if checklist {
+                    Self::format_checklist(cat, &label, items, cap, &options.nouns)
+                } else {
+                    Self::format_items(&label, items, cap)
+                }
             })
             .collect();
 
-        if parts.is_empty() { None } else { Some(parts.join(", ")) }
+        if parts.is_empty() {
+            None
+        } else if all_short && !checklist {
+            Some(parts.join(", "))
+        } else {
+            Some(parts.join("\n"))
+        }
     }
 
-    /// Format at Medium/Full verbosity with optional item cap.
-    fn format_detailed(&self, cap: Option<usize>) -> Option<String> {
-        let lines: Vec<String> = self
-            .as_pairs()
-            .iter()
-            .filter(|(_, items)| !items.is_empty())
-            .map(|(cat, items)| match cap {
-                Some(max) if items.len() > max => {
-                    let shown: Vec<&str> = items.iter().take(max).map(|s| s.as_str()).collect();
-                    let remaining = items.len() - max;
-                    format!("{}: {} + {} more", cat, shown.join(", "), remaining)
-                }
-                _ => {
-                    let all: Vec<&str> = items.iter().map(|s| s.as_str()).collect();
-                    format!("{}: {}", cat, all.join(", "))
-                }
-            })
-            .collect();
+    /// One category's items with optional cap: "edited: a, b + 2 more".
+    fn format_items(cat: &str, items: &[String], cap: Option<usize>) -> String {
+        match cap {
+            Some(max) if items.len() > max => {
+                let shown: Vec<&str> = items.iter().take(max).map(|s| s.as_str()).collect();
+                let remaining = items.len() - max;
+                format!("{}: {} + {} more", cat, shown.join(", "), remaining)
+            }
+            _ => {
+                let all: Vec<&str> = items.iter().map(|s| s.as_str()).collect();
+                format!("{}: {}", cat, all.join(", "))
+            }
+        }
+    }
 
-        if lines.is_empty() { None } else { Some(lines.join("\n")) }
+    /// One category's items as task-list lines with optional cap:
+    /// "- [x] edited a" ... "- [x] edited 2 more files".  `label` is the
+    /// category as displayed (with any icon).
+    fn format_checklist(
+        cat: &str,
+        label: &str,
+        items: &[String],
+        cap: Option<usize>,
+        nouns: &CategoryNouns,
+    ) -> String {
+        let shown = cap.unwrap_or(items.len()).min(items.len());
+        let mut lines: Vec<String> = items[..shown].iter().map(|item| format!("- [x] {label} {item}")).collect();
+        let remaining = items.len() - shown;
+        if remaining > 0 {
+            lines.push(format!("- [x] {label} {remaining} more {}", nouns.noun(cat, remaining)));
+        }
+        lines.join("\n")
     }
 }
 
@@ -1089,6 +2659,7 @@ pub struct AncestorIter<'a> {
     transcript: &'a Transcript,
     next_uuid: Option<&'a str>,
     visited: HashSet<&'a str>,
+    remaining: usize,
 }
 
 impl<'a> Iterator for AncestorIter<'a> {
@@ -1099,6 +2670,14 @@ impl<'a> Iterator for AncestorIter<'a> {
         if !self.visited.insert(uuid) {
             return None; // cycle detected
         }
+        if self.remaining == 0 {
+            crate::log::warning!(
+                "ancestor walk stopped at {uuid} after {} entries (depth cap)",
+                self.transcript.max_walk_depth
+            );
+            return None;
+        }
+        self.remaining -= 1;
         let entry = self.transcript.get(uuid)?;
         self.next_uuid = entry.parent_uuid();
         Some(entry)
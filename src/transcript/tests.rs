@@ -95,6 +95,122 @@ fn parse_assistant_with_text_and_tool_use() {
     }
 }
 
+#[test]
+fn parse_server_tool_use_and_web_search_result() {
+    let input = json!({
+        "type": "assistant",
+        "uuid": "bbb",
+        "parentUuid": "aaa",
+        "isSidechain": false,
+        "userType": "external",
+        "cwd": "/tmp",
+        "sessionId": "sess-1",
+        "timestamp": "2025-01-01T00:00:01Z",
+        "version": "1.0",
+        "message": {
+            "role": "assistant",
+            "content": [
+                {
+                    "type": "server_tool_use",
+                    "id": "srvtoolu_01",
+                    "name": "web_search",
+                    "input": { "query": "rust serde tagged enums" }
+                },
+                {
+                    "type": "web_search_tool_result",
+                    "tool_use_id": "srvtoolu_01",
+                    "content": [
+                        { "type": "web_search_result", "url": "https://serde.rs/enum-representations.html", "title": "Enum representations" }
+                    ]
+                },
+                { "type": "text", "text": "Found it." }
+            ]
+        }
+    });
+
+    let entry: TranscriptEntry = serde_json::from_value(input).unwrap();
+    let TranscriptEntry::Assistant(e) = &entry else {
+        panic!("expected Assistant, got {:?}", entry);
+    };
+    let MessageContent::Blocks(blocks) = &e.message.content else {
+        panic!("expected Blocks, got {:?}", e.message.content);
+    };
+    match &blocks[0] {
+        ContentBlock::ServerToolUse(stu) => {
+            assert_eq!(stu.name, "web_search");
+            assert_eq!(stu.client_tool_name(), "WebSearch");
+        }
+        other => panic!("expected ServerToolUse, got {:?}", other),
+    }
+    match &blocks[1] {
+        ContentBlock::WebSearchToolResult(r) => {
+            assert_eq!(r.tool_use_id, "srvtoolu_01");
+            assert!(r.content.is_array());
+        }
+        other => panic!("expected WebSearchToolResult, got {:?}", other),
+    }
+
+    // Server-side searches are summarized like the client WebSearch tool.
+    let turn = [&entry];
+    let summary = Transcript::summarize_turn(&turn, Verbosity::Full).unwrap();
+    assert!(summary.contains("rust serde tagged enums"), "summary: {summary}");
+    assert_eq!(Transcript::tool_error_stats(&turn), (0, 1));
+}
+
+#[test]
+fn web_search_error_result_counts_as_failed_tool() {
+    let entry: TranscriptEntry = serde_json::from_value(json!({
+        "type": "assistant", "uuid": "a1",
+        "isSidechain": false, "userType": "external",
+        "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+        "message": { "role": "assistant", "content": [
+            { "type": "server_tool_use", "id": "srvtoolu_01", "name": "web_search", "input": { "query": "q" } },
+            { "type": "web_search_tool_result", "tool_use_id": "srvtoolu_01",
+              "content": { "type": "web_search_tool_result_error", "error_code": "max_uses_exceeded" } }
+        ]}
+    }))
+    .unwrap();
+    assert_eq!(Transcript::tool_error_stats(&[&entry]), (1, 1));
+}
+
+#[test]
+fn parse_unknown_content_block_keeps_entry() {
+    let lines = [
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "hello" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "hologram", "payload": { "frames": 3 } },
+                { "type": "text", "text": "Still here." }
+            ]}
+        }),
+    ];
+    let contents = lines.iter().map(|v| serde_json::to_string(v).unwrap()).collect::<Vec<_>>().join("\n");
+    let (transcript, errors) = Transcript::parse(&contents);
+    assert!(errors.is_empty(), "errors: {errors:?}");
+
+    let entry = transcript.get("a1").expect("entry is indexed");
+    let TranscriptEntry::Assistant(e) = entry else {
+        panic!("expected Assistant, got {:?}", entry);
+    };
+    let MessageContent::Blocks(blocks) = &e.message.content else {
+        panic!("expected Blocks, got {:?}", e.message.content);
+    };
+    assert!(matches!(&blocks[0], ContentBlock::Unknown));
+    assert!(transcript.is_ancestor("a1", "u1"));
+
+    let turn = transcript.turn("a1", Some("u1"));
+    let summary = Transcript::summarize_turn(&turn, Verbosity::Medium).unwrap();
+    assert_eq!(summary, "Still here.");
+}
+
 #[test]
 fn parse_user_tool_result() {
     let input = json!({
@@ -390,6 +506,85 @@ fn ancestor_iter_terminates_on_cycle() {
     assert_eq!(ancestors, vec!["a", "b"], "should visit each node once then stop");
 }
 
+#[test]
+fn is_ancestor_matches_naive_walk_on_long_chain() {
+    let entry = |uuid: &str, parent: Option<&str>| {
+        json!({
+            "type": "system", "uuid": uuid, "parentUuid": parent,
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "subtype": "info", "content": "x", "level": "info"
+        })
+    };
+    let mut lines = vec![entry("e0", None)];
+    for i in 1..400 {
+        lines.push(entry(&format!("e{i}"), Some(&format!("e{}", i - 1))));
+    }
+    // A side branch, a three-node cycle with a child, and a dangling parent.
+    for i in 0..10 {
+        let parent = if i == 0 { "e200".to_string() } else { format!("b{}", i - 1) };
+        lines.push(entry(&format!("b{i}"), Some(&parent)));
+    }
+    lines.push(entry("c0", Some("c2")));
+    lines.push(entry("c1", Some("c0")));
+    lines.push(entry("c2", Some("c1")));
+    lines.push(entry("d0", Some("c1")));
+    lines.push(entry("orphan", Some("missing")));
+    let contents = lines
+        .iter()
+        .map(|v| serde_json::to_string(v).unwrap())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let (transcript, errors) = Transcript::parse(&contents);
+    assert!(errors.is_empty(), "parse errors: {errors:?}");
+
+    let uuids: Vec<&str> = transcript.entries().iter().filter_map(|e| e.uuid()).collect();
+    let naive = |uuid: &str, ancestor: &str| transcript.ancestors(uuid).any(|e| e.uuid() == Some(ancestor));
+    for from in uuids.iter().step_by(23).chain(["b9", "c0", "d0", "orphan", "nope"].iter()) {
+        for to in uuids.iter().chain(["missing", "nope"].iter()) {
+            assert_eq!(
+                transcript.is_ancestor(from, to),
+                naive(from, to),
+                "is_ancestor({from}, {to})"
+            );
+        }
+    }
+    assert!(transcript.is_ancestor("b9", "e0"));
+    assert!(!transcript.is_ancestor("b9", "e201"));
+    assert!(transcript.is_ancestor("c0", "c1"));
+    assert!(transcript.is_ancestor("d0", "c2"));
+}
+
+#[test]
+fn validate_dag_reports_dangling_parent_and_extra_roots() {
+    let entry = |uuid: &str, parent: Option<&str>| {
+        json!({
+            "type": "system", "uuid": uuid, "parentUuid": parent,
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "subtype": "info", "content": "x", "level": "info"
+        })
+    };
+    let lines = [entry("r1", None), entry("a", Some("r1")), entry("b", Some("gone")), entry("r2", None)];
+    let contents = lines
+        .iter()
+        .map(|v| serde_json::to_string(v).unwrap())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let (transcript, _) = Transcript::parse(&contents);
+
+    assert_eq!(
+        transcript.validate_dag(),
+        vec![
+            DagIssue::DanglingParent { uuid: "b".into(), parent: "gone".into() },
+            DagIssue::MultipleRoots(vec!["r1".into(), "r2".into()]),
+        ]
+    );
+
+    let (healthy, _) = Transcript::parse(&contents.lines().take(2).collect::<Vec<_>>().join("\n"));
+    assert!(healthy.validate_dag().is_empty());
+}
+
 #[test]
 fn transcript_lookup_and_raw() {
     let lines = [
@@ -444,6 +639,83 @@ fn find_user_prompt_returns_last_match() {
     assert_eq!(transcript.find_user_prompt("nonexistent"), None);
 }
 
+#[test]
+fn turn_with_unreachable_prompt_stops_at_depth_cap() {
+    let entries: Vec<serde_json::Value> = (0..50)
+        .map(|i| {
+            let parent = (i > 0).then(|| format!("e{}", i - 1));
+            json!({
+                "type": "user", "uuid": format!("e{i}"), "parentUuid": parent,
+                "isSidechain": false, "userType": "external",
+                "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+                "message": { "role": "user", "content": "step" }
+            })
+        })
+        .collect();
+    let contents = entries.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("\n");
+    let (mut transcript, _) = Transcript::parse(&contents);
+
+    // Under the default cap the walk reaches the root.
+    assert_eq!(transcript.turn("e49", Some("typo")).len(), 50);
+
+    transcript.set_max_walk_depth(10);
+    let turn = transcript.turn("e49", Some("typo"));
+    let uuids: Vec<&str> = turn.iter().filter_map(|e| e.uuid()).collect();
+    assert_eq!(uuids.len(), 10);
+    assert_eq!(uuids.first(), Some(&"e49"));
+    assert_eq!(uuids.last(), Some(&"e40"));
+
+    // The cap carries over to a truncated copy.
+    let truncated = transcript.truncated_at("e30").unwrap();
+    assert_eq!(truncated.ancestors("e30").count(), 10);
+}
+
+#[test]
+fn parse_strict_rejects_any_bad_line() {
+    let good = json!({
+        "type": "user", "uuid": "u1",
+        "isSidechain": false, "userType": "external",
+        "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+        "message": { "role": "user", "content": "hello" }
+    })
+    .to_string();
+
+    let transcript = Transcript::parse_strict(&good).unwrap();
+    assert_eq!(transcript.entries().len(), 1);
+
+    let contents = format!("{good}\n\n{{\"type\": \"user\", \"uuid\":\n");
+    let errors = Transcript::parse_strict(&contents).err().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].0, 3);
+}
+
+#[test]
+fn parse_recovers_concatenated_entries() {
+    let user = json!({
+        "type": "user", "uuid": "u1",
+        "isSidechain": false, "userType": "external",
+        "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+        "message": { "role": "user", "content": "hello" }
+    });
+    let asst = json!({
+        "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+        "isSidechain": false, "userType": "external",
+        "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+        "message": { "role": "assistant", "content": [{"type": "text", "text": "a }{ b"}] }
+    });
+
+    let (transcript, errors) = Transcript::parse(&format!("{user}{asst}"));
+    assert!(errors.is_empty(), "errors: {errors:?}");
+    assert_eq!(transcript.entries().len(), 2);
+    assert!(transcript.uuid_exists("u1"));
+    assert_eq!(transcript.get_raw("a1"), Some(&asst));
+
+    // A broken value in the run still reports the line.
+    let (transcript, errors) = Transcript::parse(&format!("{user}{{\"type\": 1}}"));
+    assert_eq!(errors.len(), 1);
+    assert!(transcript.entries().is_empty());
+}
+
 #[test]
 fn turn_and_turn_raw() {
     let lines = [
@@ -491,6 +763,33 @@ fn turn_and_turn_raw() {
     assert_eq!(uuids, vec!["a2", "u2", "a1", "u1"]);
 }
 
+#[test]
+fn turn_raw_shares_values_with_transcript() {
+    let lines = [
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "hello" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [{"type": "text", "text": "hi"}] }
+        }),
+    ];
+    let contents = lines.iter().map(|v| serde_json::to_string(v).unwrap()).collect::<Vec<_>>().join("\n");
+    let (transcript, _) = Transcript::parse(&contents);
+
+    let raw = transcript.turn_raw("a1", None);
+    let values: Vec<&serde_json::Value> = raw.iter().map(|v| &**v).collect();
+    assert_eq!(values, vec![&lines[0], &lines[1]]);
+    // Each value is the transcript's own copy, not a deep clone.
+    assert!(std::ptr::eq(&*raw[1], transcript.get_raw("a1").unwrap()));
+    assert!(std::ptr::eq(&*transcript.turn_raw("a1", None)[1], &*raw[1]));
+}
+
 #[test]
 fn is_ancestor_check() {
     let lines = [
@@ -647,6 +946,48 @@ fn summarize_turn_full() {
     assert!(summary.contains("edited: main.rs, types.rs"), "summary: {summary}");
 }
 
+#[test]
+fn summarize_turn_checklist_style() {
+    let (contents, _) = build_tool_transcript();
+    let (transcript, _) = Transcript::parse(&contents);
+    let turn = transcript.turn("a2", Some("u1"));
+    let options = SummaryOptions {
+        style: SummaryStyle::Checklist,
+        ..Verbosity::Medium.into()
+    };
+    let summary = Transcript::summarize_turn(&turn, options).unwrap();
+
+    assert!(summary.contains("- [x] edited main.rs\n- [x] edited types.rs"), "summary: {summary}");
+    assert!(summary.contains("- [x] read lib.rs"), "summary: {summary}");
+    assert!(summary.contains("- [x] read 2 more files"), "summary: {summary}");
+    assert!(!summary.contains("read:"), "summary: {summary}");
+
+    let options = SummaryOptions {
+        style: SummaryStyle::Checklist,
+        ..Verbosity::Short.into()
+    };
+    let summary = Transcript::summarize_turn(&turn, options).unwrap();
+    assert!(summary.contains("- [x] edited 2 files\n- [x] read 5 files"), "summary: {summary}");
+}
+
+#[test]
+fn summarize_turn_without_messages() {
+    let (contents, _) = build_tool_transcript();
+    let (transcript, _) = Transcript::parse(&contents);
+    let turn = transcript.turn("a2", Some("u1"));
+    let options = SummaryOptions {
+        include_messages: false,
+        ..Verbosity::Medium.into()
+    };
+    let summary = Transcript::summarize_turn(&turn, options).unwrap();
+
+    assert!(summary.contains("edited: main.rs, types.rs"), "summary: {summary}");
+    assert!(!summary.contains("---"), "summary: {summary}");
+    assert!(!summary.contains("Let me read the file."), "summary: {summary}");
+    let with_messages = Transcript::summarize_turn(&turn, Verbosity::Medium).unwrap();
+    assert!(with_messages.starts_with(&format!("{summary}\n")), "summary: {with_messages}");
+}
+
 #[test]
 fn summarize_turn_text_only() {
     let lines = vec![
@@ -850,6 +1191,38 @@ fn summarize_turn_bash_falls_back_to_command() {
     assert!(summary.contains("ran: ls -la"), "fallback to command: {summary}");
 }
 
+#[test]
+fn summarize_turn_bash_labels_pipelines_by_program() {
+    let lines = [
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "go" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "tool_use", "id": "t1", "name": "Bash", "input": {
+                    "command": "RUST_LOG=debug cargo test --workspace -- --nocapture 2>&1 | grep -v 'ok | skipped'"
+                } },
+                { "type": "tool_use", "id": "t2", "name": "Bash", "input": {
+                    "command": "cd /tmp/build && ./configure --prefix=/usr; make -j8"
+                } }
+            ]}
+        }),
+    ];
+    let contents = lines.iter().map(|v| serde_json::to_string(v).unwrap()).collect::<Vec<_>>().join("\n");
+    let (transcript, _) = Transcript::parse(&contents);
+    let turn = transcript.turn("a1", Some("u1"));
+    let summary = Transcript::summarize_turn(&turn, Verbosity::Full).unwrap();
+    assert!(summary.contains("cargo test | grep"), "summary: {summary}");
+    assert!(summary.contains("cd && configure ; make"), "summary: {summary}");
+    assert!(!summary.contains("nocapture"), "summary: {summary}");
+}
+
 #[test]
 fn summarize_turn_read_with_line_range() {
     let lines = vec![
@@ -989,6 +1362,65 @@ fn summarize_turn_ask_user_question_with_answers() {
     assert!(medium.contains("2 rounds"), "should include answers: {medium}");
 }
 
+#[test]
+fn extract_qa_collects_every_ask_user_question() {
+    let ask = |uuid: &str, parent: &str, id: &str, question: &str| {
+        json!({
+            "type": "assistant", "uuid": uuid, "parentUuid": parent,
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "tool_use", "id": id, "name": "AskUserQuestion", "input": {
+                    "questions": [{ "question": question, "header": "Q", "options": [], "multiSelect": false }]
+                }}
+            ]}
+        })
+    };
+    let answer = |uuid: &str, parent: &str, id: &str, content: serde_json::Value| {
+        json!({
+            "type": "user", "uuid": uuid, "parentUuid": parent,
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": [
+                { "type": "tool_result", "tool_use_id": id, "content": content }
+            ]}
+        })
+    };
+    let lines = [
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "plan it" }
+        }),
+        ask("a1", "u1", "ask1", "Which database?"),
+        answer("u2", "a1", "ask1", json!(
+            "User has answered your questions: \"Which database?\"=\"Postgres\". You can now continue with the user's answers in mind."
+        )),
+        ask("a2", "u2", "ask2", "Which port?"),
+        // Answers may also arrive as an array of text blocks.
+        answer("u3", "a2", "ask2", json!([
+            { "type": "text", "text": "User has answered your questions: \"Which port?\"=\"5432\". You can now continue with the user's answers in mind." }
+        ])),
+        // A result not in the "Q"="A" form is kept whole.
+        ask("a3", "u3", "ask3", "Which schema?"),
+        answer("u4", "a3", "ask3", json!("whatever you think is best")),
+    ];
+    let contents = lines.iter().map(|v| serde_json::to_string(v).unwrap()).collect::<Vec<_>>().join("\n");
+    let (transcript, errors) = Transcript::parse(&contents);
+    assert!(errors.is_empty(), "parse errors: {errors:?}");
+    let turn = transcript.turn("u4", Some("u1"));
+
+    assert_eq!(
+        Transcript::extract_qa(&turn),
+        vec![
+            QaPair { question: "Which database?".to_string(), answer: "Postgres".to_string() },
+            QaPair { question: "Which port?".to_string(), answer: "5432".to_string() },
+            QaPair { question: "Which schema?".to_string(), answer: "whatever you think is best".to_string() },
+        ]
+    );
+}
+
 #[test]
 fn summarize_turn_ask_user_question_without_answer() {
     // AskUserQuestion with no matching tool_result (e.g. user interrupted)
@@ -1156,3 +1588,959 @@ fn user_texts_until_skips_committed_entries() {
     assert_eq!(just_trigger.len(), 1);
     assert_eq!(just_trigger[0].1, "do it in plan mode");
 }
+
+#[test]
+fn summarize_turn_skips_agent_tool_calls_when_disabled() {
+    let lines = [
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "go" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "tool_use", "id": "t1", "name": "Edit", "caller": { "type": "direct" },
+                  "input": { "file_path": "/src/main.rs", "old_string": "a", "new_string": "b" } },
+                { "type": "tool_use", "id": "t2", "name": "Edit", "caller": { "type": "code_execution_20250825", "tool_id": "srvtoolu_1" },
+                  "input": { "file_path": "/src/agent.rs", "old_string": "a", "new_string": "b" } },
+                { "type": "tool_use", "id": "t3", "name": "Bash", "caller": { "type": "code_execution_20250825", "tool_id": "srvtoolu_1" },
+                  "input": { "command": "cargo test" } }
+            ]}
+        }),
+    ];
+    let contents = lines.iter().map(|v| serde_json::to_string(v).unwrap()).collect::<Vec<_>>().join("\n");
+    let (transcript, errors) = Transcript::parse(&contents);
+    assert!(errors.is_empty(), "parse errors: {errors:?}");
+    let turn = transcript.turn("a1", Some("u1"));
+
+    // Default: agent tool calls count.
+    let all = Transcript::summarize_turn(&turn, Verbosity::Medium).unwrap();
+    assert!(all.contains("edited: main.rs, agent.rs"), "summary: {all}");
+    assert!(all.contains("ran: cargo test"), "summary: {all}");
+
+    let opts = SummaryOptions {
+        count_agent_tool_calls: false,
+        ..Verbosity::Medium.into()
+    };
+    let filtered = Transcript::summarize_turn(&turn, opts).unwrap();
+    assert!(filtered.contains("edited: main.rs"), "summary: {filtered}");
+    assert!(!filtered.contains("agent.rs"), "agent edit should be skipped: {filtered}");
+    assert!(!filtered.contains("ran:"), "agent command should be skipped: {filtered}");
+}
+
+#[test]
+fn caller_of_a_recorded_tool_call_is_direct() {
+    // An assistant entry as Claude Code wrote it, trimmed of identifying
+    // fields.
+    let recorded = include_str!("../../tests/fixtures/direct_tool_use.jsonl");
+    let uuid = "e729f32a-fff0-4e00-ad46-1fb426dc9a4f";
+    let opts = SummaryOptions {
+        count_agent_tool_calls: false,
+        ..Verbosity::Medium.into()
+    };
+
+    let (transcript, errors) = Transcript::parse(recorded);
+    assert!(errors.is_empty(), "parse errors: {errors:?}");
+    let turn = transcript.turn(uuid, None);
+    let summary = Transcript::summarize_turn(&turn, opts.clone()).unwrap();
+    assert!(summary.contains("read: main.rs"), "direct call should count: {summary}");
+
+    // The same call issued from code execution is an agent's.
+    let delegated = recorded.replace(
+        r#""caller":{"type":"direct"}"#,
+        r#""caller":{"type":"code_execution_20250825","tool_id":"srvtoolu_1"}"#,
+    );
+    let (transcript, errors) = Transcript::parse(&delegated);
+    assert!(errors.is_empty(), "parse errors: {errors:?}");
+    let turn = transcript.turn(uuid, None);
+    assert_eq!(Transcript::summarize_turn(&turn, opts), None);
+}
+
+#[test]
+fn summarize_turn_mixes_per_category_verbosity() {
+    let tool = |uuid: &str, parent: &str, name: &str, input: serde_json::Value| {
+        json!({
+            "type": "assistant", "uuid": uuid, "parentUuid": parent,
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "tool_use", "id": format!("t-{uuid}"), "name": name, "input": input }
+            ]}
+        })
+    };
+    let mut lines = vec![json!({
+        "type": "user", "uuid": "u1",
+        "isSidechain": false, "userType": "external",
+        "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+        "message": { "role": "user", "content": "go" }
+    })];
+    let mut parent = "u1".to_string();
+    for (i, file) in ["a.rs", "b.rs", "c.rs", "d.rs"].iter().enumerate() {
+        let uuid = format!("e{i}");
+        lines.push(tool(&uuid, &parent, "Edit", json!({ "file_path": format!("/repo/{file}") })));
+        parent = uuid;
+    }
+    for (i, file) in ["x.rs", "y.rs"].iter().enumerate() {
+        let uuid = format!("r{i}");
+        lines.push(tool(&uuid, &parent, "Read", json!({ "file_path": format!("/repo/{file}") })));
+        parent = uuid;
+    }
+    let contents = lines.iter().map(|v| serde_json::to_string(v).unwrap()).collect::<Vec<_>>().join("\n");
+    let (transcript, _) = Transcript::parse(&contents);
+    let turn = transcript.turn(&parent, Some("u1"));
+
+    let mut options: SummaryOptions = Verbosity::Medium.into();
+    options.category_verbosity.insert("edited".into(), Verbosity::Full);
+    options.category_verbosity.insert("read".into(), Verbosity::Short);
+    let summary = Transcript::summarize_turn(&turn, options).unwrap();
+    // Full: all four edits listed, no "+ N more" cap.
+    for file in ["a.rs", "b.rs", "c.rs", "d.rs"] {
+        assert!(summary.contains(file), "summary: {summary}");
+    }
+    assert!(!summary.contains("more"), "summary: {summary}");
+    assert!(summary.contains("\nread 2 files"), "summary: {summary}");
+    assert!(!summary.contains("x.rs"), "reads should be counted only: {summary}");
+}
+
+#[test]
+fn summarize_turn_prefixes_category_icons() {
+    let lines = [
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "go" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "tool_use", "id": "t1", "name": "Edit", "input": { "file_path": "/repo/main.rs" } },
+                { "type": "tool_use", "id": "t2", "name": "Read", "input": { "file_path": "/repo/lib.rs" } }
+            ]}
+        }),
+    ];
+    let contents = lines.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("\n");
+    let (transcript, _) = Transcript::parse(&contents);
+    let turn = transcript.turn("a1", Some("u1"));
+
+    let mut options: SummaryOptions = Verbosity::Medium.into();
+    options.category_icons.insert("edited".into(), "✏️".into());
+    let summary = Transcript::summarize_turn(&turn, options.clone()).unwrap();
+    assert!(summary.contains("✏️ edited: main.rs"), "summary: {summary}");
+    assert!(summary.lines().any(|l| l == "read: lib.rs"), "summary: {summary}");
+    assert_eq!(summary.matches("✏️").count(), 1, "summary: {summary}");
+
+    options.verbosity = Verbosity::Short;
+    let summary = Transcript::summarize_turn(&turn, options).unwrap();
+    assert!(summary.starts_with("✏️ edited 1 file, read 1 file"), "summary: {summary}");
+}
+
+#[test]
+fn summarize_turn_puts_non_object_input_under_other() {
+    let lines = [
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "go" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "tool_use", "id": "t1", "name": "Edit", "input": ["/repo/main.rs"] },
+                { "type": "tool_use", "id": "t2", "name": "Bash", "input": {"command": "ls"} }
+            ]}
+        }),
+    ];
+    let contents = lines.iter().map(|v| serde_json::to_string(v).unwrap()).collect::<Vec<_>>().join("\n");
+    let (transcript, errors) = Transcript::parse(&contents);
+    assert!(errors.is_empty(), "parse errors: {errors:?}");
+    let turn = transcript.turn("a1", Some("u1"));
+
+    let medium = Transcript::summarize_turn(&turn, Verbosity::Medium).unwrap();
+    assert!(medium.contains("other: Edit"), "summary: {medium}");
+    assert!(!medium.contains("edited"), "summary: {medium}");
+    assert!(!medium.contains("(unknown)"), "summary: {medium}");
+    assert!(medium.contains("ran: ls"), "summary: {medium}");
+
+    let short = Transcript::summarize_turn(&turn, Verbosity::Short).unwrap();
+    assert!(short.contains("other 1 tool"), "summary: {short}");
+}
+
+#[test]
+fn summarize_turn_marks_reverted_edits() {
+    let edit = |uuid: &str, parent: &str, id: &str, file: &str| {
+        json!({
+            "type": "assistant", "uuid": uuid, "parentUuid": parent,
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "tool_use", "id": id, "name": "Edit", "input": { "file_path": file } }
+            ]}
+        })
+    };
+    let result = |uuid: &str, parent: &str, id: &str, file: &str, old: &str, new: &str, original: &str| {
+        json!({
+            "type": "user", "uuid": uuid, "parentUuid": parent,
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": [
+                { "type": "tool_result", "tool_use_id": id, "content": "ok" }
+            ]},
+            "toolUseResult": {
+                "filePath": file, "oldString": old, "newString": new, "originalFile": original
+            }
+        })
+    };
+    let lines = [
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "try something" }
+        }),
+        edit("a1", "u1", "t1", "/repo/main.rs"),
+        result("r1", "a1", "t1", "/repo/main.rs", "let x = 1;", "let x = 2;", "fn f() { let x = 1; }"),
+        edit("a2", "r1", "t2", "/repo/lib.rs"),
+        result("r2", "a2", "t2", "/repo/lib.rs", "old", "new", "old code"),
+        // Put main.rs back the way it was.
+        edit("a3", "r2", "t3", "/repo/main.rs"),
+        result("r3", "a3", "t3", "/repo/main.rs", "let x = 2;", "let x = 1;", "fn f() { let x = 2; }"),
+    ];
+    let contents = lines.iter().map(|v| serde_json::to_string(v).unwrap()).collect::<Vec<_>>().join("\n");
+    let (transcript, errors) = Transcript::parse(&contents);
+    assert!(errors.is_empty(), "parse errors: {errors:?}");
+    let turn = transcript.turn("r3", Some("u1"));
+
+    assert_eq!(
+        Transcript::reverted_files(&turn).into_iter().collect::<Vec<_>>(),
+        vec!["/repo/main.rs".to_string()]
+    );
+
+    let full = Transcript::summarize_turn(&turn, Verbosity::Full).unwrap();
+    assert!(full.contains("main.rs (reverted)"), "summary: {full}");
+    assert!(!full.contains("lib.rs (reverted)"), "summary: {full}");
+
+    let options = SummaryOptions {
+        exclude_reverted: true,
+        ..Verbosity::Short.into()
+    };
+    let short = Transcript::summarize_turn(&turn, options).unwrap();
+    assert!(short.contains("edited 1 file"), "summary: {short}");
+}
+
+#[test]
+fn summarize_turn_matches_reverted_files_by_full_path() {
+    let tool = |uuid: &str, parent: &str, id: &str, name: &str, file: &str| {
+        json!({
+            "type": "assistant", "uuid": uuid, "parentUuid": parent,
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "tool_use", "id": id, "name": name, "input": { "file_path": file } }
+            ]}
+        })
+    };
+    let result = |uuid: &str, parent: &str, id: &str, tool_use_result: serde_json::Value| {
+        json!({
+            "type": "user", "uuid": uuid, "parentUuid": parent,
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": [
+                { "type": "tool_result", "tool_use_id": id, "content": "ok" }
+            ]},
+            "toolUseResult": tool_use_result
+        })
+    };
+    let edit = |file: &str, old: &str, new: &str, original: &str| {
+        json!({ "filePath": file, "oldString": old, "newString": new, "originalFile": original })
+    };
+    let lines = [
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "tidy up" }
+        }),
+        // a/mod.rs is changed and changed back; b/mod.rs really changes.
+        tool("a1", "u1", "t1", "Edit", "/repo/a/mod.rs"),
+        result("r1", "a1", "t1", edit("/repo/a/mod.rs", "x", "y", "x")),
+        tool("a2", "r1", "t2", "Edit", "/repo/a/mod.rs"),
+        result("r2", "a2", "t2", edit("/repo/a/mod.rs", "y", "x", "y")),
+        tool("a3", "r2", "t3", "Edit", "/repo/b/mod.rs"),
+        result("r3", "a3", "t3", edit("/repo/b/mod.rs", "x", "y", "x")),
+        // Rewritten with the content it already had.
+        tool("a4", "r3", "t4", "Write", "/repo/same.rs"),
+        result("r4", "a4", "t4", json!({
+            "type": "update", "filePath": "/repo/same.rs", "content": "same", "originalFile": "same"
+        })),
+    ];
+    let contents = lines.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("\n");
+    let (transcript, errors) = Transcript::parse(&contents);
+    assert!(errors.is_empty(), "parse errors: {errors:?}");
+    let turn = transcript.turn("r4", Some("u1"));
+
+    let full = Transcript::summarize_turn(&turn, Verbosity::Full).unwrap();
+    assert!(full.contains("edited: mod.rs\n"), "summary: {full}");
+    assert!(full.contains("same.rs (reverted) (+4 bytes)"), "summary: {full}");
+
+    let options = SummaryOptions {
+        exclude_reverted: true,
+        ..Verbosity::Full.into()
+    };
+    let excluded = Transcript::summarize_turn(&turn, options).unwrap();
+    assert!(excluded.contains("mod.rs"), "summary: {excluded}");
+    assert!(!excluded.contains("same.rs"), "summary: {excluded}");
+}
+
+#[test]
+fn summarize_turn_annotates_write_sizes_without_content() {
+    let body = "generated line\n".repeat(100);
+    let lines = [
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "generate it" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "tool_use", "id": "w1", "name": "Write",
+                  "input": { "file_path": "/repo/new.rs", "content": body } }
+            ]}
+        }),
+        json!({
+            "type": "user", "uuid": "r1", "parentUuid": "a1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": [
+                { "type": "tool_result", "tool_use_id": "w1", "content": "File created" }
+            ]},
+            "toolUseResult": { "type": "create", "filePath": "/repo/new.rs", "content": body }
+        }),
+    ];
+    let contents = lines.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("\n");
+    let (transcript, errors) = Transcript::parse(&contents);
+    assert!(errors.is_empty(), "parse errors: {errors:?}");
+    let turn = transcript.turn("r1", Some("u1"));
+
+    let full = Transcript::summarize_turn(&turn, Verbosity::Full).unwrap();
+    assert!(full.contains("new.rs (+1500 bytes)"), "summary: {full}");
+    assert!(!full.contains("generated line"), "summary: {full}");
+
+    let medium = Transcript::summarize_turn(&turn, Verbosity::Medium).unwrap();
+    assert!(!medium.contains("bytes"), "summary: {medium}");
+}
+
+#[test]
+fn web_activity_separates_urls_and_queries() {
+    let lines = [
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/repo", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "look it up" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/repo", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "tool_use", "id": "t1", "name": "WebSearch", "input": { "query": "rust arc vs rc" } },
+                { "type": "tool_use", "id": "t2", "name": "WebFetch",
+                  "input": { "url": "https://doc.rust-lang.org/std/sync/struct.Arc.html", "prompt": "summarize" } },
+                { "type": "tool_use", "id": "t3", "name": "Edit", "input": { "file_path": "/repo/main.rs" } }
+            ]}
+        }),
+    ];
+    let contents = lines.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("\n");
+    let (transcript, _) = Transcript::parse(&contents);
+    let turn = transcript.turn("a1", Some("u1"));
+
+    let (urls, queries) = Transcript::web_activity(&turn);
+    assert_eq!(urls, vec!["https://doc.rust-lang.org/std/sync/struct.Arc.html"]);
+    assert_eq!(queries, vec!["rust arc vs rc"]);
+}
+
+#[test]
+fn summarize_turn_marks_replace_all_edits_at_full() {
+    let lines = [
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/repo", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "rename it everywhere" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/repo", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "tool_use", "id": "t1", "name": "Edit", "input": {
+                    "file_path": "/repo/main.rs", "old_string": "foo", "new_string": "bar", "replace_all": true } },
+                { "type": "tool_use", "id": "t2", "name": "Edit", "input": {
+                    "file_path": "/repo/lib.rs", "old_string": "foo", "new_string": "bar" } }
+            ]}
+        }),
+    ];
+    let contents = lines.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("\n");
+    let (transcript, _) = Transcript::parse(&contents);
+    let turn = transcript.turn("a1", Some("u1"));
+
+    let full = Transcript::summarize_turn(&turn, Verbosity::Full).unwrap();
+    assert!(full.contains("main.rs (replace all)"), "summary: {full}");
+    assert!(!full.contains("lib.rs (replace all)"), "summary: {full}");
+
+    let medium = Transcript::summarize_turn(&turn, Verbosity::Medium).unwrap();
+    assert!(medium.contains("main.rs"), "summary: {medium}");
+    assert!(!medium.contains("replace all"), "summary: {medium}");
+}
+
+#[test]
+fn summarize_turn_marks_edits_outside_workdir() {
+    let lines = [
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/repo", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "update the config too" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/repo", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "tool_use", "id": "t1", "name": "Edit", "input": { "file_path": "/repo/src/main.rs" } },
+                { "type": "tool_use", "id": "t2", "name": "Edit", "input": { "file_path": "/etc/foo" } },
+                // Shares a basename with a file inside the workdir.
+                { "type": "tool_use", "id": "t3", "name": "Edit", "input": { "file_path": "/repo/config.toml" } },
+                { "type": "tool_use", "id": "t4", "name": "Edit", "input": { "file_path": "/etc/config.toml" } }
+            ]}
+        }),
+    ];
+    let contents = lines.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("\n");
+    let (transcript, _) = Transcript::parse(&contents);
+    let turn = transcript.turn("a1", Some("u1"));
+
+    let external = Transcript::external_files(&turn, Path::new("/repo"));
+    assert_eq!(external.into_iter().collect::<Vec<_>>(), vec!["/etc/config.toml", "/etc/foo"]);
+
+    let options = SummaryOptions {
+        workdir: Some("/repo".into()),
+        ..Verbosity::Medium.into()
+    };
+    let summary = Transcript::summarize_turn(&turn, options.clone()).unwrap();
+    assert!(summary.contains("foo (external)"), "summary: {summary}");
+    assert!(!summary.contains("main.rs (external)"), "summary: {summary}");
+    assert!(!summary.contains("config.toml (external)"), "summary: {summary}");
+
+    let excluded = SummaryOptions { exclude_external: true, ..options };
+    let summary = Transcript::summarize_turn(&turn, excluded).unwrap();
+    assert!(summary.contains("main.rs"), "summary: {summary}");
+    assert!(summary.contains("config.toml"), "summary: {summary}");
+    assert!(!summary.contains("foo"), "summary: {summary}");
+
+    // Without a workdir nothing is marked.
+    let summary = Transcript::summarize_turn(&turn, Verbosity::Medium).unwrap();
+    assert!(!summary.contains("(external)"), "summary: {summary}");
+}
+
+#[test]
+fn summarize_turn_tags_deep_thinking() {
+    let transcript_with = |thinking: serde_json::Value| {
+        let lines = [
+            json!({
+                "type": "user", "uuid": "u1",
+                "isSidechain": false, "userType": "external",
+                "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+                "thinkingMetadata": thinking,
+                "message": { "role": "user", "content": "think hard" }
+            }),
+            json!({
+                "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+                "isSidechain": false, "userType": "external",
+                "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+                "message": { "role": "assistant", "content": [
+                    { "type": "tool_use", "id": "t1", "name": "Edit", "input": { "file_path": "/repo/main.rs" } }
+                ]}
+            }),
+        ];
+        let contents = lines.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("\n");
+        Transcript::parse(&contents).0
+    };
+    let options = SummaryOptions {
+        tag_deep_thinking: true,
+        ..Verbosity::Medium.into()
+    };
+
+    let high = transcript_with(json!({ "level": "high", "disabled": false, "triggers": [] }));
+    let turn = high.turn("a1", None);
+    let summary = Transcript::summarize_turn(&turn, options.clone()).unwrap();
+    assert!(summary.starts_with("(deep thinking) edited"), "summary: {summary}");
+    // Off by default, and never at Short.
+    let summary = Transcript::summarize_turn(&turn, Verbosity::Medium).unwrap();
+    assert!(!summary.contains("deep thinking"), "summary: {summary}");
+    let short = SummaryOptions { verbosity: Verbosity::Short, ..options.clone() };
+    let summary = Transcript::summarize_turn(&turn, short).unwrap();
+    assert!(!summary.contains("deep thinking"), "summary: {summary}");
+    // The "thinking" category's verbosity decides, like any other section.
+    let mut per_category = options.clone();
+    per_category.category_verbosity.insert("thinking".into(), Verbosity::Short);
+    let summary = Transcript::summarize_turn(&turn, per_category).unwrap();
+    assert!(!summary.contains("deep thinking"), "summary: {summary}");
+    let mut short_but_thinking = SummaryOptions { verbosity: Verbosity::Short, ..options.clone() };
+    short_but_thinking.category_verbosity.insert("thinking".into(), Verbosity::Medium);
+    let summary = Transcript::summarize_turn(&turn, short_but_thinking).unwrap();
+    assert!(summary.starts_with("(deep thinking) "), "summary: {summary}");
+
+    let disabled = transcript_with(json!({ "level": "high", "disabled": true, "triggers": [] }));
+    let turn = disabled.turn("a1", None);
+    let summary = Transcript::summarize_turn(&turn, options).unwrap();
+    assert!(!summary.contains("deep thinking"), "summary: {summary}");
+}
+
+#[test]
+fn deepest_chain_prefers_longest_branch() {
+    // Main chain u1→a1→u2→a2→u3→a3, plus a short branch u1→b1 written last.
+    let lines = [
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "start" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [{"type": "text", "text": "1"}] }
+        }),
+        json!({
+            "type": "user", "uuid": "u2", "parentUuid": "a1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "more" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a2", "parentUuid": "u2",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [{"type": "text", "text": "2"}] }
+        }),
+        json!({
+            "type": "user", "uuid": "u3", "parentUuid": "a2",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "even more" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a3", "parentUuid": "u3",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [{"type": "text", "text": "3"}] }
+        }),
+        json!({
+            "type": "assistant", "uuid": "b1", "parentUuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [{"type": "text", "text": "side"}] }
+        }),
+    ];
+    let contents = lines.iter().map(|v| serde_json::to_string(v).unwrap()).collect::<Vec<_>>().join("\n");
+    let (transcript, _) = Transcript::parse(&contents);
+
+    assert_eq!(transcript.tail(), Some("b1"), "tail is the last entry in the file");
+    assert_eq!(transcript.deepest_chain(), Some("a3"), "deepest leaf is on the main chain");
+    assert_eq!(transcript.select_tail(TailStrategy::Last), Some("b1"));
+    assert_eq!(transcript.select_tail(TailStrategy::Deepest), Some("a3"));
+    assert_eq!(Transcript::empty().deepest_chain(), None);
+}
+
+#[test]
+fn search_finds_bash_command_text() {
+    let lines = [
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "run the tests" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "text", "text": "Running them now." },
+                { "type": "tool_use", "id": "t1", "name": "Bash",
+                  "input": { "command": "cargo test --Workspace", "description": "Run tests" } }
+            ]}
+        }),
+    ];
+    let contents = lines.iter().map(|v| serde_json::to_string(v).unwrap()).collect::<Vec<_>>().join("\n");
+    let (transcript, _) = Transcript::parse(&contents);
+
+    // Case-insensitive match inside the Bash command.
+    assert_eq!(transcript.search("--workspace"), vec!["a1"]);
+    assert_eq!(
+        transcript.search_snippet("a1", "--workspace", 80).as_deref(),
+        Some("cargo test --Workspace")
+    );
+    // User text matches too; both entries mention tests.
+    assert_eq!(transcript.search("TEST"), vec!["u1", "a1"]);
+    assert!(transcript.search("nonexistent").is_empty());
+}
+
+#[test]
+fn to_markdown_renders_prompts_text_and_tools() {
+    let entries = [
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "fix the parser" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "thinking", "thinking": "hmm" },
+                { "type": "tool_use", "id": "t1", "name": "Edit",
+                  "input": { "file_path": "/src/parse.rs", "old_string": "a", "new_string": "b" } },
+                { "type": "text", "text": "Fixed." }
+            ]}
+        }),
+        json!({ "type": "progress", "uuid": "p1", "parentUuid": "a1" }),
+    ];
+    let md = Transcript::to_markdown(&entries);
+    assert_eq!(
+        md,
+        "### User\n\nfix the parser\n\n### Assistant\n\n- Edit parse.rs\n\nFixed."
+    );
+}
+
+#[test]
+fn to_html_renders_the_same_items_as_markdown() {
+    let entries = [
+        json!({ "type": "elided", "count": 3 }),
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "fix <parse>" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "thinking", "thinking": "hmm" },
+                { "type": "tool_use", "id": "t1", "name": "Edit",
+                  "input": { "file_path": "/src/parse.rs" } },
+                { "type": "text", "text": "Fixed." }
+            ]}
+        }),
+    ];
+    let md = Transcript::to_markdown(&entries);
+    assert_eq!(
+        md,
+        "_3 earlier entries elided_\n\n### User\n\nfix <parse>\n\n### Assistant\n\n- Edit parse.rs\n\nFixed."
+    );
+    let html = Transcript::to_html(&entries);
+    assert!(html.contains("<p class=\"elided\">3 earlier entries elided</p>"), "{html}");
+    assert!(html.contains("<div class=\"text\">fix &lt;parse&gt;</div>"), "{html}");
+    assert!(html.contains("<summary>Edit parse.rs</summary>"), "{html}");
+    assert!(html.contains("<div class=\"text\">Fixed.</div>"), "{html}");
+    assert!(!html.contains("hmm"), "{html}");
+}
+
+#[test]
+fn format_short_uses_overridden_nouns() {
+    let lines = [
+        json!({
+            "type": "assistant", "uuid": "a1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "tool_use", "id": "t1", "name": "Bash", "input": { "command": "./build.sh" } },
+                { "type": "tool_use", "id": "t2", "name": "Bash", "input": { "command": "./deploy.sh" } },
+                { "type": "tool_use", "id": "t3", "name": "Edit", "input": { "file_path": "/a.rs" } }
+            ]}
+        }),
+    ];
+    let contents = lines.iter().map(|v| serde_json::to_string(v).unwrap()).collect::<Vec<_>>().join("\n");
+    let (transcript, _) = Transcript::parse(&contents);
+    let turn = transcript.turn("a1", None);
+
+    let mut overrides = std::collections::BTreeMap::new();
+    overrides.insert(
+        "ran".to_string(),
+        Noun { singular: "script".into(), plural: "scripts".into() },
+    );
+    let options = SummaryOptions {
+        nouns: CategoryNouns::with_overrides(overrides),
+        ..Verbosity::Short.into()
+    };
+    let summary = Transcript::summarize_turn(&turn, options).unwrap();
+    assert!(summary.contains("ran 2 scripts"), "summary: {summary}");
+    // Categories without an override keep the English default.
+    assert!(summary.contains("edited 1 file"), "summary: {summary}");
+}
+
+#[test]
+fn tool_timeline_lists_tools_in_call_order() {
+    let lines = [
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "go" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "tool_use", "id": "t1", "name": "Read", "input": { "file_path": "/src/lib.rs" } },
+                { "type": "tool_use", "id": "t2", "name": "Edit", "input": { "file_path": "/src/lib.rs" } }
+            ]}
+        }),
+        json!({
+            "type": "assistant", "uuid": "a2", "parentUuid": "a1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "tool_use", "id": "t3", "name": "Bash", "input": { "command": "cargo test", "description": "Run tests" } },
+                { "type": "tool_use", "id": "t4", "name": "TodoWrite", "input": { "todos": [] } }
+            ]}
+        }),
+    ];
+    let contents = lines.iter().map(|v| serde_json::to_string(v).unwrap()).collect::<Vec<_>>().join("\n");
+    let (transcript, _) = Transcript::parse(&contents);
+    let turn = transcript.turn("a2", None);
+
+    let timeline = Transcript::tool_timeline(&turn);
+    let as_strs: Vec<(&str, &str, &str)> = timeline
+        .iter()
+        .map(|(u, n, l)| (u.as_str(), n.as_str(), l.as_str()))
+        .collect();
+    assert_eq!(
+        as_strs,
+        [
+            ("a1", "Read", "lib.rs"),
+            ("a1", "Edit", "lib.rs"),
+            ("a2", "Bash", "Run tests"),
+            ("a2", "TodoWrite", ""),
+        ]
+    );
+}
+
+#[test]
+fn parse_strips_bom_and_skips_leading_garbage() {
+    let user = json!({
+        "type": "user", "uuid": "u1",
+        "isSidechain": false, "userType": "external",
+        "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+        "message": { "role": "user", "content": "hello" }
+    });
+    let asst = json!({
+        "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+        "isSidechain": false, "userType": "external",
+        "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+        "message": { "role": "assistant", "content": [{ "type": "text", "text": "hi" }] }
+    });
+
+    // BOM on the first line.
+    let contents = format!("\u{feff}{user}\n{asst}\n");
+    let (transcript, errors) = Transcript::parse(&contents);
+    assert!(errors.is_empty(), "errors: {errors:?}");
+    assert_eq!(transcript.entries().len(), 2);
+    assert!(transcript.uuid_exists("u1"));
+    assert_eq!(transcript.skipped_header(), None);
+
+    // A stray header line is skipped, not reported as a parse error.
+    let contents = format!("Script started on 2025-01-01\n{user}\n{asst}\n");
+    let (transcript, errors) = Transcript::parse(&contents);
+    assert!(errors.is_empty(), "errors: {errors:?}");
+    assert_eq!(transcript.entries().len(), 2);
+    assert_eq!(transcript.skipped_header(), Some("Script started on 2025-01-01"));
+}
+
+#[test]
+fn parse_errors_report_physical_line_and_snippet() {
+    let user = json!({
+        "type": "user", "uuid": "u1",
+        "isSidechain": false, "userType": "external",
+        "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+        "message": { "role": "user", "content": "hello" }
+    });
+    let long_garbage = format!("{{\"type\": \"user\", {}", "x".repeat(200));
+    // Blank lines before the bad ones still count toward the line number.
+    let contents = format!("{user}\n\n\n{{\"type\":\n{long_garbage}\n");
+    let (transcript, errors) = Transcript::parse(&contents);
+    assert_eq!(transcript.entries().len(), 1);
+    assert_eq!(errors.len(), 2, "errors: {errors:?}");
+
+    let (line, _, snippet) = &errors[0];
+    assert_eq!(*line, 4);
+    assert_eq!(snippet, "{\"type\":");
+
+    let (line, _, snippet) = &errors[1];
+    assert_eq!(*line, 5);
+    assert!(snippet.starts_with("{\"type\": \"user\", xxx"), "snippet: {snippet}");
+    assert!(snippet.ends_with("..."), "snippet: {snippet}");
+    assert_eq!(snippet.chars().count(), 83);
+}
+
+#[test]
+fn session_id_comes_from_first_conversation_entry() {
+    let (empty, _) = Transcript::parse("");
+    assert_eq!(empty.session_id(), None);
+
+    let lines = [
+        // Queue operations carry a session ID but aren't conversation.
+        json!({ "type": "queue-operation", "operation": "enqueue", "timestamp": "t", "sessionId": "queued" }),
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "hi" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "first", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [{ "type": "text", "text": "hello" }] }
+        }),
+        json!({
+            "type": "user", "uuid": "u2", "parentUuid": "a1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "resumed", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "again" }
+        }),
+    ];
+    let contents = lines.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("\n");
+    let (transcript, errors) = Transcript::parse(&contents);
+    assert!(errors.is_empty(), "errors: {errors:?}");
+    assert_eq!(transcript.session_id(), Some("first"));
+}
+
+#[test]
+fn resume_points_mark_session_id_changes() {
+    let entry = |uuid: &str, parent: Option<&str>, sid: &str| {
+        json!({
+            "type": "user", "uuid": uuid, "parentUuid": parent,
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": sid, "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "hi" }
+        })
+    };
+    let lines = [
+        entry("u1", None, "first"),
+        entry("u2", Some("u1"), "first"),
+        // Snapshots carry no session ID and don't affect boundaries.
+        json!({ "type": "file-history-snapshot", "messageId": "m", "snapshot": {
+            "messageId": "m", "trackedFileBackups": {}, "timestamp": "t" }, "isSnapshotUpdate": false }),
+        entry("u3", Some("u2"), "second"),
+        entry("u4", Some("u3"), "second"),
+    ];
+    let contents = lines.iter().map(|v| serde_json::to_string(v).unwrap()).collect::<Vec<_>>().join("\n");
+    let (transcript, errors) = Transcript::parse(&contents);
+    assert!(errors.is_empty(), "errors: {errors:?}");
+    assert_eq!(transcript.resume_points(), vec![3]);
+}
+
+#[test]
+fn commands_run_returns_bash_commands_verbatim_in_order() {
+    let long = format!("cargo test --workspace -- {}", "--nocapture ".repeat(10));
+    let lines = [
+        json!({
+            "type": "user", "uuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": "build and test" }
+        }),
+        json!({
+            "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "tool_use", "id": "t1", "name": "Bash",
+                  "input": { "command": "cargo build", "description": "Build" } },
+                { "type": "tool_use", "id": "t2", "name": "Read",
+                  "input": { "file_path": "/tmp/x.rs" } }
+            ]}
+        }),
+        json!({
+            "type": "assistant", "uuid": "a2", "parentUuid": "a1",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "assistant", "content": [
+                { "type": "tool_use", "id": "t3", "name": "Bash",
+                  "input": { "command": long, "run_in_background": true } }
+            ]}
+        }),
+        json!({
+            "type": "user", "uuid": "r3", "parentUuid": "a2",
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": "user", "content": [
+                { "type": "tool_result", "tool_use_id": "t3", "content": "" }
+            ]},
+            "toolUseResult": { "stdout": "", "stderr": "", "interrupted": true, "isImage": false }
+        }),
+    ];
+    let contents = lines.iter().map(|v| serde_json::to_string(v).unwrap()).collect::<Vec<_>>().join("\n");
+    let (transcript, _) = Transcript::parse(&contents);
+    let turn = transcript.turn("r3", Some("u1"));
+    assert_eq!(
+        Transcript::commands_run(&turn),
+        ["cargo build".to_string(), format!("{long} &  # interrupted")],
+    );
+}
+
+#[test]
+fn as_conversation_pairs_pairs_prompts_with_replies() {
+    let entry = |kind: &str, uuid: &str, parent: Option<&str>, content: serde_json::Value| {
+        json!({
+            "type": kind, "uuid": uuid, "parentUuid": parent,
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+            "message": { "role": kind, "content": content }
+        })
+    };
+    let lines = [
+        entry("user", "u1", None, json!("first question")),
+        entry("assistant", "a1", Some("u1"), json!([
+            {"type": "text", "text": "let me look"},
+            {"type": "tool_use", "id": "t1", "name": "Read", "input": {"file_path": "/x"}}
+        ])),
+        entry("user", "r1", Some("a1"), json!([
+            {"type": "tool_result", "tool_use_id": "t1", "content": "contents"}
+        ])),
+        entry("assistant", "a2", Some("r1"), json!([{"type": "text", "text": "it says contents"}])),
+        entry("user", "u2", Some("a2"), json!("second question")),
+        entry("assistant", "a3", Some("u2"), json!([{"type": "text", "text": "second answer"}])),
+    ];
+    let contents: String = lines.iter().map(|l| format!("{l}\n")).collect();
+    let (t, _) = Transcript::parse(&contents);
+
+    assert_eq!(
+        t.as_conversation_pairs(),
+        vec![
+            ("first question".to_string(), "let me look\n\nit says contents".to_string()),
+            ("second question".to_string(), "second answer".to_string()),
+        ]
+    );
+}
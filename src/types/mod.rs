@@ -435,6 +435,73 @@ pub struct HookOutput {
     pub hook_specific_output: Option<HookSpecificOutput>,
 }
 
+impl HookOutput {
+    /// A message shown to the user; the action proceeds.
+    pub fn hint(message: impl Into<String>) -> Self {
+        Self {
+            system_message: Some(message.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Block the action, with `reason` shown to Claude (and relayed
+    /// verbatim for UserPromptSubmit).
+    pub fn block(reason: impl Into<String>) -> Self {
+        Self {
+            decision: Some("block".into()),
+            reason: Some(reason.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Add `context` to Claude's context via the event's
+    /// `hookSpecificOutput.additionalContext`.
+    pub fn additional_context(event: ContextEvent, context: impl Into<String>) -> Self {
+        let additional_context = Some(context.into());
+        let specific = match event {
+            ContextEvent::SessionStart => {
+                HookSpecificOutput::SessionStart(SessionStartOutput { additional_context })
+            }
+            ContextEvent::UserPromptSubmit => {
+                HookSpecificOutput::UserPromptSubmit(UserPromptSubmitOutput { additional_context })
+            }
+            ContextEvent::PreToolUse => HookSpecificOutput::PreToolUse(PreToolUseOutput {
+                additional_context,
+                ..Default::default()
+            }),
+            ContextEvent::PostToolUse => HookSpecificOutput::PostToolUse(PostToolUseOutput {
+                additional_context,
+                ..Default::default()
+            }),
+            ContextEvent::PostToolUseFailure => {
+                HookSpecificOutput::PostToolUseFailure(PostToolUseFailureOutput { additional_context })
+            }
+            ContextEvent::Notification => {
+                HookSpecificOutput::Notification(NotificationOutput { additional_context })
+            }
+            ContextEvent::SubagentStart => {
+                HookSpecificOutput::SubagentStart(SubagentStartOutput { additional_context })
+            }
+        };
+        Self {
+            hook_specific_output: Some(specific),
+            ..Default::default()
+        }
+    }
+}
+
+/// Hook events whose output can carry `additionalContext` for Claude.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextEvent {
+    SessionStart,
+    UserPromptSubmit,
+    PreToolUse,
+    PostToolUse,
+    PostToolUseFailure,
+    Notification,
+    SubagentStart,
+}
+
 /// Event-specific output, tagged by `hookEventName`.
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "hookEventName")]
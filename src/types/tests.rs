@@ -432,3 +432,48 @@ fn output_round_trip() {
     assert_eq!(deserialized.decision.as_deref(), Some("block"));
     assert_eq!(deserialized.reason.as_deref(), Some("test"));
 }
+
+// =================================================================
+// HookOutput constructors
+// =================================================================
+
+#[test]
+fn hook_output_hint_sets_system_message_only() {
+    let v = serde_json::to_value(HookOutput::hint("saved")).unwrap();
+    assert_eq!(v, json!({ "systemMessage": "saved" }));
+}
+
+#[test]
+fn hook_output_block_sets_decision_and_reason() {
+    let v = serde_json::to_value(HookOutput::block("not now")).unwrap();
+    assert_eq!(v, json!({ "decision": "block", "reason": "not now" }));
+}
+
+#[test]
+fn hook_output_additional_context_is_tagged_by_event() {
+    let v = serde_json::to_value(HookOutput::additional_context(
+        ContextEvent::SessionStart,
+        "resumed",
+    ))
+    .unwrap();
+    assert_eq!(
+        v,
+        json!({ "hookSpecificOutput": {
+            "hookEventName": "SessionStart",
+            "additionalContext": "resumed"
+        }})
+    );
+
+    let v = serde_json::to_value(HookOutput::additional_context(
+        ContextEvent::PostToolUse,
+        "note",
+    ))
+    .unwrap();
+    assert_eq!(
+        v,
+        json!({ "hookSpecificOutput": {
+            "hookEventName": "PostToolUse",
+            "additionalContext": "note"
+        }})
+    );
+}
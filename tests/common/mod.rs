@@ -1,8 +1,32 @@
 use std::io::Write;
+use std::path::Path;
 use std::process::{Command, Stdio};
 
+/// `XDG_CONFIG_HOME` for every child unless a test sets its own: a path
+/// that doesn't exist, so a developer's global clautribution.toml can't
+/// leak into the tests.
+const NO_GLOBAL_CONFIG: &str = "/nonexistent/clautribution-test-config";
+
 pub fn run_cli(stdin_json: &str) -> (i32, String, String) {
+    run_cli_with_env(stdin_json, &[])
+}
+
+/// Like `run_cli`, with extra environment variables set on the child.
+pub fn run_cli_with_env(stdin_json: &str, envs: &[(&str, &str)]) -> (i32, String, String) {
+    run_cli_with_args(&[], stdin_json, envs)
+}
+
+/// Like `run_cli_with_env`, with extra command-line arguments (e.g.
+/// `--verbose`) passed to the hook.
+pub fn run_cli_with_args(
+    args: &[&str],
+    stdin_json: &str,
+    envs: &[(&str, &str)],
+) -> (i32, String, String) {
     let mut child = Command::new(env!("CARGO_BIN_EXE_clautribution"))
+        .args(args)
+        .env("XDG_CONFIG_HOME", NO_GLOBAL_CONFIG)
+        .envs(envs.iter().copied())
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -24,6 +48,47 @@ pub fn run_cli(stdin_json: &str) -> (i32, String, String) {
     )
 }
 
+/// Run a subcommand (e.g. `["diff", cwd]`) with empty stdin.
+#[allow(dead_code)]
+pub fn run_subcommand(args: &[&str]) -> (i32, String, String) {
+    run_subcommand_with_env(args, &[])
+}
+
+/// Like `run_subcommand`, with extra environment variables set on the child.
+#[allow(dead_code)]
+pub fn run_subcommand_with_env(args: &[&str], envs: &[(&str, &str)]) -> (i32, String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_clautribution"))
+        .args(args)
+        .env("XDG_CONFIG_HOME", NO_GLOBAL_CONFIG)
+        .envs(envs.iter().copied())
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to run binary");
+    (
+        output.status.code().unwrap_or(-1),
+        String::from_utf8_lossy(&output.stdout).to_string(),
+        String::from_utf8_lossy(&output.stderr).to_string(),
+    )
+}
+
+/// Place `contents` where session discovery looks for transcripts,
+/// `$HOME/.claude/projects/<mangled workdir>/<session_id>.jsonl`, under a
+/// fresh temp home.  Returns the home dir, to be passed as `HOME`.
+#[allow(dead_code)]
+pub fn install_transcript(repo: &Path, session_id: &str, contents: &str) -> tempfile::TempDir {
+    let home = tempfile::tempdir().unwrap();
+    let mangled = repo
+        .canonicalize()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .replace('/', "-");
+    let projects = home.path().join(".claude/projects").join(mangled);
+    std::fs::create_dir_all(&projects).unwrap();
+    std::fs::write(projects.join(format!("{session_id}.jsonl")), contents).unwrap();
+    home
+}
+
 /// Create a temp dir containing a git repo with an initial commit and return it.
 /// The `TempDir` must be kept alive for the duration of the test.
 pub fn temp_git_repo() -> tempfile::TempDir {
@@ -2,7 +2,7 @@ mod common;
 
 use std::fs;
 
-use common::{common, run_cli, temp_git_repo};
+use common::{common, run_cli, run_cli_with_env, temp_git_repo};
 
 #[test]
 fn handle_user_prompt_submit() {
@@ -48,3 +48,51 @@ fn handle_user_prompt_submit_blocks_on_uncommitted_changes() {
         "expected reason about uncommitted changes, got: {stdout}"
     );
 }
+
+#[test]
+fn prompt_metadata_records_submission_time_and_slash_commands() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let meta_path = repo.path().join(".clautribution/prompt-test-session.json");
+
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "UserPromptSubmit", "prompt": "/review the parser" }}"#
+    );
+    let (code, _stdout, stderr) = run_cli(&input);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    let meta: serde_json::Value = serde_json::from_str(&fs::read_to_string(&meta_path).unwrap()).unwrap();
+    assert_eq!(meta["prompt"], "/review the parser");
+    assert_eq!(meta["is_slash_command"], true);
+    let submitted_at = meta["submitted_at"].as_str().unwrap();
+    assert_eq!(submitted_at.len(), "2025-01-01T00:00:00Z".len(), "submitted_at: {submitted_at}");
+    assert!(submitted_at.starts_with("20") && submitted_at.ends_with('Z'), "submitted_at: {submitted_at}");
+
+    let input = input.replace("/review the parser", "fix the parser");
+    let (code, _stdout, stderr) = run_cli(&input);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    let meta: serde_json::Value = serde_json::from_str(&fs::read_to_string(&meta_path).unwrap()).unwrap();
+    assert_eq!(meta["is_slash_command"], false);
+}
+
+#[test]
+fn suppress_hints_marks_output_suppressed() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "UserPromptSubmit", "prompt": "hello world" }}"#
+    );
+
+    let (code, stdout, stderr) =
+        run_cli_with_env(&input, &[("CLAUTRIBUTION_PREFERENCES", r#"{"suppress_hints": true}"#)]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    let output: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(output["suppressOutput"], true, "got: {stdout}");
+
+    let (_, stdout, _) = run_cli(&input);
+    let output: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert!(output["suppressOutput"].is_null(), "got: {stdout}");
+}
@@ -1,6 +1,8 @@
 mod common;
 
-use common::{common, run_cli, temp_git_repo};
+use std::fs;
+
+use common::{common, run_cli, run_cli_with_env, temp_git_repo};
 
 #[test]
 fn handle_session_start() {
@@ -25,3 +27,102 @@ fn handle_session_start() {
     );
     assert!(repo.path().join(".clautribution").is_dir());
 }
+
+#[test]
+fn preferences_env_var_overrides_file() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let common = common(cwd, "/tmp/t.jsonl");
+    let input = format!(
+        r#"{{ {common},
+            "hook_event_name": "SessionStart",
+            "source": "startup"
+        }}"#
+    );
+    // Only warn_branches is overridden; the test repo is on master, which
+    // would otherwise trigger the branch warning.
+    let (code, stdout, stderr) =
+        run_cli_with_env(&input, &[("CLAUTRIBUTION_PREFERENCES", r#"{"warn_branches": []}"#)]);
+    assert_eq!(code, 0);
+    assert!(stderr.is_empty(), "expected no stderr, got: {stderr}");
+    assert!(
+        !stdout.contains("feature branch"),
+        "env override should disable the branch warning, got: {stdout}"
+    );
+
+    // The override is not persisted, and other fields keep file defaults.
+    let toml = std::fs::read_to_string(repo.path().join(".clautribution/clautribution.toml")).unwrap();
+    assert!(toml.contains("\"master\""), "file keeps default warn_branches: {toml}");
+    assert!(toml.contains("summary_verbosity = \"medium\""), "file: {toml}");
+}
+
+#[test]
+fn resume_injects_context_about_accumulated_work() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    let track = || {
+        fs::write(
+            data_dir.join("prompt-test-session.json"),
+            r#"{"prompt":"refactor the parser","session_id":"s","uuid":"u1"}"#,
+        ).unwrap();
+        fs::write(
+            data_dir.join("continuation-test-session.json"),
+            r#"{"tail_uuid":"a1","session_id":"test-session"}"#,
+        ).unwrap();
+    };
+
+    let common = common(cwd, "/tmp/t.jsonl");
+    let input = format!(
+        r#"{{ {common},
+            "hook_event_name": "SessionStart",
+            "source": "resume"
+        }}"#
+    );
+
+    // Tracking state left by a turn that changed nothing isn't work.
+    track();
+    let (code, stdout, stderr) = run_cli(&input);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    let output: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert!(output["hookSpecificOutput"].is_null(), "got: {stdout}");
+
+    track();
+    fs::write(repo.path().join("parser.rs"), "fn parse() {}\n").unwrap();
+    let (code, stdout, stderr) = run_cli(&input);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    let output: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    let hso = &output["hookSpecificOutput"];
+    assert_eq!(hso["hookEventName"], "SessionStart");
+    let context = hso["additionalContext"].as_str().unwrap();
+    assert!(context.contains("refactor the parser"), "context: {context}");
+
+    // A fresh startup with the same state gets no additional context.
+    let input = input.replace("\"resume\"", "\"startup\"");
+    let (_, stdout, _) = run_cli(&input);
+    let output: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert!(output["hookSpecificOutput"].is_null(), "got: {stdout}");
+}
+
+#[test]
+fn log_env_var_records_input_and_output() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let log = repo.path().join("hook.log");
+    let common = common(cwd, "/tmp/t.jsonl");
+    let input = format!(
+        r#"{{ {common},
+            "hook_event_name": "SessionStart",
+            "source": "startup"
+        }}"#
+    );
+    let (code, stdout, stderr) =
+        run_cli_with_env(&input, &[("CLAUTRIBUTION_LOG", log.to_str().unwrap())]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+
+    let logged = fs::read_to_string(&log).unwrap();
+    assert!(logged.contains(" input: {"), "log: {logged}");
+    assert!(logged.contains(r#""hook_event_name":"SessionStart""#), "log: {logged}");
+    assert!(logged.contains(&format!(" output: {}", stdout.trim())), "log: {logged}");
+}
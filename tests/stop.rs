@@ -2,7 +2,7 @@ mod common;
 
 use std::fs;
 
-use common::{common, read_note, run_cli, temp_git_repo};
+use common::{common, read_note, run_cli, run_cli_with_args, run_cli_with_env, run_subcommand_with_env, temp_git_repo};
 
 #[test]
 fn handle_stop() {
@@ -386,3 +386,1096 @@ fn reset_detected_via_breadcrumb() {
     let msg = out["systemMessage"].as_str().unwrap();
     assert!(msg.contains("reset detected"), "expected reset detected via breadcrumb, got: {msg}");
 }
+
+#[test]
+fn handle_stop_marks_binary_files_in_diffstat() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"make a logo"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"done"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"make a logo","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    fs::write(repo.path().join("logo.png"), [0x89, b'P', b'N', b'G', 0, 0, 0, 0x0d, 0xff, 0]).unwrap();
+    fs::write(repo.path().join("notes.txt"), "one\ntwo\n").unwrap();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let (code, _stdout, stderr) = run_cli(&input);
+    assert_eq!(code, 0, "stderr: {stderr}");
+
+    let diffstat = read_note(repo.path(), "refs/notes/diffstat").expect("expected diffstat note");
+    assert!(diffstat.contains("logo.png (binary)"), "diffstat: {diffstat}");
+    assert!(diffstat.contains("notes.txt +2 -0"), "diffstat: {diffstat}");
+}
+
+#[test]
+fn handle_stop_falls_back_when_transcript_path_is_stale() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+
+    // Place the real transcript where discovery looks for it:
+    // $HOME/.claude/projects/<mangled workdir>/<session>.jsonl
+    let home = tempfile::tempdir().unwrap();
+    let mangled = repo
+        .path()
+        .canonicalize()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .replace('/', "-");
+    let projects = home.path().join(".claude/projects").join(mangled);
+    fs::create_dir_all(&projects).unwrap();
+    fs::write(projects.join("test-session.jsonl"), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"hello"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"hi"}]}}"#, "\n",
+    )).unwrap();
+
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"hello","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    fs::write(repo.path().join("output.txt"), "result").unwrap();
+
+    // The hook input points at a transcript that no longer exists.
+    let stale = home.path().join("rotated-away.jsonl");
+    let common = common(cwd, stale.to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let (code, _stdout, stderr) =
+        run_cli_with_env(&input, &[("HOME", home.path().to_str().unwrap())]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+
+    // The discovered transcript was used: the commit carries its tail.
+    assert_eq!(read_note(repo.path(), "refs/notes/tail").as_deref(), Some("a1"));
+}
+
+#[test]
+fn handle_stop_writes_markdown_transcript_note() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"hello"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"hi"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"hello","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    fs::write(repo.path().join("output.txt"), "result").unwrap();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let (code, _stdout, stderr) = run_cli_with_env(
+        &input,
+        &[("CLAUTRIBUTION_PREFERENCES", r#"{"transcript_note_format":"markdown"}"#)],
+    );
+    assert_eq!(code, 0, "stderr: {stderr}");
+
+    let note = read_note(repo.path(), "refs/notes/transcript").expect("expected transcript note");
+    assert!(note.contains("hello"), "note: {note}");
+    assert!(!note.contains('{') && !note.contains('}'), "raw JSON in note: {note}");
+}
+
+#[test]
+fn handle_stop_reentrant_at_committed_tail_does_not_recommit() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"hello"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"hi"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"hello","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    fs::write(repo.path().join("output.txt"), "result").unwrap();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let first = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let (code, _stdout, stderr) = run_cli(&first);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let committed = git.head().unwrap().peel_to_commit().unwrap().id();
+
+    // Another hook touches the tree and blocks; Stop re-fires at the same tail.
+    fs::write(repo.path().join("formatted.txt"), "by another hook").unwrap();
+    let refire = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": true }}"#
+    );
+    let (code, stdout, stderr) = run_cli(&refire);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.is_empty(), "expected no output, got: {stdout}");
+    assert_eq!(
+        git.head().unwrap().peel_to_commit().unwrap().id(),
+        committed,
+        "re-entrant stop must not create a second commit"
+    );
+}
+
+#[test]
+fn handle_stop_defers_commit_within_min_interval() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let prefs = [("CLAUTRIBUTION_PREFERENCES", r#"{"min_commit_interval_secs":3600}"#)];
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    let turn1 = concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"first"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"ok"}]}}"#, "\n",
+    );
+    fs::write(transcript.path(), turn1).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"first","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    fs::write(repo.path().join("one.txt"), "1").unwrap();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let (code, _stdout, stderr) = run_cli_with_env(&input, &prefs);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let first_commit = git.head().unwrap().peel_to_commit().unwrap().id();
+    assert_eq!(read_note(repo.path(), "refs/notes/tail").as_deref(), Some("a1"));
+
+    // Second productive turn moments later: deferred, not committed.
+    fs::write(transcript.path(), format!("{turn1}{}", concat!(
+        r#"{"type":"user","uuid":"u2","parentUuid":"a1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"second"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a2","parentUuid":"u2","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r2","message":{"role":"assistant","content":[{"type":"text","text":"ok"}]}}"#, "\n",
+    ))).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"second","session_id":"s","uuid":"u2"}"#,
+    ).unwrap();
+    fs::write(repo.path().join("two.txt"), "2").unwrap();
+    let (code, stdout, stderr) = run_cli_with_env(&input, &prefs);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    let output: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    let msg = output["systemMessage"].as_str().unwrap();
+    assert!(msg.contains("commit deferred"), "got: {msg}");
+    assert_eq!(
+        git.head().unwrap().peel_to_commit().unwrap().id(),
+        first_commit,
+        "second stop inside the interval must not commit"
+    );
+    assert!(data_dir.join("continuation-test-session.json").exists(), "expected breadcrumb");
+
+    // The deferred changes don't block the next prompt or trigger a rewind.
+    let submit = format!(
+        r#"{{ {common}, "hook_event_name": "UserPromptSubmit", "prompt": "third" }}"#
+    );
+    let (code, stdout, stderr) = run_cli_with_env(&submit, &prefs);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    let output: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert!(output["decision"].is_null(), "prompt blocked: {stdout}");
+    assert_eq!(git.head().unwrap().peel_to_commit().unwrap().id(), first_commit);
+}
+
+#[test]
+fn handle_stop_splits_commits_by_directory() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"touch both"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"done"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"touch both","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    fs::create_dir_all(repo.path().join("src")).unwrap();
+    fs::create_dir_all(repo.path().join("docs")).unwrap();
+    fs::write(repo.path().join("src/lib.rs"), "pub fn f() {}\n").unwrap();
+    fs::write(repo.path().join("docs/guide.md"), "# Guide\n").unwrap();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let (code, _stdout, stderr) = run_cli_with_env(
+        &input,
+        &[("CLAUTRIBUTION_PREFERENCES", r#"{"split_commits_by":"directory","separate_added_files":true}"#)],
+    );
+    assert_eq!(code, 0, "stderr: {stderr}");
+
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let head = git.head().unwrap().peel_to_commit().unwrap();
+    let parent = head.parent(0).unwrap();
+    assert_eq!(parent.parent(0).unwrap().summary(), Some("initial"));
+    // Groups are committed in sorted order: docs, then src.
+    assert_eq!(parent.summary(), Some("docs: touch both"));
+    assert_eq!(head.summary(), Some("src: touch both"));
+    assert!(parent.tree().unwrap().get_path(std::path::Path::new("src/lib.rs")).is_err());
+    assert!(head.tree().unwrap().get_path(std::path::Path::new("docs/guide.md")).is_ok());
+    // Each commit lists only its own files.
+    assert!(parent.message().unwrap().ends_with("\n\nAdded: docs/guide.md"), "{:?}", parent.message());
+    assert!(head.message().unwrap().ends_with("\n\nAdded: src/lib.rs"), "{:?}", head.message());
+
+    // Each commit carries its own tail and diffstat.
+    for oid in [parent.id(), head.id()] {
+        let note = git.find_note(Some("refs/notes/tail"), oid).unwrap();
+        assert_eq!(note.message(), Some("a1"));
+        assert!(git.find_note(Some("refs/notes/diffstat"), oid).is_ok());
+    }
+    // The transcript and prompt go on the last commit only; the earlier
+    // one points at it.
+    for ref_name in ["refs/notes/transcript", "refs/notes/prompt"] {
+        assert!(git.find_note(Some(ref_name), head.id()).is_ok(), "{ref_name} on last");
+        assert!(git.find_note(Some(ref_name), parent.id()).is_err(), "{ref_name} on earlier");
+    }
+    let pointer = git.find_note(Some("refs/notes/split"), parent.id()).unwrap();
+    assert_eq!(pointer.message(), Some(head.id().to_string().as_str()));
+    assert!(git.find_note(Some("refs/notes/split"), head.id()).is_err());
+}
+
+#[test]
+fn handle_stop_tolerates_invalid_utf8_in_transcript() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(br#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"fix it"}}"#);
+    bytes.push(b'\n');
+    bytes.extend_from_slice(br#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"bad "#);
+    bytes.extend_from_slice(&[0xff, 0xfe]);
+    bytes.extend_from_slice(br#" byte"}]}}"#);
+    bytes.push(b'\n');
+    fs::write(transcript.path(), bytes).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"fix it","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    fs::write(repo.path().join("new.txt"), "hello\n").unwrap();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let (code, _stdout, stderr) = run_cli(&input);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stderr.contains("invalid UTF-8"), "stderr: {stderr}");
+
+    // Both entries parsed: the commit's tail is the assistant reply.
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let head = git.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.summary(), Some("fix it"));
+    let note = git.find_note(Some("refs/notes/tail"), head.id()).unwrap();
+    assert_eq!(note.message(), Some("a1"));
+}
+
+#[test]
+fn handle_stop_uses_custom_notes_namespace() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"hello"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"hi"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"hello","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    fs::write(repo.path().join("output.txt"), "result").unwrap();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let env = [("CLAUTRIBUTION_PREFERENCES", r#"{"notes_namespace":"refs/clautribution"}"#)];
+    let (code, _stdout, stderr) = run_cli_with_env(&input, &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert_eq!(read_note(repo.path(), "refs/clautribution/tail").as_deref(), Some("a1"));
+    assert!(read_note(repo.path(), "refs/clautribution/transcript").is_some());
+    assert!(read_note(repo.path(), "refs/notes/tail").is_none());
+
+    // The tail is read back from the namespace: a follow-up commit only
+    // carries entries after it.
+    fs::write(repo.path().join("later.txt"), "more").unwrap();
+    let (code, stdout, stderr) = run_cli_with_env(&input, &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("(0 transcript entries)"), "stdout: {stdout}");
+}
+
+#[test]
+fn handle_stop_layers_repo_preferences_over_global() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"hello"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"hi"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"hello","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    fs::write(
+        data_dir.join("clautribution.toml"),
+        "subject_suffix = \" (repo)\"\n[category_icons]\nedited = \"E\"\n",
+    ).unwrap();
+    fs::write(repo.path().join("output.txt"), "result").unwrap();
+
+    let config = tempfile::tempdir().unwrap();
+    fs::create_dir_all(config.path().join("clautribution")).unwrap();
+    fs::write(
+        config.path().join("clautribution/clautribution.toml"),
+        "subject_prefix = \"[AI] \"\nsubject_suffix = \" (global)\"\n[category_icons]\nran = \"R\"\n",
+    ).unwrap();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let (code, _stdout, stderr) =
+        run_cli_with_env(&input, &[("XDG_CONFIG_HOME", config.path().to_str().unwrap())]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+
+    // The prefix comes from the global file; the repo's suffix wins.
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let head = git.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.summary(), Some("[AI] hello (repo)"));
+
+    // Tables merge key by key rather than the repo's replacing the global.
+    let (code, stdout, stderr) = run_subcommand_with_env(
+        &["config", "get", cwd, "category_icons"],
+        &[("XDG_CONFIG_HOME", config.path().to_str().unwrap())],
+    );
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("edited = \"E\"") && stdout.contains("ran = \"R\""), "stdout: {stdout}");
+}
+
+#[test]
+fn handle_stop_keeps_commit_when_notes_write_fails() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"hello"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"hi"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"hello","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    fs::write(repo.path().join("output.txt"), "result").unwrap();
+    // Simulate a concurrent git process holding the transcript notes ref.
+    let notes_dir = repo.path().join(".git/refs/notes");
+    fs::create_dir_all(&notes_dir).unwrap();
+    fs::write(notes_dir.join("transcript.lock"), "").unwrap();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let (code, stdout, stderr) = run_cli(&input);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stderr.contains("refs/notes/transcript"), "expected a warning, got: {stderr}");
+    assert!(stderr.contains("tail note is missing"), "got: {stderr}");
+    assert!(!stderr.contains("warning: warning:"), "got: {stderr}");
+    let output: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert!(output["systemMessage"].as_str().unwrap().contains("committed"), "got: {stdout}");
+
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let head = git.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.summary(), Some("hello"));
+    assert!(head.tree().unwrap().get_name("output.txt").is_some());
+}
+
+#[test]
+fn handle_stop_fails_cleanly_when_index_is_locked() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"hello"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"hi"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"hello","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    fs::write(repo.path().join("output.txt"), "result").unwrap();
+    // A lock that's never released: every retry sees contention.
+    fs::write(repo.path().join(".git/index.lock"), "").unwrap();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let (code, stdout, stderr) = run_cli(&input);
+    assert_eq!(code, 2, "stdout: {stdout}");
+    assert!(stderr.contains("index is locked by another process after 3 attempts"), "stderr: {stderr}");
+    assert!(!stderr.contains("panicked"), "stderr: {stderr}");
+
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let head = git.head().unwrap().peel_to_commit().unwrap();
+    assert_ne!(head.summary(), Some("hello"), "no commit while the index is locked");
+}
+
+#[test]
+fn repeated_stop_without_new_entries_short_circuits() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"hello"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"hi"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"hello","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+
+    let (code, stdout, stderr) = run_cli(&input);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("nonproductive"), "first stop decides: {stdout}");
+    assert_eq!(
+        fs::read_to_string(data_dir.join("last-stop-tail-test-session.txt")).unwrap(),
+        "a1"
+    );
+
+    // Same tail, still nothing to commit: no decision, no output.
+    let (code, stdout, stderr) = run_cli(&input);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.is_empty(), "second stop should short-circuit, got: {stdout}");
+
+    // Changes since then → the guard no longer applies.
+    fs::write(repo.path().join("output.txt"), "result").unwrap();
+    let (code, stdout, stderr) = run_cli(&input);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("committed changes"), "got: {stdout}");
+}
+
+#[test]
+fn verbose_flag_logs_stop_decision() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"hello"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"hi"}]}}"#, "\n",
+        "not json\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"hello","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    fs::write(repo.path().join("output.txt"), "result").unwrap();
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+
+    let (code, stdout, stderr) = run_cli_with_args(&["--verbose"], &input, &[]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("committed changes"), "got: {stdout}");
+    assert!(stderr.contains("debug: session test-session"), "stderr: {stderr}");
+    assert!(stderr.contains("debug: tail a1"), "stderr: {stderr}");
+    assert!(stderr.contains("debug: decision Productive"), "stderr: {stderr}");
+    assert!(stderr.contains("parse error at line 3"), "warnings still shown: {stderr}");
+
+    // --quiet drops the parse warning.
+    fs::write(repo.path().join("output.txt"), "more").unwrap();
+    let (code, _, stderr) = run_cli_with_args(&["--quiet"], &input, &[]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stderr.is_empty(), "expected no stderr with --quiet, got: {stderr}");
+}
+
+#[test]
+fn commit_branch_commits_without_moving_head() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"hello"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"hi"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"hello","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    fs::write(repo.path().join("output.txt"), "result").unwrap();
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let head_before = git.head().unwrap().target().unwrap();
+    // Something the user staged themselves.
+    fs::write(repo.path().join("staged.txt"), "mine").unwrap();
+    let mut index = git.index().unwrap();
+    index.add_path(std::path::Path::new("staged.txt")).unwrap();
+    index.write().unwrap();
+    let index_before = fs::read(repo.path().join(".git/index")).unwrap();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let env = [("CLAUTRIBUTION_PREFERENCES", r#"{"commit_branch": "claude/work"}"#)];
+    let (code, stdout, stderr) = run_cli_with_env(&input, &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("committed changes"), "got: {stdout}");
+
+    // HEAD and the index stay where they were.
+    assert_eq!(git.head().unwrap().target().unwrap(), head_before);
+    assert_eq!(fs::read(repo.path().join(".git/index")).unwrap(), index_before);
+    let index = git.index().unwrap();
+    assert!(index.get_path(std::path::Path::new("output.txt"), 0).is_none());
+    assert!(index.get_path(std::path::Path::new("staged.txt"), 0).is_some());
+
+    // The commit is on the branch, parented on the old HEAD.
+    let branch = git.find_branch("claude/work", git2::BranchType::Local).unwrap();
+    let commit = branch.get().peel_to_commit().unwrap();
+    assert_eq!(commit.parent_id(0).unwrap(), head_before);
+    assert_eq!(commit.message().unwrap().lines().next(), Some("hello"));
+    assert!(commit.tree().unwrap().get_name("output.txt").is_some());
+
+    // The working tree matches the branch now, so nothing is pending.
+    let (code, stdout, stderr) = run_cli_with_env(&input, &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(!stdout.contains("committed changes"), "got: {stdout}");
+    let tip = git.find_branch("claude/work", git2::BranchType::Local).unwrap();
+    assert_eq!(tip.get().target().unwrap(), commit.id());
+
+    // Pending changes are measured against the branch tip, not HEAD.
+    let (code, stdout, stderr) = run_subcommand_with_env(&["diff", cwd], &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.is_empty(), "already on the branch: {stdout}");
+    fs::write(repo.path().join("second.txt"), "more").unwrap();
+    let (_, stdout, _) = run_subcommand_with_env(&["diff", cwd], &env);
+    assert!(stdout.contains("second.txt"), "diff: {stdout}");
+    assert!(!stdout.contains("output.txt"), "diff: {stdout}");
+}
+
+#[test]
+fn amend_consecutive_grows_a_single_commit() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let initial = git.head().unwrap().target().unwrap();
+    let env = [("CLAUTRIBUTION_PREFERENCES", r#"{"amend_consecutive": true}"#)];
+
+    let turn1 = concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"hello"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"hi"}]}}"#, "\n",
+    );
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), turn1).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"hello","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    fs::write(repo.path().join("first.txt"), "one").unwrap();
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let (code, stdout, stderr) = run_cli_with_env(&input, &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("committed changes"), "got: {stdout}");
+    let first = git.head().unwrap().target().unwrap();
+    assert_ne!(first, initial);
+
+    // Second turn in the same session, with more changes.
+    fs::write(transcript.path(), format!("{turn1}{}", concat!(
+        r#"{"type":"user","uuid":"u2","parentUuid":"a1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"and another"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a2","parentUuid":"u2","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r2","message":{"role":"assistant","content":[{"type":"text","text":"ok"}]}}"#, "\n",
+    ))).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"and another","session_id":"s","uuid":"u2"}"#,
+    ).unwrap();
+    fs::write(repo.path().join("second.txt"), "two").unwrap();
+    let (code, stdout, stderr) = run_cli_with_env(&input, &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("committed changes"), "got: {stdout}");
+
+    // One commit on top of the initial one, holding both turns' work.
+    let head = git.head().unwrap().peel_to_commit().unwrap();
+    assert_ne!(head.id(), first);
+    assert_eq!(head.parent_count(), 1);
+    assert_eq!(head.parent_id(0).unwrap(), initial);
+    let tree = head.tree().unwrap();
+    assert!(tree.get_name("first.txt").is_some());
+    assert!(tree.get_name("second.txt").is_some());
+    assert_eq!(head.message().unwrap().lines().next(), Some("and another"));
+
+    // Notes moved to the amended commit and span both turns.
+    assert_eq!(read_note(repo.path(), "refs/notes/tail").as_deref(), Some("a2"));
+    let note: Vec<serde_json::Value> =
+        serde_json::from_str(&read_note(repo.path(), "refs/notes/transcript").unwrap()).unwrap();
+    assert_eq!(note.len(), 4, "note: {note:?}");
+}
+
+#[test]
+fn separate_added_files_lists_new_and_modified_footers() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    // Commit an existing file to modify later.
+    fs::write(repo.path().join("existing.txt"), "before").unwrap();
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let mut index = git.index().unwrap();
+    index.add_path(std::path::Path::new("existing.txt")).unwrap();
+    index.write().unwrap();
+    let tree = git.find_tree(index.write_tree().unwrap()).unwrap();
+    let sig = git.signature().unwrap();
+    let parent = git.head().unwrap().peel_to_commit().unwrap();
+    git.commit(Some("HEAD"), &sig, &sig, "add existing", &tree, &[&parent]).unwrap();
+
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"hello"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"hi"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"hello","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    fs::write(repo.path().join("existing.txt"), "after").unwrap();
+    fs::write(repo.path().join("new.txt"), "brand new").unwrap();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let env = [("CLAUTRIBUTION_PREFERENCES", r#"{"separate_added_files": true}"#)];
+    let (code, stdout, stderr) = run_cli_with_env(&input, &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("committed changes"), "got: {stdout}");
+
+    let head = git.head().unwrap().peel_to_commit().unwrap();
+    let message = head.message().unwrap();
+    assert!(message.contains("\n\nAdded: new.txt\nModified: existing.txt"), "msg: {message}");
+}
+
+#[test]
+fn subject_diffstat_appends_line_counts() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    fs::write(repo.path().join("existing.txt"), "one\ntwo\nthree\n").unwrap();
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let mut index = git.index().unwrap();
+    index.add_path(std::path::Path::new("existing.txt")).unwrap();
+    index.write().unwrap();
+    let tree = git.find_tree(index.write_tree().unwrap()).unwrap();
+    let sig = git.signature().unwrap();
+    let parent = git.head().unwrap().peel_to_commit().unwrap();
+    git.commit(Some("HEAD"), &sig, &sig, "add existing", &tree, &[&parent]).unwrap();
+
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"hello"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"hi"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"hello","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    // One line changed in place, two new lines in a new file.
+    fs::write(repo.path().join("existing.txt"), "one\n2\nthree\n").unwrap();
+    fs::write(repo.path().join("new.txt"), "a\nb\n").unwrap();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let env = [("CLAUTRIBUTION_PREFERENCES", r#"{"subject_diffstat": true}"#)];
+    let (code, stdout, stderr) = run_cli_with_env(&input, &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("committed changes"), "got: {stdout}");
+
+    let head = git.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.summary(), Some("hello (+3 -1)"));
+}
+
+#[test]
+fn rotated_transcript_parts_are_read_as_one() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let transcript = dir.path().join("s.jsonl");
+    fs::write(&transcript, concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"start the port"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"started"}]}}"#, "\n",
+    )).unwrap();
+    // The session continues in the rotated part, parented across the split.
+    fs::write(dir.path().join("s.1.jsonl"), concat!(
+        r#"{"type":"user","uuid":"u2","parentUuid":"a1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"finish the port"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a2","parentUuid":"u2","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r2","message":{"role":"assistant","content":[{"type":"text","text":"finished"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"finish the port","session_id":"s","uuid":"u2"}"#,
+    ).unwrap();
+    fs::write(repo.path().join("port.rs"), "fn main() {}\n").unwrap();
+
+    let common = common(cwd, transcript.to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let (code, stdout, stderr) = run_cli(&input);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("committed changes"), "got: {stdout}");
+
+    // The tail comes from the rotated part and the walk reaches the first
+    // file's root.
+    assert_eq!(read_note(repo.path(), "refs/notes/tail").as_deref(), Some("a2"));
+    let transcript_note = read_note(repo.path(), "refs/notes/transcript").unwrap();
+    for uuid in ["u1", "a1", "u2", "a2"] {
+        assert!(transcript_note.contains(&format!("\"{uuid}\"")), "{uuid} missing: {transcript_note}");
+    }
+}
+
+#[test]
+fn session_end_trigger_defers_commit_until_session_end() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"write the parser"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"done"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"write the parser","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    fs::write(repo.path().join("parser.rs"), "fn parse() {}\n").unwrap();
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let initial = git.head().unwrap().peel_to_commit().unwrap().id();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let env = [("CLAUTRIBUTION_PREFERENCES", r#"{"commit_trigger": "session_end"}"#)];
+    let stop = format!(r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#);
+    let (code, stdout, stderr) = run_cli_with_env(&stop, &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("commit deferred to session end"), "got: {stdout}");
+    assert_eq!(git.head().unwrap().peel_to_commit().unwrap().id(), initial);
+
+    let end = format!(r#"{{ {common}, "hook_event_name": "SessionEnd", "reason": "other" }}"#);
+    let (code, _stdout, stderr) = run_cli_with_env(&end, &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    let head = git.head().unwrap().peel_to_commit().unwrap();
+    assert_ne!(head.id(), initial);
+    assert_eq!(head.summary(), Some("write the parser"));
+    assert!(head.tree().unwrap().get_name("parser.rs").is_some());
+    assert_eq!(read_note(repo.path(), "refs/notes/tail").as_deref(), Some("a1"));
+}
+
+#[test]
+fn config_flag_loads_preferences_from_given_file() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"hello"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"hi"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"hello","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    fs::write(repo.path().join("output.txt"), "result").unwrap();
+    let config_dir = tempfile::tempdir().unwrap();
+    let config = config_dir.path().join("profile.toml");
+    fs::write(&config, "[commit_template]\ninline = \"profile: {{ prompt }}\"\n").unwrap();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let (code, stdout, stderr) =
+        run_cli_with_args(&["--config", config.to_str().unwrap()], &input, &[]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("committed changes"), "got: {stdout}");
+
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let head = git.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.message().unwrap().lines().next(), Some("profile: hello"));
+    // The repo's own preferences file isn't created or consulted.
+    assert!(!data_dir.join("clautribution.toml").exists());
+
+    // A missing config file is an error, not a silent fallback.
+    let missing = config_dir.path().join("missing.toml");
+    let (code, _, stderr) = run_cli_with_args(&["--config", missing.to_str().unwrap()], &input, &[]);
+    assert_eq!(code, 2);
+    assert!(stderr.contains("missing.toml not found"), "stderr: {stderr}");
+}
+
+/// Commit `content` as `conflict.txt` on top of `parent` without moving any
+/// ref, returning the new commit.
+fn commit_conflict_txt<'r>(
+    git: &'r git2::Repository,
+    parent: &git2::Commit<'_>,
+    content: &str,
+) -> git2::Commit<'r> {
+    let blob = git.blob(content.as_bytes()).unwrap();
+    let mut builder = git.treebuilder(Some(&parent.tree().unwrap())).unwrap();
+    builder.insert("conflict.txt", blob, 0o100644).unwrap();
+    let tree = git.find_tree(builder.write().unwrap()).unwrap();
+    let sig = git.signature().unwrap();
+    let oid = git.commit(None, &sig, &sig, content, &tree, &[parent]).unwrap();
+    git.find_commit(oid).unwrap()
+}
+
+#[test]
+fn unresolved_merge_conflicts_refuse_the_commit() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let base = git.head().unwrap().peel_to_commit().unwrap();
+    let ours = commit_conflict_txt(&git, &base, "ours\n");
+    let theirs = commit_conflict_txt(&git, &base, "theirs\n");
+    git.reset(ours.as_object(), git2::ResetType::Hard, None).unwrap();
+    let annotated = git.find_annotated_commit(theirs.id()).unwrap();
+    git.merge(&[&annotated], None, None).unwrap();
+    assert!(git.index().unwrap().has_conflicts());
+
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"hello"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"hi"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"hello","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let (code, _, stderr) = run_cli(&input);
+    assert_ne!(code, 0);
+    assert!(
+        stderr.contains("refusing to commit: unresolved merge conflicts in conflict.txt"),
+        "stderr: {stderr}"
+    );
+    assert_eq!(git.head().unwrap().peel_to_commit().unwrap().id(), ours.id());
+    assert!(git.index().unwrap().has_conflicts());
+}
+
+#[test]
+fn commit_deletions_false_leaves_deletions_pending() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let base = git.head().unwrap().peel_to_commit().unwrap();
+    let with_file = commit_conflict_txt(&git, &base, "keep me\n");
+    git.reset(with_file.as_object(), git2::ResetType::Hard, None).unwrap();
+
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"hello"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"hi"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"hello","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    fs::remove_file(repo.path().join("conflict.txt")).unwrap();
+    fs::write(repo.path().join("new.txt"), "new\n").unwrap();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let env = [("CLAUTRIBUTION_PREFERENCES", r#"{"commit_deletions": false}"#)];
+    let (code, stdout, stderr) = run_cli_with_env(&input, &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("committed changes"), "got: {stdout}");
+
+    let tree = git.head().unwrap().peel_to_commit().unwrap().tree().unwrap();
+    assert!(tree.get_name("new.txt").is_some());
+    assert!(tree.get_name("conflict.txt").is_some(), "deletion was committed");
+}
+
+#[test]
+fn deferred_stop_is_committed_at_session_end() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let prefs = [("CLAUTRIBUTION_PREFERENCES", r#"{"min_commit_interval_secs":3600}"#)];
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"first"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"ok"}]}}"#, "\n",
+        r#"{"type":"user","uuid":"u2","parentUuid":"a1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"second"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a2","parentUuid":"u2","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r2","message":{"role":"assistant","content":[{"type":"text","text":"ok"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let stop = format!(r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#);
+    let git = git2::Repository::open(repo.path()).unwrap();
+
+    // The first turn commits; the second, moments later, is deferred.
+    fs::write(data_dir.join("prompt-test-session.json"), r#"{"prompt":"first","session_id":"s","uuid":"u1"}"#).unwrap();
+    fs::write(repo.path().join("one.txt"), "1").unwrap();
+    let (code, _, stderr) = run_cli_with_env(&stop, &prefs);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    fs::write(data_dir.join("prompt-test-session.json"), r#"{"prompt":"second","session_id":"s","uuid":"u2"}"#).unwrap();
+    fs::write(repo.path().join("two.txt"), "2").unwrap();
+    let (code, stdout, stderr) = run_cli_with_env(&stop, &prefs);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("commit deferred"), "got: {stdout}");
+
+    // SessionEnd commits the deferred turn instead of dropping it.
+    let end = format!(r#"{{ {common}, "hook_event_name": "SessionEnd", "reason": "other" }}"#);
+    let (code, _, stderr) = run_cli_with_env(&end, &prefs);
+    assert_eq!(code, 0, "stderr: {stderr}");
+
+    // A new session's first prompt finds a clean tree: nothing is blocked
+    // or rewound.
+    let next = common.replace("test-session", "next-session");
+    let submit = format!(r#"{{ {next}, "hook_event_name": "UserPromptSubmit", "prompt": "third" }}"#);
+    let (code, stdout, stderr) = run_cli_with_env(&submit, &prefs);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(!stdout.contains("uncommitted changes"), "prompt blocked: {stdout}");
+
+    let head = git.head().unwrap().peel_to_commit().unwrap();
+    let tree = head.tree().unwrap();
+    assert!(tree.get_name("one.txt").is_some());
+    assert!(tree.get_name("two.txt").is_some());
+    assert_eq!(head.summary(), Some("second"));
+    assert_eq!(head.parent(0).unwrap().summary(), Some("first"));
+}
+
+#[test]
+fn session_end_trigger_still_blocks_on_manual_edits_before_first_prompt() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let env = [("CLAUTRIBUTION_PREFERENCES", r#"{"commit_trigger": "session_end"}"#)];
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"write the parser"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"done"}]}}"#, "\n",
+    )).unwrap();
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let submit = format!(r#"{{ {common}, "hook_event_name": "UserPromptSubmit", "prompt": "write the parser" }}"#);
+
+    // Edits made before the session's first prompt are the user's.
+    fs::write(repo.path().join("manual.txt"), "mine").unwrap();
+    let (code, stdout, stderr) = run_cli_with_env(&submit, &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("commit your manual changes"), "got: {stdout}");
+
+    // Once they're committed, Claude's deferred changes don't block.
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let mut index = git.index().unwrap();
+    index.add_path(std::path::Path::new("manual.txt")).unwrap();
+    index.write().unwrap();
+    let tree = git.find_tree(index.write_tree().unwrap()).unwrap();
+    let sig = git.signature().unwrap();
+    let parent = git.head().unwrap().peel_to_commit().unwrap();
+    git.commit(Some("HEAD"), &sig, &sig, "manual", &tree, &[&parent]).unwrap();
+    let (code, stdout, stderr) = run_cli_with_env(&submit, &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(!stdout.contains("uncommitted changes"), "got: {stdout}");
+
+    fs::write(repo.path().join("parser.rs"), "fn parse() {}\n").unwrap();
+    let stop = format!(r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#);
+    let (code, stdout, stderr) = run_cli_with_env(&stop, &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("commit deferred to session end"), "got: {stdout}");
+    let (code, stdout, stderr) = run_cli_with_env(&submit, &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(!stdout.contains("uncommitted changes"), "got: {stdout}");
+}
+
+#[test]
+fn legacy_plan_context_with_string_qa_still_loads() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"implement the plan"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"done"}]}}"#, "\n",
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(
+        data_dir.join("prompt-test-session.json"),
+        r#"{"prompt":"implement the plan","session_id":"s","uuid":"u1"}"#,
+    ).unwrap();
+    // Written by a release that stored each Q&A as one preformatted string.
+    fs::write(
+        data_dir.join("plan-context.json"),
+        r#"{"original_prompt":"add a cache","qa":["\"Which store?\"=\"Redis\""]}"#,
+    ).unwrap();
+    fs::write(repo.path().join("cache.rs"), "struct Cache;\n").unwrap();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(
+        r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#
+    );
+    let env = [("CLAUTRIBUTION_PREFERENCES", r#"{"body_sections": ["qa"]}"#)];
+    let (code, stdout, stderr) = run_cli_with_env(&input, &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("committed changes"), "got: {stdout}");
+
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let head = git.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.summary(), Some("add a cache"));
+    assert!(head.message().unwrap().contains("\"Which store?\"=\"Redis\"\n"), "got: {:?}", head.message());
+}
@@ -0,0 +1,492 @@
+mod common;
+
+use std::fs;
+
+use common::{common, install_transcript, run_cli, run_subcommand, run_subcommand_with_env, temp_git_repo};
+
+#[test]
+fn diff_shows_only_committable_changes() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    fs::write(repo.path().join("lib.rs"), "fn main() {}\n").unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    fs::write(data_dir.join("prompt-s.json"), r#"{"prompt":"secret"}"#).unwrap();
+
+    let (code, stdout, stderr) = run_subcommand(&["diff", cwd]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("+++ b/lib.rs"), "diff: {stdout}");
+    assert!(stdout.contains("+fn main() {}"), "diff: {stdout}");
+    assert!(!stdout.contains(".clautribution"), "metadata in diff: {stdout}");
+    assert!(!stdout.contains("secret"), "metadata in diff: {stdout}");
+}
+
+#[test]
+fn export_html_renders_conversation() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let home = install_transcript(repo.path(), "s1", concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","message":{"role":"user","content":"make <b> bold"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"on it"},{"type":"tool_use","id":"t1","name":"Edit","input":{"file_path":"/repo/page.html"}}]}}"#, "\n",
+    ));
+    let home = home.path().to_str().unwrap();
+
+    let (code, stdout, stderr) = run_subcommand_with_env(&["export-html", cwd], &[("HOME", home)]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.starts_with("<!DOCTYPE html>"), "html: {stdout}");
+    assert!(stdout.contains("<style>"), "html: {stdout}");
+    assert!(stdout.contains("make &lt;b&gt; bold"), "prompt escaped: {stdout}");
+    assert!(stdout.contains(r#"<div class="msg assistant">"#), "html: {stdout}");
+    assert!(stdout.contains("<details class=\"tool\"><summary>Edit page.html</summary>"), "html: {stdout}");
+    assert!(stdout.trim_end().ends_with("</html>"), "html: {stdout}");
+
+    let out = repo.path().join("session.html");
+    let (code, stdout, stderr) = run_subcommand_with_env(
+        &["export-html", cwd, "--out", out.to_str().unwrap()],
+        &[("HOME", home)],
+    );
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.is_empty(), "stdout: {stdout}");
+    assert!(fs::read_to_string(&out).unwrap().contains("make &lt;b&gt; bold"));
+}
+
+#[test]
+fn export_pairs_prints_prompt_reply_lines() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let home = install_transcript(repo.path(), "s1", concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","message":{"role":"user","content":"what is 2+2?"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"4"}]}}"#, "\n",
+        r#"{"type":"user","uuid":"u2","parentUuid":"a1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","message":{"role":"user","content":"and 3+3?"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a2","parentUuid":"u2","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","requestId":"r2","message":{"role":"assistant","content":[{"type":"text","text":"6"}]}}"#, "\n",
+    ));
+    let home = home.path().to_str().unwrap();
+
+    let (code, stdout, stderr) = run_subcommand_with_env(&["export-pairs", cwd], &[("HOME", home)]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    let pairs: Vec<serde_json::Value> =
+        stdout.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(
+        pairs,
+        vec![
+            serde_json::json!({ "prompt": "what is 2+2?", "reply": "4" }),
+            serde_json::json!({ "prompt": "and 3+3?", "reply": "6" }),
+        ]
+    );
+}
+
+#[test]
+fn timeline_lists_tool_calls_in_order() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let home = install_transcript(repo.path(), "s1", concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","message":{"role":"user","content":"fix it"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"tool_use","id":"t1","name":"Read","input":{"file_path":"/repo/lib.rs"}}]}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a2","parentUuid":"a1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","requestId":"r2","message":{"role":"assistant","content":[{"type":"tool_use","id":"t2","name":"Edit","input":{"file_path":"/repo/lib.rs"}}]}}"#, "\n",
+    ));
+    let home = home.path().to_str().unwrap();
+
+    let (code, stdout, stderr) = run_subcommand_with_env(&["timeline", cwd], &[("HOME", home)]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert_eq!(stdout, "a1  Read  lib.rs\na2  Edit  lib.rs\n");
+}
+
+#[test]
+fn preview_turn_replays_a_past_turn() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let home = install_transcript(repo.path(), "s1", concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","message":{"role":"user","content":"first task"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"tool_use","id":"t1","name":"Edit","input":{"file_path":"/repo/one.rs"}}]}}"#, "\n",
+        r#"{"type":"user","uuid":"u2","parentUuid":"a1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","message":{"role":"user","content":"second task"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a2","parentUuid":"u2","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","requestId":"r2","message":{"role":"assistant","content":[{"type":"tool_use","id":"t2","name":"Edit","input":{"file_path":"/repo/two.rs"}}]}}"#, "\n",
+    ));
+    let env = [("HOME", home.path().to_str().unwrap())];
+
+    let (code, stdout, stderr) = run_subcommand_with_env(&["preview", cwd], &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.starts_with("second task"), "preview: {stdout}");
+
+    let (code, stdout, stderr) = run_subcommand_with_env(&["preview", cwd, "--turn", "a1"], &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.starts_with("first task"), "preview: {stdout}");
+    assert!(stdout.contains("one.rs"), "preview: {stdout}");
+    assert!(!stdout.contains("two.rs"), "later turn leaked in: {stdout}");
+
+    let (code, _stdout, stderr) = run_subcommand_with_env(&["preview", cwd, "--turn", "nope"], &env);
+    assert_eq!(code, 2);
+    assert!(stderr.contains("no transcript entry with uuid nope"), "stderr: {stderr}");
+}
+
+#[test]
+fn preview_commit_commits_pending_changes() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let home = install_transcript(repo.path(), "s1", concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","message":{"role":"user","content":"add the config"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"done"}]}}"#, "\n",
+    ));
+    let env = [("HOME", home.path().to_str().unwrap())];
+
+    // Nothing changed yet: the real decision is nonproductive.
+    let (code, stdout, stderr) = run_subcommand_with_env(&["preview", cwd, "--commit"], &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("nonproductive"), "stdout: {stdout}");
+
+    fs::write(repo.path().join("config.toml"), "x = 1\n").unwrap();
+    let (code, stdout, stderr) = run_subcommand_with_env(&["preview", cwd, "--commit"], &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("committed changes"), "stdout: {stdout}");
+
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let head = git.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.summary(), Some("add the config"));
+    assert!(head.tree().unwrap().get_name("config.toml").is_some());
+}
+
+#[test]
+fn preview_explains_empty_and_unparseable_transcripts() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let home = install_transcript(repo.path(), "s1", "");
+    let env = [("HOME", home.path().to_str().unwrap())];
+
+    let (code, stdout, stderr) = run_subcommand_with_env(&["preview", cwd], &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("is empty — nothing to preview"), "stdout: {stdout}");
+
+    let home = install_transcript(repo.path(), "s1", "{\"type\": \"user\", \"uuid\":\n{not json}\n");
+    let env = [("HOME", home.path().to_str().unwrap())];
+    let (code, stdout, stderr) = run_subcommand_with_env(&["preview", cwd], &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("no readable entries (2 line(s) failed to parse)"), "stdout: {stdout}");
+    assert!(stdout.contains("validate-transcript"), "stdout: {stdout}");
+}
+
+#[test]
+fn preview_explains_when_everything_is_committed() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let home = install_transcript(repo.path(), "s1", concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","message":{"role":"user","content":"add the config"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"done"}]}}"#, "\n",
+    ));
+    let env = [("HOME", home.path().to_str().unwrap())];
+    fs::write(repo.path().join("config.toml"), "x = 1\n").unwrap();
+    let (code, stdout, stderr) = run_subcommand_with_env(&["preview", cwd, "--commit"], &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("committed changes"), "stdout: {stdout}");
+
+    let (code, stdout, stderr) = run_subcommand_with_env(&["preview", cwd], &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("Nothing new since the last commit"), "stdout: {stdout}");
+    assert!(stdout.contains("preview --turn"), "stdout: {stdout}");
+}
+
+#[test]
+fn validate_transcript_reports_parse_errors_and_dag_issues() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("t.jsonl");
+    fs::write(&path, concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"hi"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","#, "\n",
+        r#"{"type":"assistant","uuid":"a2","parentUuid":"gone","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"assistant","content":[]}}"#, "\n",
+    )).unwrap();
+
+    let (code, stdout, stderr) = run_subcommand(&["validate-transcript", path.to_str().unwrap()]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("2 entries"), "stdout: {stdout}");
+    assert!(stdout.contains("  assistant: 1\n  user: 1"), "stdout: {stdout}");
+    assert!(stdout.contains("1 parse errors:\n  line 2: "), "stdout: {stdout}");
+    assert!(stdout.contains("entry a2 references missing parent gone"), "stdout: {stdout}");
+    assert!(!stdout.contains("No problems found."), "stdout: {stdout}");
+}
+
+#[test]
+fn validate_transcript_strict_fails_on_bad_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("t.jsonl");
+    fs::write(&path, concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"hi"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","#, "\n",
+    )).unwrap();
+
+    let (code, stdout, stderr) = run_subcommand(&["validate-transcript", path.to_str().unwrap(), "--strict"]);
+    assert_eq!(code, 2);
+    assert!(stdout.starts_with("line 2: "), "stdout: {stdout}");
+    assert!(stderr.contains("t.jsonl: 1 parse errors"), "stderr: {stderr}");
+}
+
+#[test]
+fn show_prompt_prints_full_long_prompt() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let prompt = format!("rewrite the parser {}and stop", "with care ".repeat(500));
+    assert!(prompt.len() > 4096);
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    let user = serde_json::json!({
+        "type": "user", "uuid": "u1", "isSidechain": false, "userType": "external",
+        "cwd": "/tmp", "sessionId": "s", "timestamp": "t", "version": "v",
+        "message": { "role": "user", "content": prompt },
+    });
+    fs::write(transcript.path(), format!(
+        "{user}\n{}\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"done"}]}}"#,
+    )).unwrap();
+    let data_dir = repo.path().join(".clautribution");
+    fs::create_dir_all(&data_dir).unwrap();
+    let meta = serde_json::json!({ "prompt": prompt, "session_id": "s", "uuid": "u1" });
+    fs::write(data_dir.join("prompt-test-session.json"), meta.to_string()).unwrap();
+    fs::write(repo.path().join("parser.rs"), "fn parse() {}\n").unwrap();
+
+    let common = common(cwd, transcript.path().to_str().unwrap());
+    let input = format!(r#"{{ {common}, "hook_event_name": "Stop", "stop_hook_active": false }}"#);
+    let (code, _stdout, stderr) = run_cli(&input);
+    assert_eq!(code, 0, "stderr: {stderr}");
+
+    let (code, stdout, stderr) = run_subcommand(&["show-prompt", cwd]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert_eq!(stdout.trim_end(), prompt);
+
+    let (code, _stdout, stderr) = run_subcommand(&["show-prompt", cwd, "HEAD~1"]);
+    assert_eq!(code, 2);
+    assert!(stderr.contains("no prompt note on HEAD~1"), "stderr: {stderr}");
+}
+
+#[test]
+fn decide_prints_decision_as_json() {
+    let transcript = tempfile::NamedTempFile::new().unwrap();
+    fs::write(transcript.path(), concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","message":{"role":"user","content":"add a readme"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"done"}]}}"#, "\n",
+    )).unwrap();
+    let path = transcript.path().to_str().unwrap();
+
+    let (code, stdout, stderr) = run_subcommand(&["decide", path, "--changes"]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    let decision: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(decision["type"], "Productive");
+    assert!(decision["commit_message"].as_str().unwrap().starts_with("add a readme"), "got: {stdout}");
+    assert_eq!(decision["notes"]["refs/notes/session"], "s");
+
+    // Without changes it's nonproductive; with the prompt already
+    // committed there's nothing to do.
+    let (_, stdout, _) = run_subcommand(&["decide", path]);
+    let decision: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(decision["type"], "Nonproductive");
+    assert!(decision["hint_message"].is_string());
+
+    let (_, stdout, _) = run_subcommand(&["decide", path, "--committed-tail", "a1", "--changes"]);
+    let decision: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(decision["type"], "AlreadyCommitted", "got: {stdout}");
+
+    let (code, _, stderr) = run_subcommand(&["decide", path, "--committed-tail"]);
+    assert_eq!(code, 1);
+    assert!(stderr.contains("usage: clautribution decide"), "stderr: {stderr}");
+}
+
+#[test]
+fn install_hooks_merges_into_existing_settings() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let settings_path = repo.path().join(".claude/settings.json");
+    fs::create_dir_all(settings_path.parent().unwrap()).unwrap();
+    fs::write(&settings_path, r#"{
+        "model": "opus",
+        "hooks": { "Stop": [{ "hooks": [{ "type": "command", "command": "notify-send done" }] }] }
+    }"#).unwrap();
+
+    let (code, stdout, stderr) = run_subcommand(&["install-hooks", cwd]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("Stop: installed"), "stdout: {stdout}");
+
+    let settings: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&settings_path).unwrap()).unwrap();
+    assert_eq!(settings["model"], "opus");
+    let bin = env!("CARGO_BIN_EXE_clautribution");
+    for event in ["SessionStart", "UserPromptSubmit", "Stop", "SessionEnd"] {
+        let groups = settings["hooks"][event].as_array().unwrap();
+        assert!(
+            groups.iter().any(|g| g["hooks"][0]["command"] == bin),
+            "{event} missing hook: {settings}"
+        );
+    }
+    // The unrelated Stop hook survives alongside ours.
+    assert_eq!(settings["hooks"]["Stop"].as_array().unwrap().len(), 2);
+
+    // Running again doesn't duplicate entries.
+    let (code, stdout, _) = run_subcommand(&["install-hooks", cwd]);
+    assert_eq!(code, 0);
+    assert!(stdout.contains("Stop: already installed"), "stdout: {stdout}");
+    let settings: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&settings_path).unwrap()).unwrap();
+    assert_eq!(settings["hooks"]["Stop"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn config_set_then_get_round_trips() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+
+    let (code, stdout, stderr) = run_subcommand(&["config", "get", cwd, "summary_verbosity"]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert_eq!(stdout.trim(), "medium");
+
+    let (code, _, stderr) = run_subcommand(&["config", "set", cwd, "summary_verbosity", "full"]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    let (code, stdout, stderr) = run_subcommand(&["config", "get", cwd, "summary_verbosity"]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert_eq!(stdout.trim(), "full");
+    let file = fs::read_to_string(repo.path().join(".clautribution/clautribution.toml")).unwrap();
+    assert!(file.contains(r#"summary_verbosity = "full""#), "file: {file}");
+
+    // Non-string values are parsed as TOML literals.
+    let (code, _, stderr) = run_subcommand(&["config", "set", cwd, "subject_max_len", "72"]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    let (_, stdout, _) = run_subcommand(&["config", "get", cwd, "subject_max_len"]);
+    assert_eq!(stdout.trim(), "72");
+
+    let (code, _, stderr) = run_subcommand(&["config", "set", cwd, "subject_max_len", "long"]);
+    assert_eq!(code, 2);
+    assert!(stderr.contains("invalid value for subject_max_len"), "stderr: {stderr}");
+
+    let (code, _, stderr) = run_subcommand(&["config", "get", cwd, "verbosity_level"]);
+    assert_eq!(code, 2);
+    assert!(stderr.contains("unknown preference verbosity_level"), "stderr: {stderr}");
+}
+
+#[test]
+fn verify_checks_provenance_attestation() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let home = install_transcript(repo.path(), "s1", concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","message":{"role":"user","content":"add the config"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"done"}]}}"#, "\n",
+    ));
+    let env = [
+        ("HOME", home.path().to_str().unwrap()),
+        ("CLAUTRIBUTION_PREFERENCES", r#"{"provenance_hmac_key": "s3cret"}"#),
+    ];
+    fs::write(repo.path().join("config.toml"), "x = 1\n").unwrap();
+    let (code, stdout, stderr) = run_subcommand_with_env(&["preview", cwd, "--commit"], &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("committed changes"), "stdout: {stdout}");
+
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let head = git.head().unwrap().peel_to_commit().unwrap();
+    let message = head.message().unwrap().to_string();
+    assert!(message.starts_with("add the config\n"), "msg: {message}");
+    assert!(message.lines().last().unwrap().starts_with("Claude-Attestation: "), "msg: {message}");
+
+    let (code, stdout, stderr) = run_subcommand_with_env(&["verify", cwd, "HEAD"], &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("attestation valid"), "stdout: {stdout}");
+
+    // The wrong key doesn't verify.
+    let wrong = [env[0], ("CLAUTRIBUTION_PREFERENCES", r#"{"provenance_hmac_key": "guess"}"#)];
+    let (code, _, stderr) = run_subcommand_with_env(&["verify", cwd, "HEAD"], &wrong);
+    assert_eq!(code, 2);
+    assert!(stderr.contains("does not match"), "stderr: {stderr}");
+
+    // Reword the subject, carrying the trailer and tail note over.
+    let tail = git.find_note(Some("refs/notes/tail"), head.id()).unwrap().message().unwrap().to_string();
+    let tampered = message.replacen("add the config", "add the secure config", 1);
+    let oid = head.amend(Some("HEAD"), None, None, None, Some(&tampered), None).unwrap();
+    let sig = git.signature().unwrap();
+    git.note(&sig, &sig, Some("refs/notes/tail"), oid, &tail, true).unwrap();
+    let (code, _, stderr) = run_subcommand_with_env(&["verify", cwd, "HEAD"], &env);
+    assert_eq!(code, 2);
+    assert!(stderr.contains("attestation of HEAD does not match"), "stderr: {stderr}");
+}
+
+#[test]
+fn log_limit_stops_after_n_annotated_commits() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let git = git2::Repository::open(repo.path()).unwrap();
+    let sig = git.signature().unwrap();
+    for n in 1..=3 {
+        let parent = git.head().unwrap().peel_to_commit().unwrap();
+        let tree = parent.tree().unwrap();
+        let oid = git.commit(Some("HEAD"), &sig, &sig, &format!("commit {n}"), &tree, &[&parent]).unwrap();
+        git.note(&sig, &sig, Some("refs/notes/prompt"), oid, &format!("prompt {n}\nmore"), false).unwrap();
+    }
+
+    let (code, stdout, stderr) = run_subcommand(&["log", cwd, "--limit", "2"]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "stdout: {stdout}");
+    assert!(lines[0].ends_with("  prompt 3"), "stdout: {stdout}");
+    assert!(lines[1].ends_with("  prompt 2"), "stdout: {stdout}");
+
+    // --all also lists the unannotated initial commit.
+    let (code, stdout, stderr) = run_subcommand(&["log", cwd, "--all"]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert_eq!(stdout.lines().count(), 4, "stdout: {stdout}");
+    assert!(stdout.lines().last().unwrap().ends_with("  (not annotated)"), "stdout: {stdout}");
+
+    let (code, _, _) = run_subcommand(&["log", cwd, "--limit", "two"]);
+    assert_eq!(code, 1);
+}
+
+#[test]
+fn dump_context_prints_resolved_stop_inputs() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let home = install_transcript(repo.path(), "s1", concat!(
+        r#"{"type":"user","uuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","message":{"role":"user","content":"add the config"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","isSidechain":false,"userType":"external","cwd":"/tmp","sessionId":"s1","timestamp":"t","version":"v","requestId":"r1","message":{"role":"assistant","content":[{"type":"text","text":"done"}]}}"#, "\n",
+    ));
+    let env = [
+        ("HOME", home.path().to_str().unwrap()),
+        ("CLAUTRIBUTION_PREFERENCES", r#"{"summary_verbosity": "full"}"#),
+    ];
+    fs::write(repo.path().join("config.toml"), "x = 1\n").unwrap();
+
+    let (code, stdout, stderr) = run_subcommand_with_env(&["dump-context", cwd], &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("committed_tail: (none)"), "stdout: {stdout}");
+    assert!(stdout.contains("has_uncommitted_changes: true"), "stdout: {stdout}");
+    assert!(stdout.contains("verbosity: full"), "stdout: {stdout}");
+
+    let (code, _, stderr) = run_subcommand_with_env(&["preview", cwd, "--commit"], &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+
+    let (code, stdout, stderr) = run_subcommand_with_env(&["dump-context", cwd], &env);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stdout.contains("committed_tail: a1"), "stdout: {stdout}");
+    assert!(stdout.contains("has_uncommitted_changes: false"), "stdout: {stdout}");
+}
+
+#[test]
+fn max_walk_depth_preference_caps_ancestor_walks() {
+    let repo = temp_git_repo();
+    let cwd = repo.path().to_str().unwrap();
+    let mut contents = String::new();
+    for i in 0..20 {
+        let (kind, content) = if i % 2 == 0 {
+            ("user", serde_json::json!(format!("step {i}")))
+        } else {
+            ("assistant", serde_json::json!([{ "type": "text", "text": "ok" }]))
+        };
+        let parent = (i > 0).then(|| format!("e{}", i - 1));
+        let entry = serde_json::json!({
+            "type": kind, "uuid": format!("e{i}"), "parentUuid": parent,
+            "isSidechain": false, "userType": "external",
+            "cwd": "/tmp", "sessionId": "s1", "timestamp": "t", "version": "v",
+            "message": { "role": kind, "content": content },
+        });
+        contents.push_str(&format!("{entry}\n"));
+    }
+    let home = install_transcript(repo.path(), "s1", &contents);
+    let home = home.path().to_str().unwrap();
+
+    let (code, _, stderr) = run_subcommand_with_env(&["preview", cwd], &[("HOME", home)]);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(!stderr.contains("depth cap"), "stderr: {stderr}");
+
+    let capped = [("HOME", home), ("CLAUTRIBUTION_PREFERENCES", r#"{"max_walk_depth": 5}"#)];
+    let (code, _, stderr) = run_subcommand_with_env(&["preview", cwd], &capped);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert!(stderr.contains("after 5 entries (depth cap)"), "stderr: {stderr}");
+}